@@ -1,6 +1,8 @@
 use std::fmt;
 
-#[derive(Debug)]
+use chrono::{DateTime, FixedOffset};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Author {
     pub name: String,
     pub email: String,
@@ -11,10 +13,54 @@ impl Author {
     pub fn new(name: String, email: String, time: String) -> Self {
         Author { name, email, time }
     }
+
+    /*
+        Parses an identity line of the form "Name <email> 1700000000 +0000",
+        as found after the author and committer headers of a commit.
+    */
+    pub fn parse(line: &str) -> Option<Self> {
+        let email_start = line.find('<')?;
+        let email_end = email_start + line[email_start..].find('>')?;
+        let name = line[..email_start].trim_end().to_string();
+        let email = line[email_start + 1..email_end].to_string();
+        let time = line[email_end + 1..].trim_start().to_string();
+        Some(Author { name, email, time })
+    }
+
+    /*
+        Seconds since the epoch, from the "<seconds> <offset>" time field.
+    */
+    pub fn timestamp(&self) -> i64 {
+        self.time
+            .split_whitespace()
+            .next()
+            .and_then(|seconds| seconds.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /*
+        The time in the author's own timezone, as git's default date format
+        shows it: "Sun Oct 18 11:50:10 2026 +0000".
+    */
+    pub fn readable_time(&self) -> String {
+        self.datetime()
+            .map(|time| time.format("%a %b %-d %H:%M:%S %Y %z").to_string())
+            .unwrap_or_else(|| self.time.clone())
+    }
+
+    pub fn datetime(&self) -> Option<DateTime<FixedOffset>> {
+        let offset = self.time.split_whitespace().nth(1)?;
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let digits = offset.trim_start_matches(['+', '-']);
+        let hours: i32 = digits.get(0..2)?.parse().ok()?;
+        let minutes: i32 = digits.get(2..4)?.parse().ok()?;
+        let offset = FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))?;
+        Some(DateTime::from_timestamp(self.timestamp(), 0)?.with_timezone(&offset))
+    }
 }
 
 impl fmt::Display for Author {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {} {}", self.name, self.email, self.time)
+        write!(f, "{} <{}> {}", self.name, self.email, self.time)
     }
 }
@@ -1,41 +1,66 @@
+use crate::object_id::ObjectId;
 use crate::traits::Object;
 use std::fmt;
 
-#[derive(fmt::Debug)]
+#[derive(fmt::Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Kind {
     Blob,
     Tree,
+    Commit,
+    Tag,
+}
+
+impl Kind {
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "blob" => Some(Kind::Blob),
+            "tree" => Some(Kind::Tree),
+            "commit" => Some(Kind::Commit),
+            "tag" => Some(Kind::Tag),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
 }
 
 #[derive(Debug)]
 pub struct Blob {
-    pub data: String,
+    pub data: Vec<u8>,
     pub kind: Kind,
-    pub object_id: String,
+    pub object_id: Option<ObjectId>,
 }
 
 impl Blob {
-    pub fn new(data: &str) -> Self {
+    pub fn new(data: Vec<u8>) -> Self {
         Blob {
-            data: data.to_string(),
+            data,
             kind: Kind::Blob,
-            object_id: String::from(""),
+            object_id: None,
         }
     }
+
+    pub fn parse(data: &[u8]) -> Self {
+        Blob::new(data.to_vec())
+    }
 }
 
 impl Object for Blob {
-    fn to_string(&self) -> String {
-        let kind = format!("{:?}", self.kind).to_lowercase();
-        let bytesize = self.data.bytes().len();
-        format!("{} {}\0{}", kind, bytesize, self.data)
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut content = format!("{} {}\0", self.kind, self.data.len()).into_bytes();
+        content.extend_from_slice(&self.data);
+        content
     }
 
-    fn get_object_id(&self) -> String {
-        self.object_id.clone()
+    fn get_object_id(&self) -> Option<ObjectId> {
+        self.object_id
     }
 
-    fn set_object_id(&mut self, object_id: String) {
-        self.object_id = object_id;
+    fn set_object_id(&mut self, object_id: ObjectId) {
+        self.object_id = Some(object_id);
     }
 }
@@ -3,7 +3,7 @@ use std::io;
 use std::io::Write;
 use std::path::Path;
 
-mod utils;
+use shit::utils;
 
 fn main() -> io::Result<()> {
     let db_path = utils::get_db_path();
@@ -13,10 +13,10 @@ fn main() -> io::Result<()> {
     let object_filename = path_buf.file_name().unwrap();
     let object_dir = path_buf.parent().unwrap().file_name().unwrap();
     let object_path = db_path.join(object_dir).join(object_filename);
-    let object_contents = utils::inflate(object_path);
+    let object_contents = utils::inflate(object_path)?;
     let stdout = io::stdout();
     let mut handle = stdout.lock();
-    handle.write_all(object_contents.as_bytes())?;
+    handle.write_all(&object_contents)?;
 
     Ok(())
 }
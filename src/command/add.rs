@@ -0,0 +1,149 @@
+use std::{
+    collections::BTreeSet,
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::blob::Blob;
+use crate::command::fatal;
+use crate::object_id::ObjectId;
+use crate::repository::Repository;
+use crate::utils;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    if args.is_empty() {
+        eprintln!("Nothing specified, nothing added.");
+        eprintln!("hint: Maybe you wanted to say 'git add .'?");
+        return Ok(());
+    }
+
+    let root_path = utils::get_root_path();
+    let mut repo = Repository::new(root_path.clone());
+    // Paths outside the workspace are rejected before the index is locked.
+    let pathspecs: Vec<(&String, PathBuf, String)> = args
+        .iter()
+        .map(|arg| {
+            let path = utils::normalize_path(&root_path.join(arg));
+            let key = relative_key(&repo, arg, &path);
+            (arg, path, key)
+        })
+        .collect();
+    if let Err(err) = repo.index.load_for_update() {
+        fatal(err);
+    }
+
+    // Expand every pathspec before touching the index, so a bad path
+    // leaves the index unchanged.
+    let mut files = BTreeSet::new();
+    let mut removed = Vec::new();
+    let mut ignored = Vec::new();
+    for (arg, path, key) in pathspecs {
+        if path.is_file()
+            && !repo.index.is_tracked_file(&key)
+            && repo.workspace.is_ignored(Path::new(&key))
+        {
+            ignored.push(arg);
+            continue;
+        }
+        match repo.workspace.list_files(&path) {
+            Ok(listed) => {
+                // Tracked files are updated even when they match an ignore rule.
+                for tracked in repo.index.paths_under(&key) {
+                    match repo.workspace.stat_file(Path::new(&tracked)) {
+                        Ok(stat) if stat.is_file() => {
+                            files.insert(PathBuf::from(tracked));
+                        }
+                        Ok(_) => {}
+                        Err(_) => removed.push(tracked),
+                    }
+                }
+                files.extend(listed);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if !key.is_empty() && repo.index.is_tracked(&key) {
+                    removed.append(&mut repo.index.paths_under(&key));
+                } else {
+                    repo.index.release_lock()?;
+                    fatal(format!("pathspec '{}' did not match any files", arg));
+                }
+            }
+            Err(err) => {
+                repo.index.release_lock()?;
+                fatal(err);
+            }
+        }
+    }
+
+    for file in files {
+        let data = match repo.workspace.read_data(&file) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                repo.index.release_lock()?;
+                eprintln!("error: open(\"{}\"): Permission denied", file.display());
+                eprintln!("error: unable to index file '{}'", file.display());
+                fatal("adding files failed");
+            }
+            Err(err) => {
+                repo.index.release_lock()?;
+                return Err(err);
+            }
+        };
+        let (stat, object_id) = match store_file(&repo, &file, data) {
+            Ok(stored) => stored,
+            Err(err) => {
+                repo.index.release_lock()?;
+                return Err(err);
+            }
+        };
+        repo.index.add(&file, object_id, &stat);
+    }
+
+    for path in removed {
+        repo.index.remove(Path::new(&path));
+    }
+
+    if let Err(err) = repo.index.write_updates() {
+        fatal(err);
+    }
+
+    if !ignored.is_empty() {
+        eprintln!("The following paths are ignored by one of your .gitignore files:");
+        for arg in ignored {
+            eprintln!("{}", arg);
+        }
+        eprintln!("hint: Use -f if you really want to add them.");
+        eprintln!("hint: Turn this message off by running");
+        eprintln!("hint: \"git config advice.addIgnoredFile false\"");
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/*
+    Stores the contents of a workspace file as a blob, returning the stat
+    data to index it with and the blob's ID.
+*/
+fn store_file(repo: &Repository, file: &Path, data: Vec<u8>) -> io::Result<(Metadata, ObjectId)> {
+    let stat = repo.workspace.stat_file(file)?;
+    let mut blob = Blob::new(data);
+    let object_id = repo.database.store(&mut blob)?;
+    Ok((stat, object_id))
+}
+
+/*
+    Index key for a pathspec, "" when it names the workspace root. Paths outside
+    the workspace are rejected with git's message.
+*/
+fn relative_key(repo: &Repository, arg: &str, path: &Path) -> String {
+    match path.strip_prefix(repo.workspace.root()) {
+        Ok(relative) => utils::path_to_index_key(relative),
+        Err(_) => fatal(format!(
+            "{}: '{}' is outside repository at '{}'",
+            arg,
+            arg,
+            repo.workspace.root().display()
+        )),
+    }
+}
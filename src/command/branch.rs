@@ -0,0 +1,205 @@
+use std::{io, process};
+
+use crate::command::{fatal, resolve_commit};
+use crate::database::Database;
+use crate::merge_base::is_ancestor;
+use crate::object_id::ObjectId;
+use crate::refs::{self, Refs, RefsError};
+use crate::repository::Repository;
+use crate::utils;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    List,
+    Create,
+    Delete,
+    Rename,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut action = Action::List;
+    let mut force = false;
+    let mut verbose = false;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-l" | "--list" => action = Action::List,
+            "-d" | "--delete" => action = Action::Delete,
+            "-D" => {
+                action = Action::Delete;
+                force = true;
+            }
+            "-m" | "--move" => action = Action::Rename,
+            "-M" => {
+                action = Action::Rename;
+                force = true;
+            }
+            "-f" | "--force" => force = true,
+            "-v" | "-vv" | "--verbose" => verbose = true,
+            other if other.starts_with('-') => fatal(format!("unknown option '{}'", other)),
+            name => names.push(name.to_string()),
+        }
+    }
+    if action == Action::List && !names.is_empty() {
+        action = Action::Create;
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    match action {
+        Action::List => list_branches(&repo, verbose),
+        Action::Create => create_branch(&repo, &names, force),
+        Action::Delete => delete_branches(&repo, &names, force),
+        Action::Rename => rename_branch(&repo, &names, force),
+    }
+}
+
+fn list_branches(repo: &Repository, verbose: bool) -> io::Result<()> {
+    let current = repo.refs.current_branch()?;
+    let branches = repo.refs.list_branches()?;
+
+    let mut lines = Vec::new();
+    if current.is_none() {
+        if let Some(head) = repo.refs.read_head()? {
+            lines.push((
+                true,
+                format!(
+                    "(HEAD detached at {})",
+                    repo.database.short_object_id(&head)?
+                ),
+                Some(head),
+            ));
+        }
+    }
+    for name in branches {
+        let object_id = repo.refs.read_ref(&Refs::branch_ref(&name))?;
+        lines.push((current.as_deref() == Some(name.as_str()), name, object_id));
+    }
+
+    let width = lines
+        .iter()
+        .map(|(_, name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (is_current, name, object_id) in lines {
+        let marker = if is_current { '*' } else { ' ' };
+        match (verbose, object_id) {
+            (true, Some(object_id)) => {
+                let commit = repo.database.load_commit(&object_id)?;
+                println!(
+                    "{} {:<width$} {} {}",
+                    marker,
+                    name,
+                    repo.database.short_object_id(&object_id)?,
+                    commit.title_line(),
+                    width = width
+                );
+            }
+            _ => println!("{} {}", marker, name),
+        }
+    }
+    Ok(())
+}
+
+fn create_branch(repo: &Repository, names: &[String], force: bool) -> io::Result<()> {
+    let name = &names[0];
+    let start = names.get(1).map(String::as_str).unwrap_or(refs::HEAD);
+    let Some(object_id) = resolve_commit(repo, start)? else {
+        fatal(format!("not a valid object name: '{}'", start));
+    };
+    if force && repo.refs.current_branch()?.as_deref() == Some(name.as_str()) {
+        fatal(format!("cannot force update the current branch '{}'", name));
+    }
+    // Without a start point the reflog names the branch HEAD was on.
+    let start = match names.get(1) {
+        Some(start) => start.clone(),
+        None => repo
+            .refs
+            .current_branch()?
+            .unwrap_or_else(|| refs::HEAD.to_string()),
+    };
+    match repo.refs.create_branch(name, &object_id, &start, force) {
+        Ok(()) => Ok(()),
+        Err(RefsError::Io(err)) => Err(err),
+        Err(err) => fatal(err),
+    }
+}
+
+fn delete_branches(repo: &Repository, names: &[String], force: bool) -> io::Result<()> {
+    if names.is_empty() {
+        fatal("branch name required");
+    }
+    let current = repo.refs.current_branch()?;
+    let head = repo.refs.read_head()?;
+    let mut failed = false;
+    for name in names {
+        if current.as_deref() == Some(name.as_str()) {
+            eprintln!(
+                "error: Cannot delete branch '{}' checked out at '{}'",
+                name,
+                repo.workspace.root().display()
+            );
+            failed = true;
+            continue;
+        }
+        let Some(object_id) = repo.refs.read_ref(&Refs::branch_ref(name))? else {
+            eprintln!("error: branch '{}' not found.", name);
+            failed = true;
+            continue;
+        };
+        if !force && !is_merged(&repo.database, &object_id, head)? {
+            eprintln!("error: The branch '{}' is not fully merged.", name);
+            eprintln!(
+                "If you are sure you want to delete it, run 'git branch -D {}'.",
+                name
+            );
+            failed = true;
+            continue;
+        }
+        match repo.refs.delete_branch(name) {
+            Ok(object_id) => println!(
+                "Deleted branch {} (was {}).",
+                name,
+                repo.database.short_object_id(&object_id)?
+            ),
+            Err(RefsError::Io(err)) => return Err(err),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn rename_branch(repo: &Repository, names: &[String], force: bool) -> io::Result<()> {
+    let (old, new) = match names {
+        [new] => match repo.refs.current_branch()? {
+            Some(current) => (current, new.clone()),
+            None => fatal("cannot rename the current branch while not on any."),
+        },
+        [old, new] => (old.clone(), new.clone()),
+        _ => fatal("too many arguments for a rename operation"),
+    };
+    match repo.refs.rename_branch(&old, &new, force) {
+        Ok(()) => Ok(()),
+        Err(RefsError::BranchNotFound(_)) => {
+            eprintln!("error: refname {} not found", Refs::branch_ref(&old));
+            fatal("Branch rename failed");
+        }
+        Err(RefsError::Io(err)) => Err(err),
+        Err(err) => fatal(err),
+    }
+}
+
+/*
+    A branch is merged when its tip is reachable from HEAD.
+*/
+fn is_merged(database: &Database, tip: &ObjectId, head: Option<ObjectId>) -> io::Result<bool> {
+    match head {
+        Some(head) => is_ancestor(database, tip, &head),
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,81 @@
+use std::{io, process};
+
+use crate::command::fatal;
+use crate::repository::Repository;
+use crate::utils;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut verbose = false;
+    let mut quiet = false;
+    let mut non_matching = false;
+    let mut no_index = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-v" | "--verbose" => verbose = true,
+            "-q" | "--quiet" => quiet = true,
+            "-n" | "--non-matching" => non_matching = true,
+            "--no-index" => no_index = true,
+            other if other.starts_with('-') => fatal(format!("unknown option '{}'", other)),
+            path => paths.push(path),
+        }
+    }
+    if paths.is_empty() {
+        fatal("no path specified");
+    }
+    if non_matching && !verbose {
+        fatal("--non-matching is only valid with --verbose");
+    }
+
+    let root_path = utils::get_root_path();
+    let mut repo = Repository::new(root_path.clone());
+    if !no_index {
+        repo.index.load()?;
+    }
+
+    let mut ignored = 0;
+    for path in paths {
+        let full_path = utils::normalize_path(&root_path.join(path));
+        let Ok(relative) = full_path.strip_prefix(&root_path) else {
+            fatal(format!(
+                "{}: '{}' is outside repository at '{}'",
+                path,
+                path,
+                root_path.display()
+            ));
+        };
+        let key = utils::path_to_index_key(relative);
+
+        // Tracked files are never subject to ignore rules.
+        let mut found = None;
+        if no_index || !repo.index.is_tracked_file(&key) {
+            found = repo
+                .workspace
+                .ignore()
+                .match_with_parents(&key, full_path.is_dir());
+        }
+        if !verbose {
+            found = found.filter(|found| found.is_ignored());
+        }
+
+        if !quiet {
+            match (&found, verbose) {
+                (Some(found), true) => println!(
+                    "{}:{}:{}\t{}",
+                    found.source, found.pattern.line_number, found.pattern.text, path
+                ),
+                (Some(_), false) => println!("{}", path),
+                (None, true) if non_matching => println!("::\t{}", path),
+                (None, _) => {}
+            }
+        }
+        if found.is_some() {
+            ignored += 1;
+        }
+    }
+
+    if ignored == 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
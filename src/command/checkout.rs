@@ -0,0 +1,263 @@
+use std::{io, process};
+
+use crate::command::{fatal, resolve_commit};
+use crate::migration::{Migration, MigrationError};
+use crate::object_id::ObjectId;
+use crate::refs::{self, Ref, Refs, RefsError};
+use crate::repository::Repository;
+use crate::status::{ChangeType, Status};
+use crate::tree_diff::{tree_diff, TreeChange};
+use crate::utils;
+
+const DETACHED_HEAD_ADVICE: &str = "\
+You are in 'detached HEAD' state. You can look around, make experimental
+changes and commit them, and you can discard any commits you make in this
+state without impacting any branches by switching back to a branch.
+
+If you want to create a new branch to retain commits you create, you may
+do so (now or later) by using -c with the switch command. Example:
+
+  git switch -c <new-branch-name>
+
+Or undo this operation with:
+
+  git switch -
+
+Turn off this advice by setting config variable advice.detachedHead to false
+";
+
+/*
+    What checkout and switch were asked to do. new_branch is created at
+    target (HEAD if none) and replaces an existing branch if force_create.
+*/
+#[derive(Debug, Default)]
+pub struct SwitchOptions {
+    pub target: Option<String>,
+    pub new_branch: Option<String>,
+    pub force_create: bool,
+    pub detach: bool,
+    pub quiet: bool,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = SwitchOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-b" | "-B" => {
+                let Some(name) = args.next() else {
+                    fatal(format!("switch `{}' requires a value", &arg[1..]));
+                };
+                options.new_branch = Some(name.clone());
+                options.force_create = arg == "-B";
+            }
+            "--detach" => options.detach = true,
+            "-q" | "--quiet" => options.quiet = true,
+            other if other.starts_with('-') => fatal(format!("unknown option '{}'", other)),
+            target if options.target.is_none() => options.target = Some(target.to_string()),
+            _ => fatal("checking out paths is not supported"),
+        }
+    }
+
+    let mut repo = Repository::new(utils::get_root_path());
+    if options.target.is_none() && options.new_branch.is_none() && !options.detach {
+        repo.index.load()?;
+        return show_local_changes(&mut repo);
+    }
+    let target = options.target.as_deref().unwrap_or(refs::HEAD);
+    let is_branch = is_branch(&repo, target)?;
+    if options.new_branch.is_none() && !is_branch && resolve_commit(&repo, target)?.is_none() {
+        eprintln!(
+            "error: pathspec '{}' did not match any file(s) known to git",
+            target
+        );
+        process::exit(1);
+    }
+    switch_branches(&mut repo, &options)
+}
+
+pub fn is_branch(repo: &Repository, name: &str) -> io::Result<bool> {
+    Ok(refs::is_valid_branch_name(name)
+        && repo.refs.read_ref_file(&Refs::branch_ref(name))?.is_some())
+}
+
+/*
+    Moves the workspace, index and HEAD to the target, attaching HEAD to
+    it if it is a branch (or the new branch) and detaching it otherwise.
+    Local changes to files that differ between the two commits abort
+    the switch before anything is touched.
+*/
+pub fn switch_branches(repo: &mut Repository, options: &SwitchOptions) -> io::Result<()> {
+    let target = options.target.as_deref().unwrap_or(refs::HEAD);
+    let Some(new_commit) = resolve_commit(repo, target)? else {
+        match &options.new_branch {
+            Some(name) => fatal(format!(
+                "'{}' is not a commit and a branch '{}' cannot be created from it",
+                target, name
+            )),
+            None => fatal(format!("invalid reference: {}", target)),
+        }
+    };
+    let branch = match &options.new_branch {
+        Some(name) => Some(name.clone()),
+        None if !options.detach && is_branch(repo, target)? => Some(target.to_string()),
+        None => None,
+    };
+    let old_commit = repo.refs.read_head()?;
+    let old_branch = repo.refs.current_branch()?;
+    let mut branch_existed = false;
+    if let Some(name) = &options.new_branch {
+        if !refs::is_valid_branch_name(name) {
+            fatal(RefsError::InvalidBranchName(name.clone()));
+        }
+        branch_existed = is_branch(repo, name)?;
+        if branch_existed && !options.force_create {
+            fatal(RefsError::BranchExists(name.clone()));
+        }
+    }
+
+    if let Err(err) = repo.index.load_for_update() {
+        fatal(err);
+    }
+    if repo.index.has_conflicts() {
+        repo.index.release_lock()?;
+        for path in repo.index.conflict_paths() {
+            println!("{}: needs merge", path);
+        }
+        eprintln!("error: you need to resolve your current index first");
+        process::exit(1);
+    }
+    let changes = match commit_changes(repo, old_commit, &new_commit) {
+        Ok(changes) => changes,
+        Err(err) => {
+            repo.index.release_lock()?;
+            return Err(err);
+        }
+    };
+    match Migration::new(changes).apply(repo) {
+        Ok(()) => repo.index.write_updates()?,
+        Err(err) => {
+            repo.index.release_lock()?;
+            match err {
+                MigrationError::Io(err) => return Err(err),
+                err => {
+                    for message in err.messages("checkout") {
+                        eprintln!("error: {}", message);
+                    }
+                    eprintln!("Aborting");
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
+    let message = format!(
+        "checkout: moving from {} to {}",
+        match (&old_branch, old_commit) {
+            (Some(name), _) => name.clone(),
+            (None, Some(object_id)) => object_id.to_string(),
+            (None, None) => refs::HEAD.to_string(),
+        },
+        branch.as_deref().unwrap_or(target)
+    );
+    let result = match &branch {
+        Some(name) => {
+            let ref_name = Refs::branch_ref(name);
+            if options.new_branch.is_some() {
+                repo.refs.create_branch(name, &new_commit, target, true)
+            } else {
+                Ok(())
+            }
+            .and_then(|_| repo.refs.set_head(&Ref::Symbolic(ref_name), &message))
+        }
+        None => repo.refs.set_head(&Ref::Direct(new_commit), &message),
+    };
+    match result {
+        Ok(()) => {}
+        Err(RefsError::Io(err)) => return Err(err),
+        Err(err) => fatal(err),
+    }
+
+    if options.quiet {
+        return Ok(());
+    }
+    show_local_changes(repo)?;
+    if let Some(old_commit) = old_commit.filter(|_| old_branch.is_none()) {
+        if old_commit != new_commit {
+            eprintln!(
+                "Previous HEAD position was {}",
+                describe_commit(repo, &old_commit)?
+            );
+        }
+    }
+    match &branch {
+        Some(name) if options.new_branch.is_some() => {
+            if !branch_existed {
+                eprintln!("Switched to a new branch '{}'", name);
+            } else if old_branch.as_deref() == Some(name.as_str()) {
+                eprintln!("Reset branch '{}'", name);
+            } else {
+                eprintln!("Switched to and reset branch '{}'", name);
+            }
+        }
+        Some(name) if old_branch.as_deref() == Some(name.as_str()) => {
+            eprintln!("Already on '{}'", name)
+        }
+        Some(name) => eprintln!("Switched to branch '{}'", name),
+        None => {
+            if old_branch.is_some() && !options.detach {
+                eprintln!("Note: switching to '{}'.\n", target);
+                eprintln!("{}", DETACHED_HEAD_ADVICE);
+            }
+            eprintln!("HEAD is now at {}", describe_commit(repo, &new_commit)?);
+        }
+    }
+    Ok(())
+}
+
+/*
+    The changes between the trees of old_commit, if there is one, and
+    new_commit.
+*/
+fn commit_changes(
+    repo: &Repository,
+    old_commit: Option<ObjectId>,
+    new_commit: &ObjectId,
+) -> io::Result<Vec<TreeChange>> {
+    let old_tree = match old_commit {
+        Some(object_id) => Some(repo.database.load_commit(&object_id)?.tree_object_id),
+        None => None,
+    };
+    let new_tree = repo.database.load_commit(new_commit)?.tree_object_id;
+    tree_diff(&repo.database, old_tree, Some(new_tree))
+}
+
+/*
+    Lists files whose workspace version differs from HEAD, as
+    "<status>\t<path>", the way git does after switching.
+*/
+fn show_local_changes(repo: &mut Repository) -> io::Result<()> {
+    let status = Status::new(repo)?;
+    for path in &status.changed {
+        let code = match (
+            status.index_changes.get(path),
+            status.workspace_changes.get(path),
+        ) {
+            (Some(ChangeType::Added), Some(ChangeType::Deleted)) => continue,
+            (_, Some(ChangeType::Deleted)) | (Some(ChangeType::Deleted), None) => 'D',
+            (Some(ChangeType::Added), _) => 'A',
+            _ => 'M',
+        };
+        println!("{}\t{}", code, path);
+    }
+    Ok(())
+}
+
+fn describe_commit(repo: &Repository, object_id: &ObjectId) -> io::Result<String> {
+    let commit = repo.database.load_commit(object_id)?;
+    Ok(format!(
+        "{} {}",
+        repo.database.short_object_id(object_id)?,
+        commit.title_line()
+    ))
+}
@@ -0,0 +1,133 @@
+use std::{env, io, process};
+
+use chrono::Local;
+
+use crate::author::Author;
+use crate::command::fatal;
+use crate::commit::Commit;
+use crate::entry::Entry;
+use crate::object_id::ObjectId;
+use crate::repository::Repository;
+use crate::tree::Tree;
+use crate::utils;
+
+pub fn run(_args: &[String]) -> io::Result<()> {
+    let mut repo = Repository::new(utils::get_root_path());
+    repo.index.load()?;
+    if repo.index.has_conflicts() {
+        for path in repo.index.conflict_paths() {
+            println!("U\t{}", path);
+        }
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'git add/rm <file>'");
+        eprintln!("hint: as appropriate to mark resolution and make a commit.");
+        eprintln!("fatal: Exiting because of an unresolved conflict.");
+        process::exit(128);
+    }
+
+    // Get parent of current commit, plus the commit being merged if a
+    // merge stopped for conflicts.
+    let parent = repo.refs.read_head()?;
+    let pending = repo.pending_commit();
+    let mut parents: Vec<ObjectId> = parent.into_iter().collect();
+    parents.extend(pending.merge_object_id()?);
+
+    // Read commit message, falling back to the merge's own message.
+    let mut commit_message = String::new();
+    io::stdin().read_line(&mut commit_message)?;
+    if commit_message.trim().is_empty() {
+        if let Some(message) = pending.merge_message()? {
+            commit_message = strip_comments(&message);
+        }
+    }
+
+    let kind = match parents.len() {
+        0 => " (initial)",
+        1 => "",
+        _ => " (merge)",
+    };
+    let (commit_id, commit) = write_commit(&repo, parents, commit_message)?;
+    pending.clear()?;
+    let first_line = commit.title_line();
+
+    // Advance the current branch, or HEAD itself when detached.
+    let message = format!("commit{}: {}", kind, first_line);
+    if let Err(err) = repo.refs.update_head(&commit_id, &message) {
+        fatal(err);
+    }
+
+    let branch = match repo.refs.current_branch()? {
+        Some(branch) => branch,
+        None => String::from("detached HEAD"),
+    };
+    let mut is_root = String::from("");
+    if parent.is_none() {
+        is_root = String::from("(root-commit) ");
+    }
+    println!(
+        "[{} {}{}] {}",
+        branch,
+        is_root,
+        repo.database.short_object_id(&commit_id)?,
+        first_line
+    );
+    Ok(())
+}
+
+/*
+    Stores the index as a tree and a commit of it with the given parents.
+    Moving HEAD to it is left to the caller.
+*/
+pub fn write_commit(
+    repo: &Repository,
+    parents: Vec<ObjectId>,
+    message: String,
+) -> io::Result<(ObjectId, Commit)> {
+    // Read staged index entries into Entry vector (used to construct Tree).
+    let entries = repo.index.entries().map(Entry::from).collect();
+
+    // Create and store tree for commit.
+    let mut tree = Tree::build(entries);
+    tree.store_tree(&repo.database);
+    let tree_object_id = tree.object_id.expect("tree was just stored");
+
+    let mut commit = Commit::new(parents, tree_object_id, current_author(), message);
+    let commit_id = repo.database.store(&mut commit)?;
+    Ok((commit_id, commit))
+}
+
+/*
+    Cleans up a message the way git does before committing it: drops
+    "#" comment lines, such as MERGE_MSG's list of conflicts, trailing
+    whitespace, and blank lines at either end or repeated.
+*/
+fn strip_comments(message: &str) -> String {
+    let mut cleaned = String::new();
+    let mut blank = false;
+    for line in message.lines().filter(|line| !line.starts_with('#')) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = !cleaned.is_empty();
+            continue;
+        }
+        if blank {
+            cleaned.push('\n');
+            blank = false;
+        }
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+/*
+    The author of new commits, from GIT_AUTHOR_NAME and GIT_AUTHOR_EMAIL,
+    dated now.
+*/
+pub fn current_author() -> Author {
+    let now = Local::now();
+    let formatted_datetime = now.format("%s %z").to_string();
+    let author_name = env::var("GIT_AUTHOR_NAME").expect("GIT_AUTHOR_NAME not set");
+    let author_email = env::var("GIT_AUTHOR_EMAIL").expect("GIT_AUTHOR_EMAIL not set");
+    Author::new(author_name, author_email, formatted_datetime)
+}
@@ -0,0 +1,379 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::blob::{Blob, Kind};
+use crate::command::{fatal, resolve_revision};
+use crate::database::Database;
+use crate::diff::{self, EditKind, Hunk};
+use crate::entry::Entry;
+use crate::object_id::ObjectId;
+use crate::rename::{detect_renames, RenameOptions};
+use crate::repository::Repository;
+use crate::revision::RevisionError;
+use crate::status::{ChangeType, Status};
+use crate::tree_diff::{tree_diff, ChangeKind, TreeChange};
+use crate::utils;
+
+const NULL_PATH: &str = "/dev/null";
+
+// The two sides of one file's diff and how they are related.
+type FilePair = (Target, Target, ChangeKind);
+
+/*
+    One side of a file comparison. A file missing on this side has no
+    object ID or mode.
+*/
+struct Target {
+    path: String,
+    object_id: Option<ObjectId>,
+    mode: Option<u32>,
+    data: Vec<u8>,
+}
+
+impl Target {
+    fn missing(path: &str) -> Self {
+        Target {
+            path: path.to_string(),
+            object_id: None,
+            mode: None,
+            data: Vec::new(),
+        }
+    }
+
+    fn from_database(
+        database: &Database,
+        path: &str,
+        object_id: ObjectId,
+        mode: u32,
+    ) -> io::Result<Self> {
+        let (_, data) = database.read_object(&object_id)?;
+        Ok(Target {
+            path: path.to_string(),
+            object_id: Some(object_id),
+            mode: Some(mode),
+            data,
+        })
+    }
+
+    fn from_workspace(repo: &Repository, path: &str, mode: u32) -> io::Result<Self> {
+        let data = repo.workspace.read_data(Path::new(path))?;
+        let blob = Blob::new(data);
+        let object_id = repo.database.hash_object(&blob);
+        Ok(Target {
+            path: path.to_string(),
+            object_id: Some(object_id),
+            mode: Some(mode),
+            data: blob.data,
+        })
+    }
+
+    fn name(&self, prefix: &str) -> String {
+        match self.mode {
+            Some(_) => format!("{}/{}", prefix, self.path),
+            None => String::from(NULL_PATH),
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut cached = false;
+    let mut context = diff::DEFAULT_CONTEXT;
+    let mut renames = RenameOptions::default();
+    let mut revisions = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--cached" | "--staged" => cached = true,
+            "--" => break,
+            other if renames.parse_option(other) => {}
+            other => {
+                let lines = other
+                    .strip_prefix("--unified=")
+                    .or_else(|| other.strip_prefix("-U"));
+                if let Some(lines) = lines {
+                    context = match lines.parse() {
+                        Ok(lines) => lines,
+                        Err(_) => fatal(format!("'{}': not an integer", lines)),
+                    };
+                } else if other.starts_with('-') {
+                    fatal(format!("invalid option: {}", other));
+                } else {
+                    revisions.push(other.to_string());
+                }
+            }
+        }
+    }
+
+    let mut repo = Repository::new(utils::get_root_path());
+    let mut unmerged = Vec::new();
+    let pairs = match (cached, revisions.as_slice()) {
+        (_, [a, b]) => diff_revisions(&repo, a, b, &renames)?,
+        (true, []) => {
+            let status = load_status(&mut repo)?;
+            unmerged.extend(status.conflicts.keys().cloned());
+            diff_head_index(&repo, &status, &renames)?
+        }
+        (false, []) => {
+            let status = load_status(&mut repo)?;
+            unmerged.extend(status.conflicts.keys().cloned());
+            diff_index_workspace(&repo, &status)?
+        }
+        _ => fatal("comparing a single revision with the index or working tree is not supported"),
+    };
+
+    // Unmerged paths have no single version to compare, and are only
+    // named, in path order with the rest.
+    let mut out = io::stdout().lock();
+    let mut unmerged = unmerged.into_iter().peekable();
+    for (a, b, kind) in pairs {
+        while let Some(path) = unmerged.next_if(|path| *path < b.path) {
+            writeln!(out, "* Unmerged path {}", path)?;
+        }
+        print_diff(&mut out, &repo.database, &a, &b, kind, context)?;
+    }
+    for path in unmerged {
+        writeln!(out, "* Unmerged path {}", path)?;
+    }
+    Ok(())
+}
+
+fn load_status(repo: &mut Repository) -> io::Result<Status> {
+    // As with status, stat data is refreshed only when the lock is free.
+    let locked = repo.index.load_for_update().is_ok();
+    if !locked {
+        repo.index.load()?;
+    }
+    let status = Status::new(repo);
+    if locked {
+        match &status {
+            Ok(_) => repo.index.write_updates()?,
+            Err(_) => repo.index.release_lock()?,
+        }
+    }
+    status
+}
+
+/*
+    Untracked files never show up here, so there is nothing to pair
+    renames with.
+*/
+fn diff_index_workspace(repo: &Repository, status: &Status) -> io::Result<Vec<FilePair>> {
+    let mut pairs = Vec::new();
+    for (path, change) in &status.workspace_changes {
+        let entry = repo
+            .index
+            .entry_for_path(path)
+            .expect("workspace changes are to tracked files");
+        let a = Target::from_database(&repo.database, path, entry.object_id, entry.mode)?;
+        let (b, kind) = match (change, status.workspace_mode(path)) {
+            (ChangeType::Deleted, _) | (_, None) => (Target::missing(path), ChangeKind::Deleted),
+            (_, Some(mode)) => (
+                Target::from_workspace(repo, path, mode)?,
+                ChangeKind::Modified,
+            ),
+        };
+        pairs.push((a, b, kind));
+    }
+    Ok(pairs)
+}
+
+fn diff_head_index(
+    repo: &Repository,
+    status: &Status,
+    renames: &RenameOptions,
+) -> io::Result<Vec<FilePair>> {
+    let changes = status.index_tree_changes(repo);
+    let unchanged: Vec<Entry> = status
+        .head_tree
+        .iter()
+        .filter(|(path, _)| !status.index_changes.contains_key(*path))
+        .map(|(_, entry)| entry.clone())
+        .collect();
+    let changes = detect_renames(&repo.database, changes, &unchanged, renames)?;
+    file_pairs(&repo.database, changes)
+}
+
+fn diff_revisions(
+    repo: &Repository,
+    a: &str,
+    b: &str,
+    renames: &RenameOptions,
+) -> io::Result<Vec<FilePair>> {
+    let a_tree = resolve_tree(repo, a)?;
+    let b_tree = resolve_tree(repo, b)?;
+    let changes = tree_diff(&repo.database, Some(a_tree), Some(b_tree))?;
+    let unchanged = if renames.find_copies_harder {
+        let mut entries = repo.database.load_tree_entries(&a_tree)?;
+        for change in &changes {
+            entries.remove(&change.path);
+        }
+        entries.into_values().collect()
+    } else {
+        Vec::new()
+    };
+    let changes = detect_renames(&repo.database, changes, &unchanged, renames)?;
+    file_pairs(&repo.database, changes)
+}
+
+fn file_pairs(database: &Database, changes: Vec<TreeChange>) -> io::Result<Vec<FilePair>> {
+    let mut pairs = Vec::new();
+    for change in changes {
+        let a = target_for_entry(database, &change.path, change.old.as_ref())?;
+        let b = target_for_entry(database, &change.path, change.new.as_ref())?;
+        // Like git, a change of file type is shown as a deletion and an addition.
+        if change.kind == ChangeKind::TypeChanged {
+            pairs.push((a, Target::missing(&change.path), ChangeKind::Deleted));
+            pairs.push((Target::missing(&change.path), b, ChangeKind::Added));
+        } else {
+            pairs.push((a, b, change.kind));
+        }
+    }
+    Ok(pairs)
+}
+
+/*
+    A missing entry keeps path; a present one uses its own, which differs
+    from path for the source of a rename or copy.
+*/
+fn target_for_entry(database: &Database, path: &str, entry: Option<&Entry>) -> io::Result<Target> {
+    match entry {
+        Some(entry) => Target::from_database(
+            database,
+            &entry.path.to_string_lossy(),
+            entry.object_id,
+            entry.mode,
+        ),
+        None => Ok(Target::missing(path)),
+    }
+}
+
+fn resolve_tree(repo: &Repository, revision: &str) -> io::Result<ObjectId> {
+    match resolve_revision(repo, revision, Some(Kind::Tree))? {
+        Some(tree_id) => Ok(tree_id),
+        None => fatal(RevisionError::Unknown(revision.to_string())),
+    }
+}
+
+/*
+    Prints one file's "diff --git" section. Identical sides print nothing,
+    unless they are a rename or copy.
+*/
+fn print_diff(
+    out: &mut impl Write,
+    database: &Database,
+    a: &Target,
+    b: &Target,
+    kind: ChangeKind,
+    context: usize,
+) -> io::Result<()> {
+    if a.object_id == b.object_id && a.mode == b.mode && a.path == b.path {
+        return Ok(());
+    }
+    writeln!(out, "diff --git a/{} b/{}", a.path, b.path)?;
+    match (a.mode, b.mode) {
+        (None, Some(mode)) => writeln!(out, "new file mode {:o}", mode)?,
+        (Some(mode), None) => writeln!(out, "deleted file mode {:o}", mode)?,
+        (Some(a_mode), Some(b_mode)) if a_mode != b_mode => {
+            writeln!(out, "old mode {:o}", a_mode)?;
+            writeln!(out, "new mode {:o}", b_mode)?;
+        }
+        _ => {}
+    }
+    match kind {
+        ChangeKind::Renamed(similarity) | ChangeKind::Copied(similarity) => {
+            let verb = match kind {
+                ChangeKind::Renamed(_) => "rename",
+                _ => "copy",
+            };
+            writeln!(out, "similarity index {}%", similarity)?;
+            writeln!(out, "{} from {}", verb, a.path)?;
+            writeln!(out, "{} to {}", verb, b.path)?;
+        }
+        _ => {}
+    }
+    if a.object_id == b.object_id {
+        return Ok(());
+    }
+
+    write!(
+        out,
+        "index {}..{}",
+        short_id(database, a.object_id)?,
+        short_id(database, b.object_id)?
+    )?;
+    match (a.mode, b.mode) {
+        (Some(a_mode), Some(b_mode)) if a_mode == b_mode => writeln!(out, " {:o}", a_mode)?,
+        _ => writeln!(out)?,
+    }
+
+    let (a_name, b_name) = (a.name("a"), b.name("b"));
+    if diff::is_binary(&a.data) || diff::is_binary(&b.data) {
+        writeln!(out, "Binary files {} and {} differ", a_name, b_name)?;
+        return Ok(());
+    }
+    let a_lines = diff::lines(&a.data);
+    let b_lines = diff::lines(&b.data);
+    let hunks = diff::diff_hunks(&a_lines, &b_lines, context);
+    if hunks.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "--- {}", a_name)?;
+    writeln!(out, "+++ {}", b_name)?;
+    for hunk in hunks {
+        print_hunk(out, &hunk, &a_lines)?;
+    }
+    Ok(())
+}
+
+fn print_hunk(out: &mut impl Write, hunk: &Hunk, a_lines: &[&[u8]]) -> io::Result<()> {
+    write!(out, "{}", hunk.header())?;
+    if let Some(function) = function_line(a_lines, hunk.a_start) {
+        out.write_all(b" ")?;
+        out.write_all(function)?;
+    }
+    writeln!(out)?;
+    for edit in &hunk.edits {
+        let sign = match edit.kind {
+            EditKind::Eql => b" ",
+            EditKind::Ins => b"+",
+            EditKind::Del => b"-",
+        };
+        let text = edit.line().text;
+        out.write_all(sign)?;
+        out.write_all(text)?;
+        if !text.ends_with(b"\n") {
+            out.write_all(b"\n\\ No newline at end of file\n")?;
+        }
+    }
+    Ok(())
+}
+
+/*
+    git's default hunk header context: the nearest line above the hunk that
+    starts with a letter, "_" or "$", cut to 80 bytes and right-trimmed.
+*/
+fn function_line<'a>(a_lines: &[&'a [u8]], before: usize) -> Option<&'a [u8]> {
+    a_lines[..before.min(a_lines.len())]
+        .iter()
+        .rev()
+        .find(|line| {
+            line.first()
+                .is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_' || b == b'$')
+        })
+        .map(|line| {
+            let line = &line[..line.len().min(80)];
+            let end = line
+                .iter()
+                .rposition(|b| !b.is_ascii_whitespace())
+                .map_or(0, |i| i + 1);
+            &line[..end]
+        })
+}
+
+fn short_id(database: &Database, object_id: Option<ObjectId>) -> io::Result<String> {
+    match object_id {
+        Some(object_id) => database.short_object_id(&object_id),
+        None => Ok(ObjectId::default().short()),
+    }
+}
@@ -0,0 +1,30 @@
+use std::io;
+
+use crate::command::fatal;
+use crate::command::repack::repack;
+use crate::refs::RefsError;
+use crate::repository::Repository;
+use crate::utils;
+
+/*
+    Packs every ref into packed-refs and consolidates the object database
+    into a single pack. Unlike a plain "repack -a -d", objects from the
+    old packs are kept even when nothing reaches them any more, so gc
+    never loses anything.
+*/
+pub fn run(args: &[String]) -> io::Result<()> {
+    for arg in args {
+        match arg.as_str() {
+            "-q" | "--quiet" => {}
+            other => fatal(format!("unknown option '{}'", other)),
+        }
+    }
+    let mut repo = Repository::new(utils::get_root_path());
+    match repo.refs.pack_refs(&repo.database, true, true) {
+        Ok(()) => {}
+        Err(RefsError::Io(err)) => return Err(err),
+        Err(err) => fatal(err),
+    }
+    repack(&mut repo, true, true, true)?;
+    Ok(())
+}
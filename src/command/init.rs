@@ -0,0 +1,49 @@
+use std::{fs, io, path::PathBuf};
+
+use crate::config::Config;
+use crate::refs::{self, Ref, Refs};
+
+const DEFAULT_BRANCH: &str = "main";
+
+fn initialize_repo_directory(mut path_buf: PathBuf) -> io::Result<()> {
+    path_buf.push(".git");
+    let dirs = ["objects", refs::HEADS_DIR, refs::TAGS_DIR];
+    for dir in dirs.into_iter() {
+        fs::create_dir_all(path_buf.join(dir))?;
+    }
+
+    // Re-running init keeps whatever HEAD already points at.
+    let refs = Refs::new(path_buf.clone());
+    if !refs.head_path().exists() {
+        let config = Config::load(&path_buf)?;
+        let branch = config.get("init.defaultBranch").unwrap_or(DEFAULT_BRANCH);
+        let head = Ref::Symbolic(format!("{}/{}", refs::HEADS_DIR, branch));
+        refs.set_head(&head, "").map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+pub fn init(dir: &str) -> io::Result<()> {
+    let path: PathBuf = fs::canonicalize(dir).or_else(|_| {
+        fs::create_dir_all(dir)?;
+        Ok::<PathBuf, io::Error>(PathBuf::from(dir))
+    })?;
+    println!("Initialized empty Git repository in {}", path.display());
+    initialize_repo_directory(path)?;
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let default_dir = &"./".to_string();
+    let dir = args.first().unwrap_or(default_dir);
+    match init(dir) {
+        Ok(_) => {
+            println!("init success");
+        }
+        Err(_) => {
+            eprintln!("init failure");
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use std::io;
+
+use crate::author::Author;
+use crate::command::{fatal, resolve_commit};
+use crate::commit::Commit;
+use crate::database::Database;
+use crate::object_id::ObjectId;
+use crate::refs;
+use crate::repository::Repository;
+use crate::rev_list::RevList;
+use crate::revision::RevisionError;
+use crate::utils;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Format {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    // A user format, and whether it terminates (tformat) rather than
+    // separates (format) entries.
+    User(String, bool),
+}
+
+struct Options {
+    format: Format,
+    abbrev_commit: bool,
+    max_count: Option<usize>,
+    reverse: bool,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = Options {
+        format: Format::Medium,
+        abbrev_commit: false,
+        max_count: None,
+        reverse: false,
+    };
+    let mut revisions = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--oneline" => {
+                options.format = Format::Oneline;
+                options.abbrev_commit = true;
+            }
+            "--abbrev-commit" => options.abbrev_commit = true,
+            "--no-abbrev-commit" => options.abbrev_commit = false,
+            "--reverse" => options.reverse = true,
+            "-n" => match args.next() {
+                Some(count) => options.max_count = Some(parse_count(count)),
+                None => fatal("switch `n' requires a value"),
+            },
+            other => {
+                if let Some(count) = other.strip_prefix("--max-count=") {
+                    options.max_count = Some(parse_count(count));
+                } else if let Some(format) = other.strip_prefix("--format=") {
+                    options.format = parse_pretty(&format!("tformat:{}", format));
+                } else if let Some(pretty) = other.strip_prefix("--pretty=") {
+                    options.format = parse_pretty(pretty);
+                } else if let Some(count) = other.strip_prefix("-n") {
+                    options.max_count = Some(parse_count(count));
+                } else if other.len() > 1
+                    && other.starts_with('-')
+                    && other[1..].bytes().all(|b| b.is_ascii_digit())
+                {
+                    options.max_count = Some(parse_count(&other[1..]));
+                } else if other.starts_with('-') {
+                    fatal(format!("unrecognized argument: {}", other));
+                } else {
+                    revisions.push(other.to_string());
+                }
+            }
+        }
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    let starts = resolve_starts(&repo, &revisions)?;
+
+    let mut commits = Vec::new();
+    for commit in RevList::new(&repo.database, &starts)? {
+        if options.max_count.is_some_and(|max| commits.len() >= max) {
+            break;
+        }
+        commits.push(commit?);
+    }
+    if options.reverse {
+        commits.reverse();
+    }
+
+    for (i, commit) in commits.iter().enumerate() {
+        print_commit(&repo.database, commit, &options, i == 0)?;
+    }
+    Ok(())
+}
+
+fn parse_count(count: &str) -> usize {
+    match count.parse() {
+        Ok(count) => count,
+        Err(_) => fatal(format!("'{}': not an integer", count)),
+    }
+}
+
+fn parse_pretty(pretty: &str) -> Format {
+    match pretty {
+        "oneline" => Format::Oneline,
+        "short" => Format::Short,
+        "medium" => Format::Medium,
+        "full" => Format::Full,
+        other => {
+            if let Some(format) = other.strip_prefix("format:") {
+                Format::User(format.to_string(), false)
+            } else if let Some(format) = other.strip_prefix("tformat:") {
+                Format::User(format.to_string(), true)
+            } else if other.contains('%') {
+                Format::User(other.to_string(), true)
+            } else {
+                fatal(format!("invalid --pretty format: {}", other))
+            }
+        }
+    }
+}
+
+fn resolve_starts(repo: &Repository, revisions: &[String]) -> io::Result<Vec<ObjectId>> {
+    if revisions.is_empty() {
+        return match repo.refs.read_head()? {
+            Some(head) => Ok(vec![head]),
+            None => {
+                let branch = repo.refs.current_branch()?;
+                fatal(format!(
+                    "your current branch '{}' does not have any commits yet",
+                    branch.as_deref().unwrap_or(refs::HEAD)
+                ))
+            }
+        };
+    }
+    let mut starts = Vec::new();
+    for revision in revisions {
+        match resolve_commit(repo, revision)? {
+            Some(object_id) => starts.push(object_id),
+            None => fatal(RevisionError::Unknown(revision.clone())),
+        }
+    }
+    Ok(starts)
+}
+
+fn commit_id_for_header(
+    database: &Database,
+    commit: &Commit,
+    options: &Options,
+) -> io::Result<String> {
+    let object_id = commit.object_id.unwrap_or_default();
+    if options.abbrev_commit {
+        database.short_object_id(&object_id)
+    } else {
+        Ok(object_id.to_hex())
+    }
+}
+
+fn print_commit(
+    database: &Database,
+    commit: &Commit,
+    options: &Options,
+    first: bool,
+) -> io::Result<()> {
+    match &options.format {
+        Format::Oneline => {
+            let (subject, _) = split_message(&commit.message);
+            println!(
+                "{} {}",
+                commit_id_for_header(database, commit, options)?,
+                subject
+            );
+        }
+        Format::User(format, terminate) => {
+            if !first && !terminate {
+                println!();
+            }
+            let output = expand_format(database, format, commit)?;
+            if *terminate {
+                println!("{}", output);
+            } else {
+                print!("{}", output);
+            }
+        }
+        Format::Short | Format::Medium | Format::Full => {
+            if !first {
+                println!();
+            }
+            println!(
+                "commit {}",
+                commit_id_for_header(database, commit, options)?
+            );
+            if commit.parents.len() > 1 {
+                println!("Merge: {}", short_ids(database, &commit.parents)?);
+            }
+            println!("Author: {}", identity(&commit.author));
+            match options.format {
+                Format::Medium => println!("Date:   {}", commit.author.readable_time()),
+                Format::Full => println!("Commit: {}", identity(&commit.committer)),
+                _ => {}
+            }
+            println!();
+            let lines: Vec<&str> = if options.format == Format::Short {
+                commit
+                    .message
+                    .lines()
+                    .take_while(|l| !l.is_empty())
+                    .collect()
+            } else {
+                commit.message.trim_end().lines().collect()
+            };
+            for line in lines {
+                println!("    {}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn identity(author: &Author) -> String {
+    format!("{} <{}>", author.name, author.email)
+}
+
+/*
+    The subject is the first paragraph of the message joined into one line,
+    the body is everything after the blank line that ends it.
+*/
+fn split_message(message: &str) -> (String, String) {
+    let message = message.trim_start_matches('\n');
+    let (subject, body) = match message.find("\n\n") {
+        Some(index) => (&message[..index], message[index..].trim_start_matches('\n')),
+        None => (message.trim_end_matches('\n'), ""),
+    };
+    let subject = subject.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    let body = body.trim_end_matches('\n');
+    let body = if body.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", body)
+    };
+    (subject, body)
+}
+
+/*
+    Expands the placeholders of a --format string. Unknown placeholders are
+    copied through unchanged, like git does.
+*/
+fn expand_format(database: &Database, format: &str, commit: &Commit) -> io::Result<String> {
+    let object_id = commit.object_id.unwrap_or_default();
+    let (subject, body) = split_message(&commit.message);
+    let mut output = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let mut placeholder = String::new();
+        if let Some(&next) = chars.peek() {
+            placeholder.push(next);
+            chars.next();
+            if (next == 'a' || next == 'c') && chars.peek().is_some() {
+                placeholder.push(chars.next().unwrap());
+            }
+        }
+        let expanded = match placeholder.as_str() {
+            "H" => object_id.to_hex(),
+            "h" => database.short_object_id(&object_id)?,
+            "T" => commit.tree_object_id.to_hex(),
+            "t" => database.short_object_id(&commit.tree_object_id)?,
+            "P" => commit
+                .parents
+                .iter()
+                .map(|parent| parent.to_hex())
+                .collect::<Vec<_>>()
+                .join(" "),
+            "p" => short_ids(database, &commit.parents)?,
+            "an" => commit.author.name.clone(),
+            "ae" => commit.author.email.clone(),
+            "ad" => commit.author.readable_time(),
+            "at" => commit.author.timestamp().to_string(),
+            "cn" => commit.committer.name.clone(),
+            "ce" => commit.committer.email.clone(),
+            "cd" => commit.committer.readable_time(),
+            "ct" => commit.committer.timestamp().to_string(),
+            "s" => subject.clone(),
+            "b" => body.clone(),
+            "B" => commit.message.clone(),
+            "n" => String::from("\n"),
+            "%" => String::from("%"),
+            other => format!("%{}", other),
+        };
+        output.push_str(&expanded);
+    }
+    Ok(output)
+}
+
+fn short_ids(database: &Database, object_ids: &[ObjectId]) -> io::Result<String> {
+    let mut ids = Vec::new();
+    for object_id in object_ids {
+        ids.push(database.short_object_id(object_id)?);
+    }
+    Ok(ids.join(" "))
+}
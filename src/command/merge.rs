@@ -0,0 +1,723 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::Path,
+    process,
+};
+
+use crate::blob::Blob;
+
+use crate::command::checkout::is_branch;
+use crate::command::commit::write_commit;
+use crate::command::{fatal, resolve_commit};
+use crate::database::Database;
+use crate::diff::{self, EditKind};
+use crate::entry::Entry;
+use crate::merge::{merge_trees, TreeMerge};
+use crate::merge_base::merge_bases;
+use crate::migration::{Conflict, Migration, MigrationError};
+use crate::object_id::ObjectId;
+use crate::refs::{self, RefsError, TAGS_DIR};
+use crate::rename::{detect_renames, RenameOptions};
+use crate::repository::Repository;
+use crate::tree_diff::{change_kind, tree_diff, ChangeKind, TreeChange};
+use crate::utils;
+
+const ORIG_HEAD: &str = "ORIG_HEAD";
+// The only strategy there is, named as git names its default.
+const STRATEGY: &str = "ort";
+// Columns git fits the diffstat into when not writing to a terminal.
+const STAT_WIDTH: usize = 80;
+
+/*
+    Whether a merge may, or must, just move the branch forward when HEAD
+    is an ancestor of the commit being merged.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FastForward {
+    Allow,
+    Only,
+    Never,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut message = None;
+    let mut fast_forward = FastForward::Allow;
+    let mut target = None;
+    let mut abort = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "--message" => match args.next() {
+                Some(text) => message = Some(text.clone()),
+                None => fatal(format!("switch `{}' requires a value", &arg[1..])),
+            },
+            "--ff" => fast_forward = FastForward::Allow,
+            "--ff-only" => fast_forward = FastForward::Only,
+            "--no-ff" => fast_forward = FastForward::Never,
+            "--abort" => abort = true,
+            other if other.starts_with("--message=") => {
+                message = Some(other["--message=".len()..].to_string())
+            }
+            other if other.starts_with('-') => fatal(format!("unknown option '{}'", other)),
+            name if target.is_none() => target = Some(name.to_string()),
+            _ => fatal("merging more than one commit is not supported"),
+        }
+    }
+    if abort {
+        if target.is_some() || message.is_some() || fast_forward != FastForward::Allow {
+            fatal("--abort expects no arguments");
+        }
+        return abort_merge(&mut Repository::new(utils::get_root_path()));
+    }
+    let Some(target) = target else {
+        fatal("No remote for the current branch.");
+    };
+
+    let mut repo = Repository::new(utils::get_root_path());
+    repo.index.load()?;
+    if repo.index.has_conflicts() {
+        eprintln!("error: Merging is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'git add/rm <file>'");
+        eprintln!("hint: as appropriate to mark resolution and make a commit.");
+        fatal("Exiting because of an unresolved conflict.");
+    }
+    if repo.pending_commit().in_progress() {
+        eprintln!("fatal: You have not concluded your merge (MERGE_HEAD exists).");
+        eprintln!("Please, commit your changes before you merge.");
+        process::exit(128);
+    }
+    let Some(theirs) = resolve_commit(&repo, &target)? else {
+        fatal(format!("{} - not something we can merge", target));
+    };
+    let Some(ours) = repo.refs.read_head()? else {
+        fatal("merging into an unborn branch is not supported");
+    };
+
+    if let Err(err) = repo
+        .refs
+        .update_ref(ORIG_HEAD, &ours, &format!("merge {}", target))
+    {
+        fatal(err);
+    }
+
+    let bases = merge_bases(&repo.database, &ours, &[theirs])?;
+    if bases.contains(&theirs) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    if bases == [ours] && fast_forward != FastForward::Never {
+        return fast_forward_to(&mut repo, ours, theirs, &target, message.is_some());
+    }
+    if fast_forward == FastForward::Only {
+        fatal("Not possible to fast-forward, aborting.");
+    }
+    let message = match message {
+        Some(message) => format!("{}\n", message),
+        None => default_message(&repo, &target)?,
+    };
+    merge(
+        &mut repo,
+        ours,
+        theirs,
+        bases.first().copied(),
+        &target,
+        message,
+    )
+}
+
+/*
+    git's "Merge branch 'topic'", naming the current branch too unless it
+    is main or master.
+*/
+fn default_message(repo: &Repository, target: &str) -> io::Result<String> {
+    let tag_ref = format!("{}/{}", TAGS_DIR, target);
+    let what = if is_branch(repo, target)? {
+        "branch"
+    } else if refs::is_valid_ref_name(target) && repo.refs.read_ref_file(&tag_ref)?.is_some() {
+        "tag"
+    } else {
+        "commit"
+    };
+    let mut message = format!("Merge {} '{}'", what, target);
+    match repo.refs.current_branch()?.as_deref() {
+        Some("main" | "master") => {}
+        Some(branch) => message.push_str(&format!(" into {}", branch)),
+        None => message.push_str(" into HEAD"),
+    }
+    message.push('\n');
+    Ok(message)
+}
+
+/*
+    Merges theirs into ours and moves the workspace and index to the
+    result. A clean result is committed with both as parents; conflicts
+    are left in the index and workspace for the user to resolve, and the
+    merge is recorded so the next commit completes it.
+*/
+fn merge(
+    repo: &mut Repository,
+    ours: ObjectId,
+    theirs: ObjectId,
+    base: Option<ObjectId>,
+    target: &str,
+    message: String,
+) -> io::Result<()> {
+    let ours_tree = repo.database.load_commit(&ours)?.tree_object_id;
+    let theirs_tree = repo.database.load_commit(&theirs)?.tree_object_id;
+    let base_tree = match base {
+        Some(base) => Some(repo.database.load_commit(&base)?.tree_object_id),
+        None => None,
+    };
+    let ours_entries = repo.database.load_tree_entries(&ours_tree)?;
+    let result = merge_trees(
+        &repo.database,
+        base_tree,
+        ours_tree,
+        theirs_tree,
+        (refs::HEAD, target),
+    )?;
+
+    // The index is locked only once everything that can fail on reading
+    // objects is done, so those errors leave no lock behind.
+    if let Err(err) = repo.index.load_for_update() {
+        fatal(err);
+    }
+    let staged = staged_paths(repo, &ours_entries);
+    if !staged.is_empty() {
+        repo.index.release_lock()?;
+        eprintln!(
+            "error: Your local changes to the following files would be overwritten by merge:"
+        );
+        for path in staged {
+            eprintln!("  {}", path);
+        }
+        abort();
+    }
+    let changes = workspace_changes(&ours_entries, &result);
+    if let Err(err) = Migration::new(changes).apply(repo) {
+        repo.index.release_lock()?;
+        match err {
+            MigrationError::Io(err) => return Err(err),
+            err => {
+                for message in err.messages("merge") {
+                    eprintln!("error: {}", message);
+                }
+                eprintln!("Aborting");
+                abort();
+            }
+        }
+    }
+    for (path, conflict) in &result.conflicts {
+        repo.index.add_conflict_set(path, conflict.stages());
+    }
+    repo.index.write_updates()?;
+
+    for warning in &result.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    for message in &result.messages {
+        println!("{}", message);
+    }
+
+    if !result.is_clean() {
+        let mut message = format!("{}\n# Conflicts:\n", message);
+        for path in result.conflicts.keys() {
+            message.push_str(&format!("#\t{}\n", path));
+        }
+        repo.pending_commit().start(&theirs, &message)?;
+        println!("Automatic merge failed; fix conflicts and then commit the result.");
+        process::exit(1);
+    }
+
+    let (commit_id, commit) = write_commit(repo, vec![ours, theirs], message)?;
+    let outcome = format!("Merge made by the '{}' strategy.", STRATEGY);
+    match repo
+        .refs
+        .update_head(&commit_id, &format!("merge {}: {}", target, outcome))
+    {
+        Ok(()) => {}
+        Err(RefsError::Io(err)) => return Err(err),
+        Err(err) => fatal(err),
+    }
+    println!("{}", outcome);
+    print_diffstat(&repo.database, ours_tree, commit.tree_object_id)
+}
+
+/*
+    Moves HEAD, the index and the workspace forward to theirs, which
+    already contains everything in ours, without making a commit.
+*/
+fn fast_forward_to(
+    repo: &mut Repository,
+    ours: ObjectId,
+    theirs: ObjectId,
+    target: &str,
+    has_message: bool,
+) -> io::Result<()> {
+    println!(
+        "Updating {}..{}",
+        repo.database.short_object_id(&ours)?,
+        repo.database.short_object_id(&theirs)?
+    );
+    let ours_tree = repo.database.load_commit(&ours)?.tree_object_id;
+    let theirs_tree = repo.database.load_commit(&theirs)?.tree_object_id;
+    let changes = tree_diff(&repo.database, Some(ours_tree), Some(theirs_tree))?;
+    if let Err(err) = repo.index.load_for_update() {
+        fatal(err);
+    }
+    if let Err(err) = Migration::new(changes).apply(repo) {
+        repo.index.release_lock()?;
+        match err {
+            MigrationError::Io(err) => return Err(err),
+            err => {
+                for message in err.messages("merge") {
+                    eprintln!("error: {}", message);
+                }
+                eprintln!("Aborting");
+                process::exit(1);
+            }
+        }
+    }
+    repo.index.write_updates()?;
+    match repo
+        .refs
+        .update_head(&theirs, &format!("merge {}: Fast-forward", target))
+    {
+        Ok(()) => {}
+        Err(RefsError::Io(err)) => return Err(err),
+        Err(err) => fatal(err),
+    }
+
+    if has_message {
+        println!("Fast-forward (no commit created; -m option ignored)");
+    } else {
+        println!("Fast-forward");
+    }
+    print_diffstat(&repo.database, ours_tree, theirs_tree)
+}
+
+/*
+    git merge --abort: puts the index and workspace back to HEAD, which
+    a merge stopped by conflicts leaves at ORIG_HEAD, and forgets the
+    merge. Like git's reset --merge, unstaged changes to files the merge
+    did not touch are kept, and nothing is done if the merge touched a
+    file that has them.
+*/
+fn abort_merge(repo: &mut Repository) -> io::Result<()> {
+    if !repo.pending_commit().in_progress() {
+        fatal("There is no merge to abort (MERGE_HEAD missing).");
+    }
+    let head_entries = match repo.refs.read_head()? {
+        Some(head) => {
+            let head_tree = repo.database.load_commit(&head)?.tree_object_id;
+            repo.database.load_tree_entries(&head_tree)?
+        }
+        None => BTreeMap::new(),
+    };
+    if let Err(err) = repo.index.load_for_update() {
+        fatal(err);
+    }
+    match reset_index(repo, &head_entries) {
+        Ok(()) => repo.index.write_updates()?,
+        Err(err) => {
+            repo.index.release_lock()?;
+            let conflicts = match err {
+                MigrationError::Io(err) => return Err(err),
+                MigrationError::Conflicts(conflicts) => conflicts,
+            };
+            for (conflict, paths) in conflicts {
+                for path in paths {
+                    match conflict {
+                        Conflict::StaleFile => {
+                            eprintln!("error: Entry '{}' not uptodate. Cannot merge.", path)
+                        }
+                        Conflict::StaleDirectory => {
+                            eprintln!("error: Updating '{}' would lose untracked files in it", path)
+                        }
+                        Conflict::UntrackedOverwritten => eprintln!(
+                            "error: Untracked working tree file '{}' would be overwritten by merge.",
+                            path
+                        ),
+                    }
+                }
+            }
+            fatal("Could not reset index file to revision 'HEAD'.");
+        }
+    }
+    repo.pending_commit().clear()
+}
+
+/*
+    Moves the index, and the workspace files whose index entries change,
+    to head_entries. Conflicted files are overwritten whatever is in them:
+    their stages first give way to the file as it is in the workspace.
+*/
+fn reset_index(
+    repo: &mut Repository,
+    head_entries: &BTreeMap<String, Entry>,
+) -> Result<(), MigrationError> {
+    for path in repo.index.conflict_paths() {
+        let path = Path::new(&path);
+        match repo.workspace.stat_file(path) {
+            Ok(stat) if stat.is_file() => {
+                let data = repo.workspace.read_data(path)?;
+                let object_id = repo.database.hash_object(&Blob::new(data));
+                repo.index.add(path, object_id, &stat);
+            }
+            _ => repo.index.remove(path),
+        }
+    }
+    let index_entries: BTreeMap<String, Entry> = repo
+        .index
+        .entries()
+        .map(|entry| (entry.path.clone(), Entry::from(entry)))
+        .collect();
+    let paths: BTreeSet<&String> = index_entries.keys().chain(head_entries.keys()).collect();
+    let mut changes = Vec::new();
+    for path in paths {
+        let old = index_entries.get(path);
+        let new = head_entries.get(path);
+        if let Some(kind) = change_kind(old, new) {
+            changes.push(TreeChange {
+                path: path.clone(),
+                kind,
+                old: old.cloned(),
+                new: new.cloned(),
+            });
+        }
+    }
+    Migration::new(changes).apply(repo)
+}
+
+fn abort() -> ! {
+    eprintln!("Merge with strategy {} failed.", STRATEGY);
+    process::exit(2);
+}
+
+/*
+    Paths whose index entry differs from HEAD. git will not merge over
+    staged changes, even to files the merge does not touch.
+*/
+fn staged_paths(repo: &Repository, head_entries: &BTreeMap<String, Entry>) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    for entry in repo.index.entries() {
+        let staged = head_entries
+            .get(&entry.path)
+            .is_none_or(|head| head.object_id != entry.object_id || head.mode != entry.mode);
+        if staged {
+            paths.insert(entry.path.clone());
+        }
+    }
+    for path in head_entries.keys() {
+        if repo.index.entry_for_path(path).is_none() {
+            paths.insert(path.clone());
+        }
+    }
+    paths
+}
+
+/*
+    What it takes to get from ours to the merged files, as tree changes
+    for a Migration.
+*/
+fn workspace_changes(ours: &BTreeMap<String, Entry>, result: &TreeMerge) -> Vec<TreeChange> {
+    let paths: BTreeSet<&String> = ours.keys().chain(result.files.keys()).collect();
+    let mut changes = Vec::new();
+    for path in paths {
+        let old = ours.get(path);
+        let new = result.files.get(path);
+        if let Some(kind) = change_kind(old, new) {
+            changes.push(TreeChange {
+                path: path.clone(),
+                kind,
+                old: old.cloned(),
+                new: new.cloned(),
+            });
+        }
+    }
+    changes
+}
+
+/*
+    One line of a diffstat: a file's name and the lines added and removed,
+    or for binary files its size before and after.
+*/
+struct FileStat {
+    name: String,
+    added: usize,
+    deleted: usize,
+    binary: bool,
+}
+
+/*
+    Prints what changed between two trees the way git's --stat and
+    --summary do after a merge: a line per file with a +/- graph scaled
+    to fit, the totals, and then the created, deleted and renamed files.
+*/
+fn print_diffstat(database: &Database, old_tree: ObjectId, new_tree: ObjectId) -> io::Result<()> {
+    let changes = tree_diff(database, Some(old_tree), Some(new_tree))?;
+    let changes = detect_renames(database, changes, &[], &RenameOptions::default())?;
+    let mut stats = Vec::new();
+    let mut summary = Vec::new();
+    for change in &changes {
+        let old_data = match &change.old {
+            Some(entry) => database.read_object(&entry.object_id)?.1,
+            None => Vec::new(),
+        };
+        let new_data = match &change.new {
+            Some(entry) => database.read_object(&entry.object_id)?.1,
+            None => Vec::new(),
+        };
+        let old_path = change.old.as_ref().map_or(change.path.clone(), |entry| {
+            entry.path.to_string_lossy().to_string()
+        });
+        let name = match change.kind {
+            ChangeKind::Renamed(_) | ChangeKind::Copied(_) => rename_name(&old_path, &change.path),
+            _ => change.path.clone(),
+        };
+
+        let old_mode = change.old.as_ref().map(|entry| entry.mode);
+        let new_mode = change.new.as_ref().map(|entry| entry.mode);
+        match change.kind {
+            ChangeKind::Added => summary.push(format!(
+                " create mode {:o} {}",
+                new_mode.unwrap_or(0),
+                change.path
+            )),
+            ChangeKind::Deleted => summary.push(format!(
+                " delete mode {:o} {}",
+                old_mode.unwrap_or(0),
+                change.path
+            )),
+            ChangeKind::Renamed(similarity) | ChangeKind::Copied(similarity) => {
+                let verb = match change.kind {
+                    ChangeKind::Renamed(_) => "rename",
+                    _ => "copy",
+                };
+                summary.push(format!(" {} {} ({}%)", verb, name, similarity));
+                if old_mode != new_mode {
+                    summary.push(format!(
+                        " mode change {:o} => {:o}",
+                        old_mode.unwrap_or(0),
+                        new_mode.unwrap_or(0)
+                    ));
+                }
+            }
+            _ if old_mode != new_mode => summary.push(format!(
+                " mode change {:o} => {:o} {}",
+                old_mode.unwrap_or(0),
+                new_mode.unwrap_or(0),
+                change.path
+            )),
+            _ => {}
+        }
+
+        let stat = if diff::is_binary(&old_data) || diff::is_binary(&new_data) {
+            FileStat {
+                name,
+                added: new_data.len(),
+                deleted: old_data.len(),
+                binary: true,
+            }
+        } else {
+            let edits = diff::diff(&diff::lines(&old_data), &diff::lines(&new_data));
+            FileStat {
+                name,
+                added: edits
+                    .iter()
+                    .filter(|edit| edit.kind == EditKind::Ins)
+                    .count(),
+                deleted: edits
+                    .iter()
+                    .filter(|edit| edit.kind == EditKind::Del)
+                    .count(),
+                binary: false,
+            }
+        };
+        stats.push(stat);
+    }
+
+    for line in stat_lines(&stats) {
+        println!("{}", line);
+    }
+    for line in summary {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/*
+    git's way of showing a rename in a diffstat: the parts the two paths
+    share are written once around "{old => new}".
+*/
+fn rename_name(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // The common suffix may reach back into the prefix's closing slash.
+    let mut suffix = 0;
+    let floor = prefix.saturating_sub(1);
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > floor && j > floor && a[i - 1] == b[j - 1] {
+        if a[i - 1] == b'/' {
+            suffix = a.len() - (i - 1);
+        }
+        i -= 1;
+        j -= 1;
+    }
+    let old_middle = &old[prefix..old.len().saturating_sub(suffix).max(prefix)];
+    let new_middle = &new[prefix..new.len().saturating_sub(suffix).max(prefix)];
+    if prefix + suffix == 0 {
+        return format!("{} => {}", old_middle, new_middle);
+    }
+    format!(
+        "{}{{{} => {}}}{}",
+        &old[..prefix],
+        old_middle,
+        new_middle,
+        &old[old.len() - suffix..]
+    )
+}
+
+/*
+    The per-file lines and the totals line of a diffstat, with names and
+    graphs shortened to fit STAT_WIDTH columns as git's show_stats does.
+*/
+fn stat_lines(stats: &[FileStat]) -> Vec<String> {
+    let max_len = stats
+        .iter()
+        .map(|stat| stat.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut max_change = 0;
+    let mut bin_width = 0;
+    let mut number_width = 0;
+    for stat in stats {
+        if stat.binary {
+            let width = 14 + decimal_width(stat.added) + decimal_width(stat.deleted);
+            bin_width = bin_width.max(width);
+            // Change counts line up with "Bin".
+            number_width = 3;
+        } else {
+            max_change = max_change.max(stat.added + stat.deleted);
+        }
+    }
+    number_width = number_width.max(decimal_width(max_change));
+    let width = STAT_WIDTH.max(16 + 6 + number_width);
+
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = max_len;
+    if name_width + number_width + 6 + graph_width > width {
+        let graph_limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > graph_limit {
+            graph_width = graph_limit.max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for stat in stats {
+        let name = fit_name(&stat.name, name_width);
+        if stat.binary {
+            lines.push(format!(
+                " {:<name_width$} | {:>number_width$} {} -> {} bytes",
+                name, "Bin", stat.deleted, stat.added
+            ));
+            continue;
+        }
+        insertions += stat.added;
+        deletions += stat.deleted;
+        let (mut add, mut del) = (stat.added, stat.deleted);
+        if graph_width <= max_change {
+            let mut total = scale_linear(add + del, graph_width, max_change);
+            if total < 2 && add > 0 && del > 0 {
+                total = 2;
+            }
+            if add < del {
+                add = scale_linear(add, graph_width, max_change);
+                del = total - add;
+            } else {
+                del = scale_linear(del, graph_width, max_change);
+                add = total - del;
+            }
+        }
+        let changed = stat.added + stat.deleted;
+        lines.push(format!(
+            " {:<name_width$} | {:>number_width$}{}{}{}",
+            name,
+            changed,
+            if changed > 0 { " " } else { "" },
+            "+".repeat(add),
+            "-".repeat(del)
+        ));
+    }
+
+    let files = stats.len();
+    let mut total = format!(
+        " {} file{} changed",
+        files,
+        if files == 1 { "" } else { "s" }
+    );
+    if insertions > 0 || deletions == 0 {
+        let plural = if insertions == 1 { "" } else { "s" };
+        total.push_str(&format!(", {} insertion{}(+)", insertions, plural));
+    }
+    if deletions > 0 || insertions == 0 {
+        let plural = if deletions == 1 { "" } else { "s" };
+        total.push_str(&format!(", {} deletion{}(-)", deletions, plural));
+    }
+    lines.push(total);
+    lines
+}
+
+/*
+    A name too long for its column loses characters from the front,
+    then up to its next "/", and is shown with a "..." prefix.
+*/
+fn fit_name(name: &str, width: usize) -> String {
+    let len = name.chars().count();
+    if len <= width {
+        return name.to_string();
+    }
+    let keep = width.saturating_sub(3);
+    let tail: String = name.chars().skip(len - keep).collect();
+    let tail = match tail.find('/') {
+        Some(slash) => tail[slash..].to_string(),
+        None => tail,
+    };
+    format!("...{}", tail)
+}
+
+fn scale_linear(value: usize, width: usize, max_change: usize) -> usize {
+    if value == 0 {
+        0
+    } else {
+        1 + value * (width - 1) / max_change
+    }
+}
+
+fn decimal_width(mut value: usize) -> usize {
+    let mut width = 1;
+    while value >= 10 {
+        value /= 10;
+        width += 1;
+    }
+    width
+}
@@ -0,0 +1,76 @@
+use std::{io, process};
+
+use crate::command::{fatal, resolve_commit, resolve_revision};
+use crate::merge_base::History;
+use crate::object_id::ObjectId;
+use crate::repository::Repository;
+use crate::utils;
+
+const USAGE: &str = "usage: git merge-base [-a | --all] <commit> <commit>...
+   or: git merge-base [-a | --all] --octopus <commit>...
+   or: git merge-base --is-ancestor <commit> <commit>";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut octopus = false;
+    let mut is_ancestor = false;
+    let mut revisions = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-a" | "--all" => all = true,
+            "--octopus" => octopus = true,
+            "--is-ancestor" => is_ancestor = true,
+            other if other.starts_with('-') => fatal(format!("unknown option '{}'", other)),
+            revision => revisions.push(revision.to_string()),
+        }
+    }
+    if is_ancestor && all {
+        fatal("options '--is-ancestor' and '--all' cannot be used together");
+    }
+    if revisions.len() < if octopus { 1 } else { 2 } {
+        eprintln!("{}", USAGE);
+        process::exit(129);
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    let mut commits = Vec::new();
+    for revision in &revisions {
+        commits.push(commit_reference(&repo, revision)?);
+    }
+    let mut history = History::new(&repo.database)?;
+
+    if is_ancestor {
+        let [ancestor, descendant] = commits[..] else {
+            fatal("--is-ancestor takes exactly two commits");
+        };
+        if !history.is_ancestor(&ancestor, &descendant)? {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let bases = if octopus {
+        let bases = history.octopus_merge_bases(&commits)?;
+        history.reduce_heads(&bases)?
+    } else {
+        history.merge_bases(&commits[0], &commits[1..])?
+    };
+    if bases.is_empty() {
+        process::exit(1);
+    }
+    let shown = if all { bases.len() } else { 1 };
+    for base in &bases[..shown] {
+        println!("{}", base);
+    }
+    Ok(())
+}
+
+fn commit_reference(repo: &Repository, revision: &str) -> io::Result<ObjectId> {
+    if resolve_revision(repo, revision, None)?.is_none() {
+        fatal(format!("Not a valid object name {}", revision));
+    }
+    match resolve_commit(repo, revision)? {
+        Some(object_id) => Ok(object_id),
+        None => fatal(format!("Not a valid commit name {}", revision)),
+    }
+}
@@ -0,0 +1,60 @@
+use std::{fmt, io, process};
+
+use crate::blob::Kind;
+use crate::object_id::ObjectId;
+use crate::repository::Repository;
+use crate::revision::{Revision, RevisionError};
+
+pub mod add;
+pub mod branch;
+pub mod check_ignore;
+pub mod checkout;
+pub mod commit;
+pub mod diff;
+pub mod gc;
+pub mod init;
+pub mod log;
+pub mod merge;
+pub mod merge_base;
+pub mod pack_refs;
+pub mod reflog;
+pub mod repack;
+pub mod rev_parse;
+pub mod status;
+pub mod switch;
+pub mod update_ref;
+
+/*
+    Reports an unrecoverable error the way git does and exits with its status code.
+*/
+pub fn fatal(message: impl fmt::Display) -> ! {
+    eprintln!("fatal: {}", message);
+    process::exit(128);
+}
+
+/*
+    Resolves a revision for a command, peeled to kind if given. Revisions
+    that name nothing come back as None so the caller can die with its own
+    message; errors git reports before that are printed here, and errors
+    git treats as fatal in themselves end the process.
+*/
+pub fn resolve_revision(
+    repo: &Repository,
+    expression: &str,
+    kind: Option<Kind>,
+) -> io::Result<Option<ObjectId>> {
+    match Revision::new(repo, expression).resolve(kind) {
+        Ok(object_id) => Ok(Some(object_id)),
+        Err(RevisionError::Unknown(_)) => Ok(None),
+        Err(err) if err.is_reported_as_unknown() => {
+            eprintln!("error: {}", err);
+            Ok(None)
+        }
+        Err(RevisionError::Io(err)) => Err(err),
+        Err(err) => fatal(err),
+    }
+}
+
+pub fn resolve_commit(repo: &Repository, expression: &str) -> io::Result<Option<ObjectId>> {
+    resolve_revision(repo, expression, Some(Kind::Commit))
+}
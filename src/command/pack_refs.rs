@@ -0,0 +1,30 @@
+use std::io;
+
+use crate::command::fatal;
+use crate::refs::RefsError;
+use crate::repository::Repository;
+use crate::utils;
+
+/*
+    Moves loose refs into .git/packed-refs: tags and refs packed before,
+    or every ref with --all. Their loose files are removed unless
+    --no-prune is given.
+*/
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut prune = true;
+    for arg in args {
+        match arg.as_str() {
+            "--all" => all = true,
+            "--prune" => prune = true,
+            "--no-prune" => prune = false,
+            other => fatal(format!("unknown option '{}'", other)),
+        }
+    }
+    let repo = Repository::new(utils::get_root_path());
+    match repo.refs.pack_refs(&repo.database, all, prune) {
+        Ok(()) => Ok(()),
+        Err(RefsError::Io(err)) => Err(err),
+        Err(err) => fatal(err),
+    }
+}
@@ -0,0 +1,365 @@
+use std::{io, process};
+
+use chrono::Local;
+
+use crate::command::{fatal, resolve_revision};
+use crate::merge_base::History;
+use crate::object_id::ObjectId;
+use crate::refs::{ReflogEntry, RefsError, HEAD};
+use crate::repository::Repository;
+use crate::utils;
+
+const USAGE: &str = "usage: git reflog [show] [<ref>]
+   or: git reflog expire [--expire=<time>] [--expire-unreachable=<time>]
+                         [--rewrite] [--dry-run | -n] [--verbose] [--all | <refs>...]
+   or: git reflog delete [--rewrite] [--dry-run | -n] [--verbose] <ref>@{<specifier>}...";
+// How long entries are kept by default, and entries no longer reachable
+// from the tip of their ref: git's gc.reflogExpire and
+// gc.reflogExpireUnreachable defaults.
+const DEFAULT_EXPIRE_DAYS: i64 = 90;
+const DEFAULT_EXPIRE_UNREACHABLE_DAYS: i64 = 30;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("show") => show(&args[1..]),
+        Some("expire") => expire(&args[1..]),
+        Some("delete") => delete(&args[1..]),
+        Some("-h") => usage(),
+        _ => show(args),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(129);
+}
+
+/*
+    How expire and delete rewrite a reflog: with rewrite, each entry kept
+    takes the entry before it as its old value, so the log still chains;
+    verbose prints what happens to every entry.
+*/
+#[derive(Debug, Default)]
+struct PruneOptions {
+    rewrite: bool,
+    dry_run: bool,
+    verbose: bool,
+}
+
+impl PruneOptions {
+    fn parse_flag(&mut self, arg: &str) -> bool {
+        match arg {
+            "--rewrite" => self.rewrite = true,
+            "-n" | "--dry-run" => self.dry_run = true,
+            "--verbose" => self.verbose = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/*
+    Lists the reflog of a ref, newest first, as "<commit> <ref>@{<n>}: <message>".
+*/
+fn show(args: &[String]) -> io::Result<()> {
+    let mut name = None;
+    for arg in args {
+        match arg.as_str() {
+            other if other.starts_with('-') => fatal(format!("unrecognized argument: {}", other)),
+            other if name.is_none() => name = Some(other),
+            _ => usage(),
+        }
+    }
+    let name = name.unwrap_or(HEAD);
+
+    let repo = Repository::new(utils::get_root_path());
+    let Some(ref_name) = find_reflog(&repo, name) else {
+        if resolve_revision(&repo, name, None)?.is_some() {
+            return Ok(());
+        }
+        eprintln!(
+            "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.",
+            name
+        );
+        eprintln!("Use '--' to separate paths from revisions, like this:");
+        eprintln!("'git <command> [<revision>...] -- [<file>...]'");
+        process::exit(128);
+    };
+    let entries = repo.refs.read_reflog(&ref_name)?.unwrap_or_default();
+    for (n, entry) in entries.iter().rev().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            repo.database.short_object_id(&entry.new_object_id)?,
+            name,
+            n,
+            entry.message
+        );
+    }
+    Ok(())
+}
+
+/*
+    Prunes entries older than --expire, and entries older than
+    --expire-unreachable whose commits the ref no longer reaches.
+*/
+fn expire(args: &[String]) -> io::Result<()> {
+    let now = Local::now().timestamp();
+    let mut expire = now - DEFAULT_EXPIRE_DAYS * SECONDS_PER_DAY;
+    let mut expire_unreachable = now - DEFAULT_EXPIRE_UNREACHABLE_DAYS * SECONDS_PER_DAY;
+    let mut options = PruneOptions::default();
+    let mut all = false;
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if options.parse_flag(arg) {
+            continue;
+        }
+        let (option, value) = match arg.split_once('=') {
+            Some((option, value)) => (option, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        match option {
+            "--expire" | "--expire-unreachable" => {
+                let Some(value) = value.or_else(|| args.next().cloned()) else {
+                    fatal(format!("option `{}' requires a value", &option[2..]));
+                };
+                let Some(time) = parse_expiry(&value, now) else {
+                    fatal(format!(
+                        "invalid timestamp '{}' given to '{}'",
+                        value, option
+                    ));
+                };
+                if option == "--expire" {
+                    expire = time;
+                } else {
+                    expire_unreachable = time;
+                }
+            }
+            "--all" => all = true,
+            other if other.starts_with('-') => {
+                eprintln!("error: unknown option `{}'", other.trim_start_matches('-'));
+                usage();
+            }
+            _ => names.push(arg.clone()),
+        }
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    let mut ref_names = Vec::new();
+    if all {
+        for name in std::iter::once(HEAD.to_string()).chain(repo.refs.list_refs()?) {
+            if repo.refs.reflog_path(&name).is_file() {
+                ref_names.push(name);
+            }
+        }
+    }
+    let mut failed = false;
+    for name in &names {
+        match find_reflog(&repo, name) {
+            Some(ref_name) => ref_names.push(ref_name),
+            None => {
+                eprintln!("error: {} points nowhere!", name);
+                failed = true;
+            }
+        }
+    }
+
+    let mut history = History::new(&repo.database)?;
+    for ref_name in &ref_names {
+        let tips = reachability_tips(&repo, ref_name)?;
+        let entries = repo.refs.read_reflog(ref_name)?.unwrap_or_default();
+        let mut pruned = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let time = entry.committer.timestamp();
+            let prune = time < expire
+                || (time < expire_unreachable
+                    && (is_unreachable(&repo, &mut history, &entry.old_object_id, &tips)?
+                        || is_unreachable(&repo, &mut history, &entry.new_object_id, &tips)?));
+            pruned.push(prune);
+        }
+        prune_reflog(&repo, ref_name, entries, &pruned, &options)?;
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/*
+    Removes single entries, each named as <ref>@{<n>} the way rev-parse
+    reads them: n moves ago, counting from the newest entry.
+*/
+fn delete(args: &[String]) -> io::Result<()> {
+    let mut options = PruneOptions::default();
+    let mut specs = Vec::new();
+    for arg in args {
+        if options.parse_flag(arg) {
+            continue;
+        }
+        match arg.as_str() {
+            other if other.starts_with('-') => {
+                eprintln!("error: unknown option `{}'", other.trim_start_matches('-'));
+                usage();
+            }
+            spec => specs.push(spec),
+        }
+    }
+    if specs.is_empty() {
+        eprintln!("error: no reflog specified to delete");
+        process::exit(1);
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    let mut failed = false;
+    for spec in specs {
+        let Some((name, n)) = parse_reflog_spec(spec) else {
+            eprintln!("error: not a reflog: {}", spec);
+            failed = true;
+            continue;
+        };
+        let Some(ref_name) = find_reflog(&repo, name) else {
+            eprintln!("error: no reflog for '{}'", spec);
+            failed = true;
+            continue;
+        };
+        let entries = repo.refs.read_reflog(&ref_name)?.unwrap_or_default();
+        let target = entries.len().checked_sub(n + 1);
+        let pruned: Vec<bool> = (0..entries.len()).map(|i| Some(i) == target).collect();
+        prune_reflog(&repo, &ref_name, entries, &pruned, &options)?;
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/*
+    Writes back the entries of a reflog not marked in pruned.
+*/
+fn prune_reflog(
+    repo: &Repository,
+    ref_name: &str,
+    entries: Vec<ReflogEntry>,
+    pruned: &[bool],
+    options: &PruneOptions,
+) -> io::Result<()> {
+    let mut kept: Vec<ReflogEntry> = Vec::with_capacity(entries.len());
+    for (mut entry, &prune) in entries.into_iter().zip(pruned) {
+        if options.verbose {
+            match (prune, options.dry_run) {
+                (true, true) => println!("would prune {}", entry.message),
+                (true, false) => println!("prune {}", entry.message),
+                (false, _) => println!("keep {}", entry.message),
+            }
+        }
+        if prune {
+            continue;
+        }
+        if let Some(previous) = kept.last().filter(|_| options.rewrite) {
+            entry.old_object_id = previous.new_object_id;
+        }
+        kept.push(entry);
+    }
+    if options.dry_run {
+        return Ok(());
+    }
+    match repo.refs.write_reflog(ref_name, &kept) {
+        Ok(()) => Ok(()),
+        Err(RefsError::Io(err)) => Err(err),
+        Err(err) => fatal(err),
+    }
+}
+
+/*
+    The full name of the ref whose reflog name refers to, trying the same
+    places rev-parse looks for a ref, or None when none of them has a log.
+*/
+fn find_reflog(repo: &Repository, name: &str) -> Option<String> {
+    let candidates = [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+    ];
+    candidates
+        .into_iter()
+        .find(|candidate| repo.refs.reflog_path(candidate).is_file())
+}
+
+/*
+    Splits "<ref>@{<n>}" into the ref and n.
+*/
+fn parse_reflog_spec(spec: &str) -> Option<(&str, usize)> {
+    let (name, n) = spec.strip_suffix('}')?.split_once("@{")?;
+    Some((name, n.parse().ok()?))
+}
+
+/*
+    The commits that keep entries of a ref's reflog reachable: its own
+    tip, or for HEAD, which moves between branches, the tip of every ref.
+*/
+fn reachability_tips(repo: &Repository, ref_name: &str) -> io::Result<Vec<ObjectId>> {
+    let mut tips: Vec<ObjectId> = repo.refs.read_ref(ref_name)?.into_iter().collect();
+    if ref_name == HEAD {
+        for name in repo.refs.list_refs()? {
+            tips.extend(repo.refs.read_ref(&name)?);
+        }
+    }
+    Ok(tips)
+}
+
+/*
+    Whether a reflog value is a commit none of the tips reach any more.
+    The null ID of a ref's creation, and objects that are already gone,
+    are never counted as unreachable.
+*/
+fn is_unreachable(
+    repo: &Repository,
+    history: &mut History,
+    object_id: &ObjectId,
+    tips: &[ObjectId],
+) -> io::Result<bool> {
+    if *object_id == ObjectId::default() || !repo.database.contains(object_id)? {
+        return Ok(false);
+    }
+    for tip in tips {
+        if history.is_ancestor(object_id, tip)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/*
+    The times --expire understands: "never", "now", "all", seconds since
+    the epoch, and relative times like "2.weeks.ago" or "3 days ago".
+*/
+fn parse_expiry(value: &str, now: i64) -> Option<i64> {
+    match value {
+        "never" | "false" => return Some(0),
+        "now" => return Some(now),
+        "all" => return Some(i64::MAX),
+        _ => {}
+    }
+    if let Ok(timestamp) = value.parse() {
+        return Some(timestamp);
+    }
+    let words: Vec<&str> = value.split(['.', ' ']).collect();
+    let [count, unit, "ago"] = words[..] else {
+        return None;
+    };
+    let count: i64 = count.parse().ok()?;
+    let seconds = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => SECONDS_PER_DAY,
+        "week" => 7 * SECONDS_PER_DAY,
+        "month" => 30 * SECONDS_PER_DAY,
+        "year" => 365 * SECONDS_PER_DAY,
+        _ => return None,
+    };
+    Some(now - count * seconds)
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::blob::Kind;
+use crate::command::fatal;
+use crate::object_id::ObjectId;
+use crate::pack_writer::PackWriter;
+use crate::refs::HEAD;
+use crate::repository::Repository;
+use crate::utils;
+
+const ORIG_HEAD: &str = "ORIG_HEAD";
+// Files git keeps next to a pack, all removed along with it.
+const PACK_EXTENSIONS: [&str; 6] = ["pack", "idx", "rev", "bitmap", "promisor", "mtimes"];
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut delete_redundant = false;
+    for arg in args {
+        match arg.as_str() {
+            "-a" => all = true,
+            "-d" => delete_redundant = true,
+            "-ad" | "-da" => {
+                all = true;
+                delete_redundant = true;
+            }
+            "-q" | "--quiet" => {}
+            other => fatal(format!("unknown option '{}'", other)),
+        }
+    }
+
+    let mut repo = Repository::new(utils::get_root_path());
+    if !repack(&mut repo, all, delete_redundant, false)? {
+        println!("Nothing new to pack.");
+    }
+    Ok(())
+}
+
+/*
+    Packs the objects reachable from refs, HEAD, reflogs and the index:
+    all of them with all, otherwise only those not packed yet. keep_packed
+    also carries over every object from the existing packs, reachable or
+    not. With delete_redundant, the old packs are deleted when everything
+    was repacked, and loose objects that are now packed are removed.
+    Returns whether a pack was written.
+*/
+pub fn repack(
+    repo: &mut Repository,
+    all: bool,
+    delete_redundant: bool,
+    keep_packed: bool,
+) -> io::Result<bool> {
+    repo.index.load()?;
+    let old_packs: Vec<PathBuf> = repo
+        .database
+        .packs()?
+        .iter()
+        .map(|pack| pack.path().to_path_buf())
+        .collect();
+
+    let mut writer = PackWriter::new(&repo.database, !all);
+    let mut commits = Vec::new();
+    for tip in reachability_tips(repo)? {
+        // A tag is packed along with whatever it points at, which may be
+        // another tag.
+        let mut object_id = tip;
+        loop {
+            match repo.database.read_object(&object_id)?.0 {
+                Kind::Commit => commits.push(object_id),
+                Kind::Tree => writer.add_tree(&object_id, "")?,
+                Kind::Blob => writer.add_object(&object_id, "")?,
+                Kind::Tag => {
+                    writer.add_object(&object_id, "")?;
+                    object_id = repo.database.load_tag(&object_id)?.target_object_id;
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+    writer.add_history(&commits)?;
+    for entry in repo.index.entries() {
+        writer.add_object(&entry.object_id, &entry.path)?;
+    }
+    if keep_packed {
+        for pack in repo.database.packs()? {
+            for position in 0..pack.index().len() {
+                writer.add_object(&pack.index().object_id(position), "")?;
+            }
+        }
+    }
+
+    let new_pack = if writer.is_empty() {
+        None
+    } else {
+        Some(writer.write(&repo.database.path_buf.join("pack"))?)
+    };
+    if delete_redundant {
+        if all {
+            for old_pack in old_packs
+                .iter()
+                .filter(|path| Some(*path) != new_pack.as_ref())
+            {
+                remove_pack(old_pack)?;
+            }
+        }
+        repo.database.reload_packs();
+        repo.database.prune_packed()?;
+    }
+    Ok(new_pack.is_some())
+}
+
+/*
+    The objects that keep history alive: what HEAD, ORIG_HEAD, a pending
+    merge and every ref point at, and every value in their reflogs.
+*/
+fn reachability_tips(repo: &Repository) -> io::Result<Vec<ObjectId>> {
+    let mut names = vec![HEAD.to_string(), ORIG_HEAD.to_string()];
+    names.extend(repo.refs.list_refs()?);
+    let mut tips = Vec::new();
+    for name in &names {
+        tips.extend(repo.refs.read_ref(name)?);
+        for entry in repo.refs.read_reflog(name)?.unwrap_or_default() {
+            tips.push(entry.old_object_id);
+            tips.push(entry.new_object_id);
+        }
+    }
+    tips.extend(repo.pending_commit().merge_object_id()?);
+    tips.retain(|tip| *tip != ObjectId::default());
+    tips.sort();
+    tips.dedup();
+    Ok(tips)
+}
+
+fn remove_pack(pack_path: &Path) -> io::Result<()> {
+    for extension in PACK_EXTENSIONS {
+        match fs::remove_file(pack_path.with_extension(extension)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
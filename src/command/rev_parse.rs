@@ -0,0 +1,83 @@
+use std::{io, process};
+
+use crate::command::fatal;
+use crate::object_id::ObjectId;
+use crate::repository::Repository;
+use crate::revision::{Revision, RevisionError};
+use crate::utils;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut verify = false;
+    let mut quiet = false;
+    let mut short = None;
+    let mut revisions = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--verify" => verify = true,
+            "-q" | "--quiet" => quiet = true,
+            "--short" => short = Some(ObjectId::SHORT_LEN),
+            other => {
+                if let Some(len) = other.strip_prefix("--short=") {
+                    match len.parse::<usize>() {
+                        Ok(len) => short = Some(len),
+                        Err(_) => fatal(format!("'{}': not an integer", len)),
+                    }
+                } else if other.starts_with('-') {
+                    fatal(format!("unknown option '{}'", other));
+                } else {
+                    revisions.push(other.to_string());
+                }
+            }
+        }
+    }
+    // Like git, asking for an abbreviation implies --verify.
+    if short.is_some() {
+        verify = true;
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    let print = |object_id: ObjectId| -> io::Result<()> {
+        match short {
+            Some(len) => println!("{}", repo.database.abbreviate(&object_id, len)?),
+            None => println!("{}", object_id),
+        }
+        Ok(())
+    };
+
+    if verify {
+        let resolved = match revisions.as_slice() {
+            [revision] => Revision::new(&repo, revision).resolve(None),
+            _ => Err(RevisionError::Unknown(String::new())),
+        };
+        match resolved {
+            Ok(object_id) => print(object_id)?,
+            Err(RevisionError::Io(err)) => return Err(err),
+            Err(err @ RevisionError::ReflogTooShort { .. }) => fatal(err),
+            Err(err) => {
+                // --quiet hides ambiguity hints but, like git, not type errors.
+                let hidden = quiet && matches!(err, RevisionError::AmbiguousObjectId(_));
+                if err.is_reported_as_unknown() && !hidden {
+                    eprintln!("error: {}", err);
+                }
+                if quiet {
+                    process::exit(1);
+                }
+                fatal("Needed a single revision");
+            }
+        }
+        return Ok(());
+    }
+
+    for revision in &revisions {
+        match Revision::new(&repo, revision).resolve(None) {
+            Ok(object_id) => print(object_id)?,
+            Err(RevisionError::Io(err)) => return Err(err),
+            Err(err) if err.is_reported_as_unknown() => {
+                eprintln!("error: {}", err);
+                fatal(RevisionError::Unknown(revision.clone()));
+            }
+            Err(err) => fatal(err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,320 @@
+use std::{collections::BTreeMap, io};
+
+use crate::command::fatal;
+use crate::object_id::ObjectId;
+use crate::repository::Repository;
+use crate::status::{ChangeType, Status};
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Long,
+    Short,
+    PorcelainV2,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut format = Format::Long;
+    for arg in args {
+        format = match arg.as_str() {
+            "--long" => Format::Long,
+            "-s" | "--short" | "--porcelain" | "--porcelain=v1" => Format::Short,
+            "--porcelain=v2" => Format::PorcelainV2,
+            other if other.starts_with("--porcelain=") => {
+                fatal(format!(
+                    "unsupported porcelain version '{}'",
+                    &other["--porcelain=".len()..]
+                ));
+            }
+            other => fatal(format!("unknown option '{}'", other)),
+        };
+    }
+
+    let mut repo = Repository::new(utils::get_root_path());
+    // Refreshing stat data is an optimisation, so status still runs
+    // read-only when another process holds the index lock.
+    let locked = repo.index.load_for_update().is_ok();
+    if !locked {
+        repo.index.load()?;
+    }
+    let mut status = Status::new(&mut repo)?;
+    if locked {
+        repo.index.write_updates()?;
+    }
+    status.detect_index_renames(&repo)?;
+
+    match format {
+        Format::Long => print_long_format(&repo, &status)?,
+        Format::Short => print_short_format(&status),
+        Format::PorcelainV2 => print_porcelain_v2_format(&repo, &status),
+    }
+    Ok(())
+}
+
+fn print_long_format(repo: &Repository, status: &Status) -> io::Result<()> {
+    let head = repo.refs.read_head()?;
+    match repo.refs.current_branch()? {
+        Some(branch) => println!("On branch {}", branch),
+        None => println!("Not currently on any branch."),
+    }
+    if head.is_none() {
+        println!("\nNo commits yet\n");
+    }
+    let merging = repo.pending_commit().in_progress();
+    if merging {
+        print_merge_state(status);
+    }
+
+    if !status.index_changes.is_empty() {
+        println!("Changes to be committed:");
+        // A merge's changes cannot be unstaged, so git gives no hint then.
+        if !merging {
+            if head.is_some() {
+                println!("  (use \"git restore --staged <file>...\" to unstage)");
+            } else {
+                println!("  (use \"git rm --cached <file>...\" to unstage)");
+            }
+        }
+        print_changes(&status.index_changes, &status.renames);
+    }
+
+    if !status.conflicts.is_empty() {
+        print_conflicts(&status.conflicts);
+    }
+
+    if !status.workspace_changes.is_empty() {
+        println!("Changes not staged for commit:");
+        if status
+            .workspace_changes
+            .values()
+            .any(|change| *change == ChangeType::Deleted)
+        {
+            println!("  (use \"git add/rm <file>...\" to update what will be committed)");
+        } else {
+            println!("  (use \"git add <file>...\" to update what will be committed)");
+        }
+        println!("  (use \"git restore <file>...\" to discard changes in working directory)");
+        // Renames are between HEAD and the index; an edited renamed file
+        // is shown under its new name alone.
+        print_changes(&status.workspace_changes, &BTreeMap::new());
+    }
+
+    if !status.untracked.is_empty() {
+        println!("Untracked files:");
+        println!("  (use \"git add <file>...\" to include in what will be committed)");
+        for path in &status.untracked {
+            println!("\t{}", path);
+        }
+        println!();
+    }
+
+    if !status.index_changes.is_empty() {
+        return Ok(());
+    }
+    if !status.workspace_changes.is_empty() || !status.conflicts.is_empty() {
+        println!("no changes added to commit (use \"git add\" and/or \"git commit -a\")");
+    } else if !status.untracked.is_empty() {
+        println!("nothing added to commit but untracked files present (use \"git add\" to track)");
+    } else if head.is_none() {
+        println!("nothing to commit (create/copy files and use \"git add\" to track)");
+    } else {
+        println!("nothing to commit, working tree clean");
+    }
+    Ok(())
+}
+
+fn print_merge_state(status: &Status) {
+    if status.conflicts.is_empty() {
+        println!("All conflicts fixed but you are still merging.");
+        println!("  (use \"git commit\" to conclude merge)");
+    } else {
+        println!("You have unmerged paths.");
+        println!("  (fix conflicts and run \"git commit\")");
+        println!("  (use \"git merge --abort\" to abort the merge)");
+    }
+    println!();
+}
+
+/*
+    The stages of an unmerged path as a mask, 1 for base, 2 for ours and
+    4 for theirs, like git's stagemask.
+*/
+fn stage_mask(stages: &[u8]) -> u8 {
+    stages.iter().fold(0, |mask, stage| mask | 1 << (stage - 1))
+}
+
+fn print_conflicts(conflicts: &BTreeMap<String, Vec<u8>>) {
+    println!("Unmerged paths:");
+    let masks: Vec<u8> = conflicts
+        .values()
+        .map(|stages| stage_mask(stages))
+        .collect();
+    if masks.iter().all(|&mask| mask == 1) {
+        println!("  (use \"git rm <file>...\" to mark resolution)");
+    } else if masks
+        .iter()
+        .any(|&mask| mask == 1 || mask == 3 || mask == 5)
+    {
+        println!("  (use \"git add/rm <file>...\" as appropriate to mark resolution)");
+    } else {
+        println!("  (use \"git add <file>...\" to mark resolution)");
+    }
+    for (path, stages) in conflicts {
+        let label = match stage_mask(stages) {
+            1 => "both deleted:",
+            2 => "added by us:",
+            3 => "deleted by them:",
+            4 => "added by them:",
+            5 => "deleted by us:",
+            6 => "both added:",
+            _ => "both modified:",
+        };
+        println!("\t{:<17}{}", label, path);
+    }
+    println!();
+}
+
+/*
+    The two letter short status of an unmerged path.
+*/
+fn conflict_code(stages: &[u8]) -> &'static str {
+    match stage_mask(stages) {
+        1 => "DD",
+        2 => "AU",
+        3 => "UD",
+        4 => "UA",
+        5 => "DU",
+        6 => "AA",
+        _ => "UU",
+    }
+}
+
+fn print_changes(
+    changes: &BTreeMap<String, ChangeType>,
+    renames: &BTreeMap<String, (String, u32)>,
+) {
+    for (path, change) in changes {
+        let label = match change {
+            ChangeType::Added => "new file:",
+            ChangeType::Deleted => "deleted:",
+            ChangeType::Modified => "modified:",
+            ChangeType::Renamed => "renamed:",
+        };
+        println!("\t{:<12}{}", label, display_path(path, renames));
+    }
+    println!();
+}
+
+fn short_code(change: Option<&ChangeType>, unchanged: char) -> char {
+    match change {
+        Some(ChangeType::Added) => 'A',
+        Some(ChangeType::Deleted) => 'D',
+        Some(ChangeType::Modified) => 'M',
+        Some(ChangeType::Renamed) => 'R',
+        None => unchanged,
+    }
+}
+
+/*
+    A renamed path is shown as "old -> new".
+*/
+fn display_path(path: &str, renames: &BTreeMap<String, (String, u32)>) -> String {
+    match renames.get(path) {
+        Some((old_path, _)) => format!("{} -> {}", old_path, path),
+        None => path.to_string(),
+    }
+}
+
+fn print_short_format(status: &Status) {
+    for path in &status.changed {
+        if let Some(stages) = status.conflicts.get(path) {
+            println!("{} {}", conflict_code(stages), path);
+            continue;
+        }
+        println!(
+            "{}{} {}",
+            short_code(status.index_changes.get(path), ' '),
+            short_code(status.workspace_changes.get(path), ' '),
+            display_path(path, &status.renames)
+        );
+    }
+    for path in &status.untracked {
+        println!("?? {}", path);
+    }
+}
+
+/*
+    "1 XY sub mH mI mW hH hI path" for every changed tracked path, or
+    "2 XY sub mH mI mW hH hI Rscore path<tab>origPath" for renames, then
+    "u XY sub m1 m2 m3 mW h1 h2 h3 path" for unmerged paths and
+    "? path" for untracked ones. Renamed files take their HEAD side from
+    the old path.
+*/
+fn print_porcelain_v2_format(repo: &Repository, status: &Status) {
+    for path in &status.changed {
+        if status.conflicts.contains_key(path) {
+            continue;
+        }
+        let rename = status.renames.get(path);
+        let head_path = rename.map_or(path.as_str(), |(old_path, _)| old_path.as_str());
+        let head_entry = status.head_tree.get(head_path);
+        let index_entry = repo.index.entry_for_path(path);
+        let head_mode = head_entry.map_or(0, |entry| entry.mode);
+        let index_mode = index_entry.map_or(0, |entry| entry.mode);
+        let workspace_mode = status.workspace_mode(path).unwrap_or(0);
+        let head_oid = status.head_object_id(head_path).unwrap_or_default();
+        let index_oid = index_entry.map_or(ObjectId::default(), |entry| entry.object_id);
+        let fields = format!(
+            "{}{} N... {:06o} {:06o} {:06o} {} {}",
+            short_code(status.index_changes.get(path), '.'),
+            short_code(status.workspace_changes.get(path), '.'),
+            head_mode,
+            index_mode,
+            workspace_mode,
+            head_oid,
+            index_oid,
+        );
+        match rename {
+            Some((old_path, similarity)) => {
+                println!("2 {} R{} {}\t{}", fields, similarity, path, old_path)
+            }
+            None => println!("1 {} {}", fields, path),
+        }
+    }
+    for (path, stages) in &status.conflicts {
+        print_porcelain_v2_conflict(repo, status, path, stages);
+    }
+    for path in &status.untracked {
+        println!("? {}", path);
+    }
+}
+
+fn print_porcelain_v2_conflict(repo: &Repository, status: &Status, path: &str, stages: &[u8]) {
+    let entries: Vec<_> = (1..=3)
+        .map(|stage| {
+            repo.index
+                .entries()
+                .find(|entry| entry.path == path && entry.stage() == stage)
+        })
+        .collect();
+    let modes: Vec<String> = entries
+        .iter()
+        .map(|entry| format!("{:06o}", entry.map_or(0, |entry| entry.mode)))
+        .collect();
+    let object_ids: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            entry
+                .map_or(ObjectId::default(), |entry| entry.object_id)
+                .to_string()
+        })
+        .collect();
+    println!(
+        "u {} N... {} {:06o} {} {}",
+        conflict_code(stages),
+        modes.join(" "),
+        status.workspace_mode(path).unwrap_or(0),
+        object_ids.join(" "),
+        path
+    );
+}
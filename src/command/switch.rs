@@ -0,0 +1,59 @@
+use std::{io, process};
+
+use crate::command::checkout::{self, SwitchOptions};
+use crate::command::{fatal, resolve_commit};
+use crate::repository::Repository;
+use crate::utils;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut options = SwitchOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "-C" | "--create" | "--force-create" => {
+                let Some(name) = args.next() else {
+                    fatal(format!(
+                        "option `{}' requires a value",
+                        arg.trim_start_matches('-')
+                    ));
+                };
+                options.new_branch = Some(name.clone());
+                options.force_create = arg == "-C" || arg == "--force-create";
+            }
+            "-d" | "--detach" => options.detach = true,
+            "-q" | "--quiet" => options.quiet = true,
+            other if other.starts_with('-') => fatal(format!("unknown option '{}'", other)),
+            target if options.target.is_none() => options.target = Some(target.to_string()),
+            _ => fatal("only one reference expected"),
+        }
+    }
+
+    let mut repo = Repository::new(utils::get_root_path());
+    if repo.pending_commit().in_progress() {
+        eprintln!("fatal: cannot switch branch while merging");
+        eprintln!("Consider \"git merge --quit\" or \"git worktree add\".");
+        process::exit(128);
+    }
+    let Some(target) = options.target.as_deref() else {
+        if options.new_branch.is_none() && !options.detach {
+            fatal("missing branch or commit argument");
+        }
+        return checkout::switch_branches(&mut repo, &options);
+    };
+    if options.new_branch.is_some() && resolve_commit(&repo, target)?.is_none() {
+        fatal(format!("invalid reference: {}", target));
+    }
+    // Unlike checkout, switch only detaches HEAD when asked to.
+    if options.new_branch.is_none()
+        && !options.detach
+        && !checkout::is_branch(&repo, target)?
+        && resolve_commit(&repo, target)?.is_some()
+    {
+        eprintln!("fatal: a branch is expected, got commit '{}'", target);
+        eprintln!(
+            "hint: If you want to detach HEAD at the commit, try again with the --detach option."
+        );
+        process::exit(128);
+    }
+    checkout::switch_branches(&mut repo, &options)
+}
@@ -0,0 +1,290 @@
+use std::{fmt, io, process};
+
+use crate::command::{fatal, resolve_revision};
+use crate::object_id::ObjectId;
+use crate::refs::{self, RefTransaction, RefUpdate, RefsError};
+use crate::repository::Repository;
+use crate::utils;
+
+const USAGE: &str = "usage: git update-ref [<options>] -d <refname> [<old-val>]
+   or: git update-ref [<options>]    <refname> <new-val> [<old-val>]
+   or: git update-ref [<options>] --stdin";
+
+/*
+    Where a stream of --stdin commands stands. Commands outside start and
+    commit form one transaction committed at the end of input; one opened
+    with start and never committed is aborted instead.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum StdinState {
+    Open,
+    Started,
+    Prepared,
+    Closed,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut message = String::new();
+    let mut delete = false;
+    let mut no_deref = false;
+    let mut stdin = false;
+    let mut values = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => match args.next() {
+                Some(reason) => message = reason.clone(),
+                None => fatal("switch `m' requires a value"),
+            },
+            "-d" => delete = true,
+            "--no-deref" => no_deref = true,
+            "--stdin" => stdin = true,
+            other if other.starts_with('-') => {
+                eprintln!("error: unknown option `{}'", other.trim_start_matches('-'));
+                usage();
+            }
+            value => values.push(value.to_string()),
+        }
+    }
+
+    let repo = Repository::new(utils::get_root_path());
+    if stdin {
+        if delete || !values.is_empty() {
+            usage();
+        }
+        return update_refs_stdin(&repo, &message);
+    }
+
+    let (name, new, old) = match (delete, &values[..]) {
+        (true, [name]) => (name, ObjectId::default(), None),
+        (true, [name, old]) => (name, ObjectId::default(), Some(old)),
+        (false, [name, new]) => (name, object_id_arg(&repo, new)?, None),
+        (false, [name, new, old]) => (name, object_id_arg(&repo, new)?, Some(old)),
+        _ => usage(),
+    };
+    let old_object_id = match old {
+        Some(old) => Some(object_id_arg(&repo, old)?),
+        None => None,
+    };
+    if new != ObjectId::default() {
+        check_exists(&repo, name, &new)?;
+    }
+    let mut transaction = repo.refs.transaction();
+    let update = RefUpdate {
+        name: name.clone(),
+        new_object_id: Some(new),
+        old_object_id,
+        no_deref,
+        message,
+    };
+    check(transaction.queue(update).and_then(|_| transaction.commit()))
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(129);
+}
+
+/*
+    Reads update, create, delete, verify and option commands from stdin,
+    one per line, along with start, prepare, commit and abort to control
+    the transaction they go into.
+*/
+fn update_refs_stdin(repo: &Repository, message: &str) -> io::Result<()> {
+    let mut transaction = repo.refs.transaction();
+    let mut state = StdinState::Open;
+    let mut no_deref = false;
+    for line in io::stdin().lines() {
+        let line = line?;
+        let (command, args) = line.split_once(' ').unwrap_or((&line, ""));
+        let command_state = match command {
+            "update" | "create" | "delete" | "verify" | "option" => StdinState::Open,
+            "start" => StdinState::Started,
+            "prepare" => StdinState::Prepared,
+            "commit" | "abort" => StdinState::Closed,
+            _ => die(&mut transaction, format!("unknown command: {}", line)),
+        };
+        match state {
+            StdinState::Open | StdinState::Started => {
+                if state == StdinState::Started && command_state == StdinState::Started {
+                    die(&mut transaction, "cannot restart ongoing transaction");
+                }
+                // Queued updates go into a transaction started after them.
+                state = state.max(command_state);
+            }
+            StdinState::Prepared => {
+                if command_state != StdinState::Closed {
+                    die(&mut transaction, RefsError::TransactionPrepared);
+                }
+                state = command_state;
+            }
+            StdinState::Closed => {
+                if command_state != StdinState::Started {
+                    fatal(RefsError::TransactionClosed);
+                }
+                state = command_state;
+                transaction = repo.refs.transaction();
+            }
+        }
+
+        if command_state != StdinState::Open {
+            if !args.is_empty() {
+                die(&mut transaction, format!("unknown command: {}", line));
+            }
+            match command {
+                "prepare" => check(transaction.prepare())?,
+                "commit" => check(transaction.commit())?,
+                "abort" => transaction.abort(),
+                _ => {}
+            }
+            println!("{}: ok", command);
+            continue;
+        }
+        if command == "option" {
+            match args {
+                "no-deref" => no_deref = true,
+                _ => fatal(format!("option unknown: {}", args)),
+            }
+            continue;
+        }
+        let update = parse_update(repo, command, args, no_deref, message)?;
+        no_deref = false;
+        check(transaction.queue(update))?;
+    }
+
+    match state {
+        StdinState::Open => check(transaction.commit()),
+        StdinState::Started | StdinState::Prepared => {
+            transaction.abort();
+            Ok(())
+        }
+        StdinState::Closed => Ok(()),
+    }
+}
+
+/*
+    Parses the arguments of one command: "update <ref> <new> [<old>]",
+    "create <ref> <new>", "delete <ref> [<old>]" or "verify <ref> [<old>]".
+    A verify without an old value checks that the ref does not exist.
+*/
+fn parse_update(
+    repo: &Repository,
+    command: &str,
+    args: &str,
+    no_deref: bool,
+    message: &str,
+) -> io::Result<RefUpdate> {
+    let mut fields = args.split(' ');
+    let name = match fields.next() {
+        Some(name) if !name.is_empty() => name,
+        _ => fatal(format!("{}: missing <ref>", command)),
+    };
+    if !refs::is_valid_ref_name(name) {
+        fatal(format!("invalid ref format: {}", name));
+    }
+    let mut value = |field: &str| -> io::Result<Option<ObjectId>> {
+        match fields.next() {
+            Some(arg) => match parse_object_id(repo, arg)? {
+                Some(object_id) => Ok(Some(object_id)),
+                None => fatal(format!(
+                    "{} {}: invalid <{}>: {}",
+                    command, name, field, arg
+                )),
+            },
+            None => Ok(None),
+        }
+    };
+
+    let zero = ObjectId::default();
+    let (new_object_id, old_object_id) = match command {
+        "update" => {
+            let Some(new) = value("newvalue")? else {
+                fatal(format!("update {}: missing <newvalue>", name));
+            };
+            (Some(new), value("oldvalue")?)
+        }
+        "create" => {
+            let Some(new) = value("newvalue")? else {
+                fatal(format!("create {}: missing <newvalue>", name));
+            };
+            if new == zero {
+                fatal(format!("create {}: zero <newvalue>", name));
+            }
+            (Some(new), Some(zero))
+        }
+        "delete" => {
+            let old = value("oldvalue")?;
+            if old == Some(zero) {
+                fatal(format!("delete {}: zero <oldvalue>", name));
+            }
+            (Some(zero), old)
+        }
+        _ => (None, Some(value("oldvalue")?.unwrap_or(zero))),
+    };
+    let extra: Vec<&str> = fields.collect();
+    if !extra.is_empty() {
+        fatal(format!(
+            "{} {}: extra input: {}",
+            command,
+            name,
+            extra.join(" ")
+        ));
+    }
+    if let Some(new) = new_object_id.filter(|new| *new != zero) {
+        check_exists(repo, name, &new)?;
+    }
+    Ok(RefUpdate {
+        name: name.to_string(),
+        new_object_id,
+        old_object_id,
+        no_deref,
+        message: message.to_string(),
+    })
+}
+
+/*
+    A value on the command line: as with --stdin, a full object ID is
+    taken as given, anything else is resolved as a revision.
+*/
+fn object_id_arg(repo: &Repository, arg: &str) -> io::Result<ObjectId> {
+    match parse_object_id(repo, arg)? {
+        Some(object_id) => Ok(object_id),
+        None => fatal(format!("{}: not a valid SHA1", arg)),
+    }
+}
+
+fn parse_object_id(repo: &Repository, arg: &str) -> io::Result<Option<ObjectId>> {
+    if arg.len() == ObjectId::HEX_LEN {
+        if let Ok(object_id) = ObjectId::from_hex(arg) {
+            return Ok(Some(object_id));
+        }
+    }
+    resolve_revision(repo, arg, None)
+}
+
+fn check_exists(repo: &Repository, name: &str, object_id: &ObjectId) -> io::Result<()> {
+    if !repo.database.contains(object_id)? {
+        fatal(format!(
+            "cannot update ref '{}': trying to write ref '{}' with nonexistent object {}",
+            name, name, object_id
+        ));
+    }
+    Ok(())
+}
+
+/*
+    fatal exits without running destructors, so the locks of a prepared
+    transaction are released first.
+*/
+fn die(transaction: &mut RefTransaction, message: impl fmt::Display) -> ! {
+    transaction.abort();
+    fatal(message)
+}
+
+fn check(result: Result<(), RefsError>) -> io::Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(RefsError::Io(err)) => Err(err),
+        Err(err) => fatal(err),
+    }
+}
@@ -1,51 +1,107 @@
+use std::io;
+
 use crate::author::Author;
+use crate::object_id::ObjectId;
 use crate::traits::Object;
 use crate::utils;
 
 #[derive(Debug)]
 pub struct Commit {
-    pub parent: String,
+    pub parents: Vec<ObjectId>,
     pub author: Author,
+    pub committer: Author,
     pub message: String,
-    pub object_id: String,
-    pub tree_object_id: String,
+    pub object_id: Option<ObjectId>,
+    pub tree_object_id: ObjectId,
 }
 
 impl Commit {
-    pub fn new(parent: String, tree_object_id: String, author: Author, message: String) -> Self {
+    pub fn new(
+        parents: Vec<ObjectId>,
+        tree_object_id: ObjectId,
+        author: Author,
+        message: String,
+    ) -> Self {
         Commit {
-            parent,
+            parents,
+            committer: author.clone(),
             author,
             message,
             tree_object_id,
-            object_id: String::from(""),
+            object_id: None,
+        }
+    }
+
+    /*
+        Parses the body of a commit object: header lines up to the first blank
+        line, followed by the message. Unknown headers (gpgsig, encoding, ...)
+        and their continuation lines are skipped.
+    */
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let content = String::from_utf8_lossy(data);
+        let (headers, message) = match content.find("\n\n") {
+            Some(index) => (&content[..index], &content[index + 2..]),
+            None => (content.trim_end_matches('\n'), ""),
+        };
+
+        let mut tree_object_id = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        for line in headers.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "tree" => tree_object_id = Some(parse_object_id(value)?),
+                "parent" => parents.push(parse_object_id(value)?),
+                "author" => author = Author::parse(value),
+                "committer" => committer = Author::parse(value),
+                _ => {}
+            }
         }
+
+        Ok(Commit {
+            parents,
+            author: author.ok_or_else(|| utils::invalid_data("commit is missing an author"))?,
+            committer: committer
+                .ok_or_else(|| utils::invalid_data("commit is missing a committer"))?,
+            message: message.to_string(),
+            object_id: None,
+            tree_object_id: tree_object_id
+                .ok_or_else(|| utils::invalid_data("commit is missing a tree"))?,
+        })
     }
+
+    pub fn parent(&self) -> Option<ObjectId> {
+        self.parents.first().copied()
+    }
+
+    pub fn title_line(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+fn parse_object_id(hex: &str) -> io::Result<ObjectId> {
+    ObjectId::from_hex(hex).map_err(|err| utils::invalid_data(&err.to_string()))
 }
 
 impl Object for Commit {
-    fn to_string(&self) -> String {
-        let u8 = self.tree_object_id.as_bytes();
-        let mut parent = String::from("");
-        if !self.parent.is_empty() {
-            parent = format!("parent {}\n", self.parent.clone());
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut headers = format!("tree {}\n", self.tree_object_id);
+        for parent in &self.parents {
+            headers.push_str(&format!("parent {}\n", parent));
         }
         let content_str = format!(
-            "tree {}\n{}author {}\ncommitter {}\n{}",
-            utils::u8_to_hex_str(u8.to_vec()),
-            parent,
-            self.author,
-            self.author,
-            self.message
+            "{}author {}\ncommitter {}\n\n{}",
+            headers, self.author, self.committer, self.message
         );
-        format!("commit {}\0{}", content_str.bytes().len(), content_str)
+        format!("commit {}\0{}", content_str.len(), content_str).into_bytes()
     }
 
-    fn get_object_id(&self) -> String {
-        self.object_id.clone()
+    fn get_object_id(&self) -> Option<ObjectId> {
+        self.object_id
     }
 
-    fn set_object_id(&mut self, object_id: String) {
-        self.object_id = object_id;
+    fn set_object_id(&mut self, object_id: ObjectId) {
+        self.object_id = Some(object_id);
     }
 }
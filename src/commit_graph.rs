@@ -0,0 +1,197 @@
+use std::{fs, io, path::Path};
+
+use crate::object_id::ObjectId;
+use crate::utils;
+
+const SIGNATURE: &[u8] = b"CGPH";
+const HEADER_LEN: usize = 8;
+const CHUNK_ENTRY_LEN: usize = 12;
+const FANOUT_ID: &[u8] = b"OIDF";
+const LOOKUP_ID: &[u8] = b"OIDL";
+const DATA_ID: &[u8] = b"CDAT";
+const EXTRA_EDGES_ID: &[u8] = b"EDGE";
+// A tree ID, two parent positions and the generation and date.
+const DATA_LEN: usize = ObjectId::LEN + 16;
+const NO_PARENT: u32 = 0x7000_0000;
+// Set on the second parent when the commit has more than two, and on
+// the last parent of each list in the extra edges chunk.
+const EDGE_FLAG: u32 = 0x8000_0000;
+
+/*
+    A commit as recorded in the commit-graph file. generation is the
+    topological level: one more than the highest level among the parents,
+    so a commit can only reach commits with a lower one.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+    pub parents: Vec<ObjectId>,
+    pub date: i64,
+    pub generation: u32,
+}
+
+/*
+    The commit-graph file git writes to .git/objects/info, which stores
+    the parents, date and generation of each commit so history can be
+    walked without inflating commit objects. Only a single graph file is
+    read; split graph chains are ignored.
+*/
+pub struct CommitGraph {
+    data: Vec<u8>,
+    fanout: usize,
+    lookup: usize,
+    commit_data: usize,
+    extra_edges: Option<usize>,
+    count: usize,
+}
+
+impl CommitGraph {
+    /*
+        Reads the graph of the object database at objects_path, None if
+        git has not written one.
+    */
+    pub fn load(objects_path: &Path) -> io::Result<Option<Self>> {
+        match fs::read(objects_path.join("info").join("commit-graph")) {
+            Ok(data) => Self::parse(data).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<Self> {
+        if data.len() < HEADER_LEN || &data[..4] != SIGNATURE {
+            return Err(utils::invalid_data("commit-graph signature is missing"));
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err(utils::invalid_data(&format!(
+                "unsupported commit-graph version {} with hash version {}",
+                data[4], data[5]
+            )));
+        }
+        let chunk_count = data[6] as usize;
+        let table_end = HEADER_LEN + (chunk_count + 1) * CHUNK_ENTRY_LEN;
+        if data.len() < table_end {
+            return Err(utils::invalid_data("commit-graph chunk table is truncated"));
+        }
+
+        let (mut fanout, mut lookup, mut commit_data, mut extra_edges) = (None, None, None, None);
+        for i in 0..chunk_count {
+            let entry = &data[HEADER_LEN + i * CHUNK_ENTRY_LEN..];
+            let offset = u64::from_be_bytes(entry[4..12].try_into().unwrap()) as usize;
+            if offset > data.len() {
+                return Err(utils::invalid_data("commit-graph chunk is out of bounds"));
+            }
+            match &entry[..4] {
+                FANOUT_ID => fanout = Some(offset),
+                LOOKUP_ID => lookup = Some(offset),
+                DATA_ID => commit_data = Some(offset),
+                EXTRA_EDGES_ID => extra_edges = Some(offset),
+                _ => {}
+            }
+        }
+        let (Some(fanout), Some(lookup), Some(commit_data)) = (fanout, lookup, commit_data) else {
+            return Err(utils::invalid_data(
+                "commit-graph is missing a required chunk",
+            ));
+        };
+        if fanout + 256 * 4 > data.len() {
+            return Err(utils::invalid_data("commit-graph fanout is truncated"));
+        }
+        let mut graph = CommitGraph {
+            data,
+            fanout,
+            lookup,
+            commit_data,
+            extra_edges,
+            count: 0,
+        };
+        graph.count = graph.fanout_at(255) as usize;
+        if graph.lookup + graph.count * ObjectId::LEN > graph.data.len()
+            || graph.commit_data + graph.count * DATA_LEN > graph.data.len()
+        {
+            return Err(utils::invalid_data("commit-graph is truncated"));
+        }
+        Ok(graph)
+    }
+
+    pub fn lookup(&self, object_id: &ObjectId) -> io::Result<Option<GraphCommit>> {
+        let Some(position) = self.position(object_id) else {
+            return Ok(None);
+        };
+        let record = &self.data[self.commit_data + position * DATA_LEN..][..DATA_LEN];
+        let word = |at: usize| u32::from_be_bytes(record[at..at + 4].try_into().unwrap());
+        let (first, second) = (word(ObjectId::LEN), word(ObjectId::LEN + 4));
+        let (high, low) = (word(ObjectId::LEN + 8), word(ObjectId::LEN + 12));
+
+        let mut parents = Vec::new();
+        if first != NO_PARENT {
+            parents.push(self.object_id_at(first as usize)?);
+        }
+        if second & EDGE_FLAG != 0 {
+            self.read_extra_edges((second & !EDGE_FLAG) as usize, &mut parents)?;
+        } else if second != NO_PARENT {
+            parents.push(self.object_id_at(second as usize)?);
+        }
+        Ok(Some(GraphCommit {
+            parents,
+            date: ((high as i64 & 0b11) << 32) | low as i64,
+            generation: high >> 2,
+        }))
+    }
+
+    fn read_extra_edges(&self, start: usize, parents: &mut Vec<ObjectId>) -> io::Result<()> {
+        let Some(edges) = self.extra_edges else {
+            return Err(utils::invalid_data("commit-graph has no extra edges chunk"));
+        };
+        for index in start.. {
+            let at = edges + index * 4;
+            let Some(bytes) = self.data.get(at..at + 4) else {
+                return Err(utils::invalid_data(
+                    "commit-graph extra edge is out of bounds",
+                ));
+            };
+            let edge = u32::from_be_bytes(bytes.try_into().unwrap());
+            parents.push(self.object_id_at((edge & !EDGE_FLAG) as usize)?);
+            if edge & EDGE_FLAG != 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Binary search within the IDs sharing the first byte.
+    fn position(&self, object_id: &ObjectId) -> Option<usize> {
+        let first = object_id.as_bytes()[0] as usize;
+        let mut low = if first == 0 {
+            0
+        } else {
+            self.fanout_at(first - 1) as usize
+        };
+        let mut high = self.fanout_at(first) as usize;
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.id_bytes_at(middle).cmp(object_id.as_bytes()) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(middle),
+            }
+        }
+        None
+    }
+
+    fn fanout_at(&self, index: usize) -> u32 {
+        let at = self.fanout + index * 4;
+        u32::from_be_bytes(self.data[at..at + 4].try_into().unwrap())
+    }
+
+    fn id_bytes_at(&self, position: usize) -> &[u8] {
+        &self.data[self.lookup + position * ObjectId::LEN..][..ObjectId::LEN]
+    }
+
+    fn object_id_at(&self, position: usize) -> io::Result<ObjectId> {
+        if position >= self.count {
+            return Err(utils::invalid_data("commit-graph parent is out of bounds"));
+        }
+        ObjectId::from_bytes(self.id_bytes_at(position))
+            .map_err(|err| utils::invalid_data(&err.to_string()))
+    }
+}
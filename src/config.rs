@@ -0,0 +1,139 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/*
+    A read-only view of git's INI style config files. Section and variable
+    names are case insensitive, subsection names are not. Files loaded later
+    take precedence over earlier ones.
+*/
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    /*
+        Loads the system, global and repository config files, in that order.
+    */
+    pub fn load(git_path: &Path) -> io::Result<Self> {
+        let mut paths = vec![PathBuf::from("/etc/gitconfig")];
+        if let Some(config_home) = xdg_config_home() {
+            paths.push(config_home.join("git").join("config"));
+        }
+        if let Some(home) = env::var_os("HOME") {
+            paths.push(PathBuf::from(home).join(".gitconfig"));
+        }
+        paths.push(git_path.join("config"));
+
+        let mut config = Config::default();
+        for path in paths {
+            match fs::read_to_string(&path) {
+                Ok(content) => config.parse(&content),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(config)
+    }
+
+    /*
+        Looks up "section.name" or "section.subsection.name", returning the
+        last value set.
+    */
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /*
+        Like get, but expands a leading "~/" to the home directory.
+    */
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        let value = self.get(key)?;
+        match value.strip_prefix("~/") {
+            Some(rest) => env::var_os("HOME").map(|home| PathBuf::from(home).join(rest)),
+            None => Some(PathBuf::from(value)),
+        }
+    }
+
+    fn parse(&mut self, content: &str) {
+        let mut section = String::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.split(']').next().unwrap_or("");
+                section = match header.split_once(' ') {
+                    Some((name, sub)) => {
+                        format!("{}.{}", name.to_lowercase(), sub.trim().trim_matches('"'))
+                    }
+                    None => header.to_lowercase(),
+                };
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), parse_value(value)),
+                None => (line, String::from("true")),
+            };
+            self.entries
+                .push((format!("{}.{}", section, name.to_lowercase()), value));
+        }
+    }
+}
+
+fn normalize_key(key: &str) -> String {
+    let first = key.find('.').unwrap_or(key.len());
+    let last = key.rfind('.').unwrap_or(0);
+    if first >= last {
+        return key.to_lowercase();
+    }
+    format!(
+        "{}{}{}",
+        key[..first].to_lowercase(),
+        &key[first..last],
+        key[last..].to_lowercase()
+    )
+}
+
+/*
+    Strips comments and surrounding whitespace from a value, honouring
+    double quotes and backslash escapes.
+*/
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(other) => value.push(other),
+                None => {}
+            },
+            c => value.push(c),
+        }
+    }
+    if in_quotes {
+        value
+    } else {
+        value.trim_end().to_string()
+    }
+}
+
+pub fn xdg_config_home() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
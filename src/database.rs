@@ -1,47 +1,468 @@
-use std::{fs, io, path::PathBuf};
+use std::{cell::OnceCell, collections::BTreeMap, error, fmt, fs, io, path::PathBuf};
 
 use deflate::write::ZlibEncoder;
 use deflate::Compression;
 use std::io::Write;
 use tempfile::NamedTempFile;
 
+use crate::blob::{Blob, Kind};
+use crate::commit::Commit;
+use crate::entry::Entry;
+use crate::object_id::ObjectId;
+use crate::pack::Pack;
+use crate::tag::Tag;
 use crate::traits::Object;
+use crate::tree::{EntryOrTree, Tree};
 use crate::utils;
 
+// Shortest hex prefix accepted as an abbreviated object ID.
+pub const MIN_PREFIX_LEN: usize = 4;
+
+/*
+    The object store under .git/objects: loose objects, one zlib file
+    each, and the packs git's gc or clone leave in objects/pack. Lookups
+    try loose objects first and then each pack, so callers need not care
+    where an object lives. Packs are opened on first use.
+*/
 pub struct Database {
     pub path_buf: PathBuf,
+    packs: OnceCell<Vec<Pack>>,
+}
+
+#[derive(Debug)]
+pub enum LookupError {
+    Ambiguous {
+        prefix: String,
+        candidates: Vec<String>,
+    },
+    Io(io::Error),
+}
+
+impl error::Error for LookupError {}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::Ambiguous { prefix, candidates } => {
+                write!(f, "short object ID {} is ambiguous", prefix)?;
+                write!(f, "\nhint: The candidates are:")?;
+                for candidate in candidates {
+                    write!(f, "\nhint:   {}", candidate)?;
+                }
+                Ok(())
+            }
+            LookupError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for LookupError {
+    fn from(err: io::Error) -> Self {
+        LookupError::Io(err)
+    }
+}
+
+fn kind_order(kind: Kind) -> u8 {
+    match kind {
+        Kind::Tag => 0,
+        Kind::Commit => 1,
+        Kind::Tree => 2,
+        Kind::Blob => 3,
+    }
+}
+
+/*
+    An object loaded back from the database, parsed according to its type header.
+*/
+#[derive(Debug)]
+pub enum GitObject {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl GitObject {
+    pub fn kind(&self) -> Kind {
+        match self {
+            GitObject::Blob(_) => Kind::Blob,
+            GitObject::Tree(_) => Kind::Tree,
+            GitObject::Commit(_) => Kind::Commit,
+            GitObject::Tag(_) => Kind::Tag,
+        }
+    }
 }
 
 impl Database {
     pub fn new(path_buf: PathBuf) -> Self {
-        Database { path_buf }
+        Database {
+            path_buf,
+            packs: OnceCell::new(),
+        }
+    }
+
+    pub fn store(&self, object: &mut dyn Object) -> io::Result<ObjectId> {
+        let content = object.to_bytes();
+        let object_id = utils::hash_content(&content);
+        object.set_object_id(object_id);
+        self.write_object(&object_id, &content)?;
+        Ok(object_id)
+    }
+
+    pub fn object_path(&self, object_id: &ObjectId) -> PathBuf {
+        let hex = object_id.to_hex();
+        let (dir, file) = utils::hash_to_path(&hex);
+        self.path_buf.join(dir).join(file)
+    }
+
+    /*
+        IDs of the objects whose hex form starts with prefix, sorted. Only
+        the fan-out directory and pack index entries named by the first two
+        digits are scanned.
+    */
+    pub fn prefix_match(&self, prefix: &str) -> io::Result<Vec<ObjectId>> {
+        let prefix = prefix.to_ascii_lowercase();
+        if prefix.len() < 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(Vec::new());
+        }
+        let mut matches: Vec<ObjectId> = self
+            .fanout_ids(&prefix[..2])?
+            .into_iter()
+            .filter(|object_id| object_id.to_hex().starts_with(&prefix))
+            .collect();
+        matches.sort();
+        matches.dedup();
+        Ok(matches)
+    }
+
+    /*
+        Resolves an abbreviated object ID of at least MIN_PREFIX_LEN hex
+        digits. None means no object matches; several matches are an error
+        listing every candidate the way git's hints do.
+    */
+    pub fn find_by_prefix(&self, prefix: &str) -> Result<Option<ObjectId>, LookupError> {
+        if prefix.len() < MIN_PREFIX_LEN {
+            return Ok(None);
+        }
+        let matches = self.prefix_match(prefix)?;
+        if matches.len() <= 1 {
+            return Ok(matches.first().copied());
+        }
+        let mut candidates = Vec::new();
+        for object_id in matches {
+            let kind = self.read_object(&object_id)?.0;
+            candidates.push((kind, object_id));
+        }
+        // git lists tags, then commits, trees and blobs, by ID within each type.
+        candidates.sort_by_key(|(kind, object_id)| (kind_order(*kind), *object_id));
+        let mut descriptions = Vec::new();
+        for (kind, object_id) in candidates {
+            let short = self.short_object_id(&object_id)?;
+            let description = match kind {
+                Kind::Commit => {
+                    let commit = self.load_commit(&object_id)?;
+                    let date = commit
+                        .author
+                        .datetime()
+                        .map(|time| time.format("%Y-%m-%d").to_string())
+                        .unwrap_or_default();
+                    format!("{} commit {} - {}", short, date, commit.title_line())
+                }
+                Kind::Tag => {
+                    let tag = self.load_tag(&object_id)?;
+                    let date = tag
+                        .tagger
+                        .and_then(|tagger| tagger.datetime())
+                        .map(|time| time.format("%Y-%m-%d").to_string())
+                        .unwrap_or_default();
+                    format!("{} tag {} - {}", short, date, tag.name)
+                }
+                kind => format!("{} {}", short, kind),
+            };
+            descriptions.push(description);
+        }
+        Err(LookupError::Ambiguous {
+            prefix: prefix.to_string(),
+            candidates: descriptions,
+        })
+    }
+
+    /*
+        The shortest abbreviation of object_id, at least ObjectId::SHORT_LEN
+        digits long, that no other object in the database shares.
+    */
+    pub fn short_object_id(&self, object_id: &ObjectId) -> io::Result<String> {
+        self.abbreviate(object_id, ObjectId::SHORT_LEN)
+    }
+
+    pub fn abbreviate(&self, object_id: &ObjectId, min_len: usize) -> io::Result<String> {
+        let hex = object_id.to_hex();
+        let mut len = min_len.clamp(MIN_PREFIX_LEN, ObjectId::HEX_LEN);
+        for other in self.fanout_ids(&hex[..2])? {
+            if other == *object_id {
+                continue;
+            }
+            let common = hex
+                .bytes()
+                .zip(other.to_hex().bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            len = len.max(common + 1);
+        }
+        Ok(object_id.abbrev(len))
+    }
+
+    /*
+        Every object stored under the objects/<dir> fan-out directory, and
+        every packed object whose ID starts with the same byte. An object
+        both loose and packed is listed twice.
+    */
+    fn fanout_ids(&self, dir: &str) -> io::Result<Vec<ObjectId>> {
+        let mut object_ids = Vec::new();
+        if let Ok(first_byte) = u8::from_str_radix(dir, 16) {
+            for pack in self.packs()? {
+                object_ids.extend(pack.index().ids_with_first_byte(first_byte));
+            }
+        }
+        let entries = match fs::read_dir(self.path_buf.join(dir)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(object_ids),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let hex = format!("{}{}", dir, entry?.file_name().to_string_lossy());
+            if let Ok(object_id) = ObjectId::from_hex(&hex) {
+                object_ids.push(object_id);
+            }
+        }
+        Ok(object_ids)
+    }
+
+    /*
+        Reads an object's type and body, from its loose file if there is
+        one and otherwise from the first pack holding it.
+    */
+    pub fn read_object(&self, object_id: &ObjectId) -> io::Result<(Kind, Vec<u8>)> {
+        let raw = match utils::inflate(self.object_path(object_id)) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                for pack in self.packs()? {
+                    if let Some(object) = pack.read_object(object_id)? {
+                        return Ok(object);
+                    }
+                }
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        };
+        parse_loose_object(object_id, raw)
     }
 
-    pub fn store(&self, object: &mut dyn Object) -> io::Result<()> {
-        let content_str = object.to_string();
-        let content_hash = utils::hash_content(&content_str);
-        let content_hash_hex = utils::u8_to_hex_str(content_hash.clone());
-        unsafe {
-            object.set_object_id(String::from_utf8_unchecked(content_hash));
+    /*
+        Whether the object is stored, loose or packed.
+    */
+    pub fn contains(&self, object_id: &ObjectId) -> io::Result<bool> {
+        if fs::metadata(self.object_path(object_id)).is_ok() {
+            return Ok(true);
+        }
+        Ok(self.packs()?.iter().any(|pack| pack.contains(object_id)))
+    }
+
+    pub fn packs(&self) -> io::Result<&[Pack]> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+        let packs = Pack::load_all(&self.path_buf)?;
+        Ok(self.packs.get_or_init(|| packs))
+    }
+
+    /*
+        Forgets the packs opened so far, so the next lookup sees packs
+        written or deleted since.
+    */
+    pub fn reload_packs(&mut self) {
+        self.packs = OnceCell::new();
+    }
+
+    /*
+        Removes every loose object that is also in a pack, along with the
+        fan-out directories that leaves empty, like git prune-packed.
+    */
+    pub fn prune_packed(&self) -> io::Result<usize> {
+        let mut pruned = 0;
+        for byte in 0..=255u8 {
+            let dir = format!("{:02x}", byte);
+            let entries = match fs::read_dir(self.path_buf.join(&dir)) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let hex = format!("{}{}", dir, entry.file_name().to_string_lossy());
+                let Ok(object_id) = ObjectId::from_hex(&hex) else {
+                    continue;
+                };
+                if self.packs()?.iter().any(|pack| pack.contains(&object_id)) {
+                    fs::remove_file(entry.path())?;
+                    pruned += 1;
+                }
+            }
+            // Only succeeds once the directory is empty.
+            let _ = fs::remove_dir(self.path_buf.join(&dir));
+        }
+        Ok(pruned)
+    }
+
+    pub fn load(&self, object_id: &ObjectId) -> io::Result<GitObject> {
+        let (kind, body) = self.read_object(object_id)?;
+        let object = match kind {
+            Kind::Blob => {
+                let mut blob = Blob::parse(&body);
+                blob.set_object_id(*object_id);
+                GitObject::Blob(blob)
+            }
+            Kind::Tree => {
+                let mut tree = Tree::parse(&body)?;
+                tree.set_object_id(*object_id);
+                GitObject::Tree(tree)
+            }
+            Kind::Commit => {
+                let mut commit = Commit::parse(&body)?;
+                commit.set_object_id(*object_id);
+                GitObject::Commit(commit)
+            }
+            Kind::Tag => {
+                let mut tag = Tag::parse(&body)?;
+                tag.set_object_id(*object_id);
+                GitObject::Tag(tag)
+            }
+        };
+        Ok(object)
+    }
+
+    pub fn load_commit(&self, object_id: &ObjectId) -> io::Result<Commit> {
+        match self.load(object_id)? {
+            GitObject::Commit(commit) => Ok(commit),
+            other => Err(utils::invalid_data(&format!(
+                "object {} is a {}, not a commit",
+                object_id,
+                other.kind()
+            ))),
+        }
+    }
+
+    pub fn load_tag(&self, object_id: &ObjectId) -> io::Result<Tag> {
+        match self.load(object_id)? {
+            GitObject::Tag(tag) => Ok(tag),
+            other => Err(utils::invalid_data(&format!(
+                "object {} is a {}, not a tag",
+                object_id,
+                other.kind()
+            ))),
+        }
+    }
+
+    pub fn load_tree(&self, object_id: &ObjectId) -> io::Result<Tree> {
+        match self.load(object_id)? {
+            GitObject::Tree(tree) => Ok(tree),
+            other => Err(utils::invalid_data(&format!(
+                "object {} is a {}, not a tree",
+                object_id,
+                other.kind()
+            ))),
+        }
+    }
+
+    /*
+        Flattens a tree into its file entries keyed by full "/" separated path,
+        loading subtrees recursively.
+    */
+    pub fn load_tree_entries(&self, object_id: &ObjectId) -> io::Result<BTreeMap<String, Entry>> {
+        let mut entries = BTreeMap::new();
+        self.collect_tree_entries(object_id, "", &mut entries)?;
+        Ok(entries)
+    }
+
+    fn collect_tree_entries(
+        &self,
+        object_id: &ObjectId,
+        prefix: &str,
+        entries: &mut BTreeMap<String, Entry>,
+    ) -> io::Result<()> {
+        let tree = self.load_tree(object_id)?;
+        for name in &tree.entries_order {
+            let EntryOrTree::Entry(entry) = &tree.entries[name] else {
+                continue;
+            };
+            let path = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            if entry.is_tree() {
+                self.collect_tree_entries(&entry.object_id, &path, entries)?;
+            } else {
+                let mut entry = entry.clone();
+                entry.path = PathBuf::from(&path);
+                entries.insert(path, entry);
+            }
         }
-        self.write_object(&content_hash_hex, content_str.as_bytes())?;
         Ok(())
     }
 
-    pub fn write_object(&self, content_hash_hex: &str, content: &[u8]) -> io::Result<()> {
-        let (dir, file) = utils::hash_to_path(content_hash_hex);
-        let object_path = self.path_buf.join(dir);
-        if fs::metadata(object_path.join(file)).is_ok() {
+    /*
+        Computes the ID an object would be stored under, without writing it.
+    */
+    pub fn hash_object(&self, object: &dyn Object) -> ObjectId {
+        utils::hash_content(&object.to_bytes())
+    }
+
+    pub fn write_object(&self, object_id: &ObjectId, content: &[u8]) -> io::Result<()> {
+        let object_file = self.object_path(object_id);
+        if self.contains(object_id)? {
             return Ok(());
         }
+        let object_path = object_file.parent().unwrap();
         let temp_file = NamedTempFile::new()?;
         let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Fast);
         encoder.write_all(content).expect("Write error!");
         let compressed_data = encoder.finish().expect("Failed to compress object");
         fs::write(&temp_file, compressed_data).expect("Unable to write object");
-        fs::create_dir_all(&object_path)?;
-        fs::rename(temp_file.path(), object_path.join(file))?;
+        fs::create_dir_all(object_path)?;
+        fs::rename(temp_file.path(), &object_file)?;
 
         Ok(())
     }
 }
+
+/*
+    Splits a loose object's "<type> <size>\0" header off its inflated
+    contents, checking that the declared size matches the body.
+*/
+fn parse_loose_object(object_id: &ObjectId, mut raw: Vec<u8>) -> io::Result<(Kind, Vec<u8>)> {
+    let nul = raw
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| utils::invalid_data("object header is missing"))?;
+    let header = std::str::from_utf8(&raw[..nul])
+        .map_err(|_| utils::invalid_data("object header is not valid UTF-8"))?;
+    let (kind, size) = header
+        .split_once(' ')
+        .ok_or_else(|| utils::invalid_data("malformed object header"))?;
+    let kind = Kind::parse(kind)
+        .ok_or_else(|| utils::invalid_data(&format!("unknown object type {}", kind)))?;
+    let size = size
+        .parse::<usize>()
+        .map_err(|_| utils::invalid_data("malformed object size"))?;
+    let body = raw.split_off(nul + 1);
+    if body.len() != size {
+        return Err(utils::invalid_data(&format!(
+            "object {} has size {} but header says {}",
+            object_id,
+            body.len(),
+            size
+        )));
+    }
+    Ok((kind, body))
+}
@@ -0,0 +1,492 @@
+use std::{collections::HashMap, io};
+
+use crate::utils;
+
+// Bytes covered by the rolling hash, and the spacing of indexed base blocks.
+const WINDOW: usize = 16;
+// Multiplier of the polynomial rolling hash.
+const HASH_BASE: u32 = 0x0100_0193;
+// Candidates kept per hash, so repetitive bases do not make lookups slow.
+const MAX_BUCKET_LEN: usize = 64;
+const MAX_INSERT_LEN: usize = 0x7f;
+// A copy of exactly this many bytes is written with no size bytes at all.
+const MAX_COPY_LEN: usize = 0x10000;
+
+/*
+    An index of a base: the positions of its WINDOW-byte blocks, keyed by
+    their rolling hash. It holds no reference to the base, so the same
+    base must be passed back to create_delta.
+*/
+pub struct DeltaIndex {
+    base_len: usize,
+    blocks: HashMap<u32, Vec<usize>>,
+}
+
+impl DeltaIndex {
+    pub fn new(base: &[u8]) -> Self {
+        let mut blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+        // Later blocks are indexed first, so nearer matches are preferred
+        // among equally long ones, as in git's diff-delta.
+        for offset in (0..base.len() / WINDOW).rev().map(|block| block * WINDOW) {
+            let bucket = blocks
+                .entry(hash(&base[offset..offset + WINDOW]))
+                .or_default();
+            if bucket.len() < MAX_BUCKET_LEN {
+                bucket.push(offset);
+            }
+        }
+        DeltaIndex {
+            base_len: base.len(),
+            blocks,
+        }
+    }
+
+    /*
+        A delta that rebuilds target from base, or None once it would grow
+        past max_size. The target is scanned with a rolling hash; where a
+        block of the base matches, the longest match is grown backwards
+        over bytes not yet written and copied, and everything else is
+        inserted.
+    */
+    pub fn create_delta(
+        &self,
+        base: &[u8],
+        target: &[u8],
+        max_size: Option<usize>,
+    ) -> Option<Vec<u8>> {
+        assert_eq!(base.len(), self.base_len, "delta index is for another base");
+        let max_size = max_size.unwrap_or(usize::MAX);
+        let mut delta = Vec::new();
+        write_size(&mut delta, base.len());
+        write_size(&mut delta, target.len());
+
+        let mut pending = 0;
+        let mut position = 0;
+        let mut rolling = RollingHash::new(target, position);
+        while position + WINDOW <= target.len() {
+            let best = self.longest_match(base, target, position, rolling.value);
+            let Some((offset, len)) = best else {
+                rolling.roll(target, position);
+                position += 1;
+                continue;
+            };
+            let (mut start, mut base_start) = (position, offset);
+            while start > pending && base_start > 0 && target[start - 1] == base[base_start - 1] {
+                start -= 1;
+                base_start -= 1;
+            }
+            write_insert(&mut delta, &target[pending..start]);
+            write_copy(&mut delta, base_start, position + len - start);
+            if delta.len() > max_size {
+                return None;
+            }
+            position += len;
+            pending = position;
+            rolling = RollingHash::new(target, position);
+        }
+        write_insert(&mut delta, &target[pending..]);
+        (delta.len() <= max_size).then_some(delta)
+    }
+
+    // The longest run of target at position found in the base.
+    fn longest_match(
+        &self,
+        base: &[u8],
+        target: &[u8],
+        position: usize,
+        hash: u32,
+    ) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for &offset in self.blocks.get(&hash)? {
+            let len = base[offset..]
+                .iter()
+                .zip(&target[position..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= WINDOW && best.is_none_or(|(_, best_len)| len > best_len) {
+                best = Some((offset, len));
+            }
+        }
+        best
+    }
+}
+
+/*
+    A delta that turns base into target.
+*/
+pub fn diff(base: &[u8], target: &[u8]) -> Vec<u8> {
+    DeltaIndex::new(base)
+        .create_delta(base, target, None)
+        .expect("an unlimited delta always fits")
+}
+
+/*
+    Rebuilds the target of a delta from its base. A delta in git's format
+    starts with the sizes of the base and of the target as varints
+    (little-endian, 7 bits a byte, high bit set on all but the last), then
+    has instructions that either copy a range of the base (high bit set,
+    with flag bits saying which offset and size bytes follow) or insert
+    the next 1-127 bytes of the delta itself.
+*/
+pub fn apply(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+    let mut position = 0;
+    let base_size = read_size(delta, &mut position)?;
+    let target_size = read_size(delta, &mut position)?;
+    if base_size != base.len() {
+        return Err(utils::invalid_data(
+            "delta does not match the size of its base",
+        ));
+    }
+    let mut target = Vec::with_capacity(target_size);
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+        if instruction & 0x80 != 0 {
+            let mut offset = 0;
+            let mut size = 0;
+            for i in 0..4 {
+                if instruction & (1 << i) != 0 {
+                    offset |= (next_byte(delta, &mut position)? as usize) << (8 * i);
+                }
+            }
+            for i in 0..3 {
+                if instruction & (0x10 << i) != 0 {
+                    size |= (next_byte(delta, &mut position)? as usize) << (8 * i);
+                }
+            }
+            if size == 0 {
+                size = MAX_COPY_LEN;
+            }
+            let copied = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or_else(|| utils::invalid_data("delta copies past the end of its base"))?;
+            target.extend_from_slice(copied);
+        } else if instruction != 0 {
+            let end = position + instruction as usize;
+            let inserted = delta
+                .get(position..end)
+                .ok_or_else(|| utils::invalid_data("delta insert is truncated"))?;
+            target.extend_from_slice(inserted);
+            position = end;
+        } else {
+            return Err(utils::invalid_data("delta has a reserved instruction"));
+        }
+    }
+    if target.len() != target_size {
+        return Err(utils::invalid_data("delta result has the wrong size"));
+    }
+    Ok(target)
+}
+
+/*
+    The hash of the WINDOW bytes of data starting at a position, updated
+    a byte at a time as the window slides along.
+*/
+struct RollingHash {
+    value: u32,
+}
+
+impl RollingHash {
+    fn new(data: &[u8], position: usize) -> Self {
+        let end = (position + WINDOW).min(data.len());
+        RollingHash {
+            value: hash(&data[position..end]),
+        }
+    }
+
+    // Slides the window starting at position one byte further.
+    fn roll(&mut self, data: &[u8], position: usize) {
+        let Some(&incoming) = data.get(position + WINDOW) else {
+            return;
+        };
+        let outgoing =
+            (data[position] as u32).wrapping_mul(HASH_BASE.wrapping_pow(WINDOW as u32 - 1));
+        self.value = self
+            .value
+            .wrapping_sub(outgoing)
+            .wrapping_mul(HASH_BASE)
+            .wrapping_add(incoming as u32);
+    }
+}
+
+fn hash(window: &[u8]) -> u32 {
+    window.iter().fold(0, |hash: u32, &byte| {
+        hash.wrapping_mul(HASH_BASE).wrapping_add(byte as u32)
+    })
+}
+
+fn write_size(delta: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        delta.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    delta.push(size as u8);
+}
+
+fn read_size(delta: &[u8], position: &mut usize) -> io::Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = next_byte(delta, position)?;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+        if shift > 63 {
+            return Err(utils::invalid_data("delta size is too large"));
+        }
+    }
+}
+
+fn next_byte(delta: &[u8], position: &mut usize) -> io::Result<u8> {
+    let byte = *delta
+        .get(*position)
+        .ok_or_else(|| utils::invalid_data("delta is truncated"))?;
+    *position += 1;
+    Ok(byte)
+}
+
+fn write_insert(delta: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT_LEN) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY_LEN);
+        let mut instruction = 0x80;
+        let mut arguments = Vec::new();
+        for i in 0..4 {
+            let byte = (offset >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 1 << i;
+                arguments.push(byte);
+            }
+        }
+        // A size of MAX_COPY_LEN has no bytes below bit 16, and is encoded
+        // as none at all.
+        for i in 0..3 {
+            let byte = ((size % MAX_COPY_LEN) >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 0x10 << i;
+                arguments.push(byte);
+            }
+        }
+        delta.push(instruction);
+        delta.extend_from_slice(&arguments);
+        offset += size;
+        len -= size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A small xorshift generator, so the random cases are the same every run.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+
+        fn bytes_up_to(&mut self, max_len: usize) -> Vec<u8> {
+            let len = self.below(max_len + 1);
+            self.bytes(len)
+        }
+    }
+
+    fn assert_round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = diff(base, target);
+        assert_eq!(apply(base, &delta).unwrap(), target);
+        delta
+    }
+
+    // The copy instructions of a delta, as (offset, size) pairs.
+    fn copies(delta: &[u8]) -> Vec<(usize, usize)> {
+        let mut position = 0;
+        read_size(delta, &mut position).unwrap();
+        read_size(delta, &mut position).unwrap();
+        let mut copies = Vec::new();
+        while position < delta.len() {
+            let instruction = delta[position];
+            position += 1;
+            if instruction & 0x80 == 0 {
+                position += instruction as usize;
+                continue;
+            }
+            let (mut offset, mut size) = (0, 0);
+            for i in 0..7 {
+                if instruction & (1 << i) != 0 {
+                    let byte = delta[position] as usize;
+                    position += 1;
+                    if i < 4 {
+                        offset |= byte << (8 * i);
+                    } else {
+                        size |= byte << (8 * (i - 4));
+                    }
+                }
+            }
+            copies.push((offset, if size == 0 { MAX_COPY_LEN } else { size }));
+        }
+        copies
+    }
+
+    #[test]
+    fn empty_base() {
+        assert_round_trip(b"", b"");
+        assert_round_trip(b"", b"some new content that is not in the base at all");
+    }
+
+    #[test]
+    fn empty_target() {
+        let delta = assert_round_trip(b"a base with nothing kept from it", b"");
+        assert_eq!(delta, [32, 0]);
+    }
+
+    #[test]
+    fn identical_inputs_are_one_copy() {
+        let data = Random(1).bytes(1000);
+        let delta = assert_round_trip(&data, &data);
+        assert_eq!(copies(&delta), [(0, 1000)]);
+    }
+
+    #[test]
+    fn copies_longer_than_max_copy_len_are_split() {
+        let data = Random(2).bytes(3 * MAX_COPY_LEN + 123);
+        let delta = assert_round_trip(&data, &data);
+        assert_eq!(
+            copies(&delta),
+            [
+                (0, MAX_COPY_LEN),
+                (MAX_COPY_LEN, MAX_COPY_LEN),
+                (2 * MAX_COPY_LEN, MAX_COPY_LEN),
+                (3 * MAX_COPY_LEN, 123),
+            ]
+        );
+    }
+
+    #[test]
+    fn inserts_longer_than_max_insert_len_are_split() {
+        let target = Random(3).bytes(5 * MAX_INSERT_LEN + 3);
+        let delta = assert_round_trip(b"unrelated", &target);
+        assert!(copies(&delta).is_empty());
+    }
+
+    #[test]
+    fn copies_from_offsets_needing_four_bytes() {
+        // A match past 16MB, with no zero byte in its offset, is written
+        // with all four offset bytes.
+        let mut random = Random(4);
+        let mut base = vec![0; 0x0102_0304];
+        let tail = random.bytes(1000);
+        base.extend_from_slice(&tail);
+        let mut target = random.bytes(10);
+        target.extend_from_slice(&tail);
+        let delta = assert_round_trip(&base, &target);
+        assert_eq!(copies(&delta), [(0x0102_0304, 1000)]);
+    }
+
+    #[test]
+    fn random_edits_round_trip() {
+        let mut random = Random(0x5eed);
+        for _ in 0..200 {
+            let base = random.bytes_up_to(4000);
+            let mut target = base.clone();
+            for _ in 0..random.below(10) {
+                let at = random.below(target.len() + 1);
+                match random.below(3) {
+                    0 => {
+                        let inserted = random.bytes_up_to(300);
+                        target.splice(at..at, inserted);
+                    }
+                    1 => {
+                        let end = (at + random.below(300)).min(target.len());
+                        target.drain(at..end);
+                    }
+                    _ => {
+                        // Repeat part of the target, so copies can overlap.
+                        let end = (at + random.below(300)).min(target.len());
+                        let repeated = target[at..end].to_vec();
+                        target.extend_from_slice(&repeated);
+                    }
+                }
+            }
+            assert_round_trip(&base, &target);
+            assert_round_trip(&target, &base);
+        }
+    }
+
+    #[test]
+    fn random_unrelated_inputs_round_trip() {
+        let mut random = Random(0xdecaf);
+        for _ in 0..50 {
+            let base = random.bytes_up_to(2000);
+            let target = random.bytes_up_to(2000);
+            assert_round_trip(&base, &target);
+        }
+    }
+
+    #[test]
+    fn create_delta_gives_up_past_max_size() {
+        let base = Random(5).bytes(1000);
+        let target = Random(6).bytes(1000);
+        let index = DeltaIndex::new(&base);
+        assert!(index.create_delta(&base, &target, Some(100)).is_none());
+        assert!(index.create_delta(&base, &base, Some(100)).is_some());
+    }
+
+    #[test]
+    fn apply_rejects_truncated_deltas() {
+        let mut random = Random(7);
+        let base = random.bytes(1000);
+        let mut target = base.clone();
+        target.splice(500..500, random.bytes(50));
+        let delta = diff(&base, &target);
+        for len in 0..delta.len() {
+            let err = apply(&base, &delta[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn apply_rejects_size_mismatches() {
+        let base = b"the quick brown fox jumps over the lazy dog";
+        let target = b"the quick brown fox jumps over the lazy cat";
+        let delta = diff(base, target);
+        assert!(apply(&base[1..], &delta).is_err());
+
+        // A target size that does not match what the instructions write.
+        let mut wrong_target_size = Vec::new();
+        write_size(&mut wrong_target_size, base.len());
+        write_size(&mut wrong_target_size, target.len() + 1);
+        let mut position = 0;
+        read_size(&delta, &mut position).unwrap();
+        read_size(&delta, &mut position).unwrap();
+        wrong_target_size.extend_from_slice(&delta[position..]);
+        assert!(apply(base, &wrong_target_size).is_err());
+
+        // A copy reaching past the end of the base.
+        let mut past_end = Vec::new();
+        write_size(&mut past_end, base.len());
+        write_size(&mut past_end, 10);
+        write_copy(&mut past_end, base.len() - 5, 10);
+        assert!(apply(base, &past_end).is_err());
+    }
+}
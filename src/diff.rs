@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+
+// Lines of unchanged context git shows around each change by default.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Eql,
+    Ins,
+    Del,
+}
+
+/*
+    A line of one side of a diff, numbered from 1. text keeps its
+    trailing "\n", so a final line without one can be told apart.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub number: usize,
+    pub text: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit<'a> {
+    pub kind: EditKind,
+    pub a_line: Option<Line<'a>>,
+    pub b_line: Option<Line<'a>>,
+}
+
+impl Edit<'_> {
+    /*
+        The line this edit prints: the new line for insertions, the old
+        one otherwise.
+    */
+    pub fn line(&self) -> Line<'_> {
+        match self.kind {
+            EditKind::Ins => self.b_line.expect("insertions have a new line"),
+            _ => self.a_line.expect("deletions and context have an old line"),
+        }
+    }
+}
+
+/*
+    Splits data into lines, keeping each line's "\n".
+*/
+pub fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/*
+    Data git would refuse to show as text: anything with a NUL byte in
+    its first 8000 bytes.
+*/
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/*
+    The shortest edit script turning a into b. Lines found on only one
+    side can never match, so they are set aside before the rest are
+    matched with the linear-space form of Myers' algorithm, as git's
+    xdiff does. Deletions come before insertions within each change.
+*/
+pub fn diff<'a>(a: &[&'a [u8]], b: &[&'a [u8]]) -> Vec<Edit<'a>> {
+    let mut ids = HashMap::new();
+    let a_ids: Vec<usize> = a.iter().map(|text| intern(&mut ids, text)).collect();
+    let b_ids: Vec<usize> = b.iter().map(|text| intern(&mut ids, text)).collect();
+    let mut in_a = vec![false; ids.len()];
+    let mut in_b = vec![false; ids.len()];
+    a_ids.iter().for_each(|&id| in_a[id] = true);
+    b_ids.iter().for_each(|&id| in_b[id] = true);
+    let a_kept: Vec<usize> = (0..a.len()).filter(|&i| in_b[a_ids[i]]).collect();
+    let b_kept: Vec<usize> = (0..b.len()).filter(|&j| in_a[b_ids[j]]).collect();
+
+    let mut myers = Myers {
+        a: a_kept.iter().map(|&i| a_ids[i]).collect(),
+        b: b_kept.iter().map(|&j| b_ids[j]).collect(),
+        matches: Vec::new(),
+    };
+    myers.compare(0, myers.a.len(), 0, myers.b.len());
+
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    let (mut x, mut y) = (0, 0);
+    let ends = myers
+        .matches
+        .iter()
+        .map(|&(i, j)| (a_kept[i], b_kept[j]))
+        .chain(std::iter::once((a.len(), b.len())));
+    for (match_x, match_y) in ends {
+        edits.extend((x..match_x).map(|i| Edit {
+            kind: EditKind::Del,
+            a_line: Some(line(a, i)),
+            b_line: None,
+        }));
+        edits.extend((y..match_y).map(|j| Edit {
+            kind: EditKind::Ins,
+            a_line: None,
+            b_line: Some(line(b, j)),
+        }));
+        if match_x < a.len() {
+            edits.push(Edit {
+                kind: EditKind::Eql,
+                a_line: Some(line(a, match_x)),
+                b_line: Some(line(b, match_y)),
+            });
+        }
+        (x, y) = (match_x + 1, match_y + 1);
+    }
+    edits
+}
+
+fn intern<'a>(ids: &mut HashMap<&'a [u8], usize>, text: &'a [u8]) -> usize {
+    let next = ids.len();
+    *ids.entry(text).or_insert(next)
+}
+
+fn line<'a>(lines: &[&'a [u8]], index: usize) -> Line<'a> {
+    Line {
+        number: index + 1,
+        text: lines[index],
+    }
+}
+
+/*
+    Two sequences of line ids and the (a, b) index pairs matched so far,
+    in order.
+*/
+struct Myers {
+    a: Vec<usize>,
+    b: Vec<usize>,
+    matches: Vec<(usize, usize)>,
+}
+
+impl Myers {
+    /*
+        Matches a[a_lo..a_hi] against b[b_lo..b_hi]: trims the common
+        prefix and suffix, then splits around a middle snake and recurses
+        on each half, so only O(N + M) memory is live at once.
+    */
+    fn compare(&mut self, mut a_lo: usize, mut a_hi: usize, mut b_lo: usize, mut b_hi: usize) {
+        while a_lo < a_hi && b_lo < b_hi && self.a[a_lo] == self.b[b_lo] {
+            self.matches.push((a_lo, b_lo));
+            a_lo += 1;
+            b_lo += 1;
+        }
+        let mut suffix = 0;
+        while a_lo < a_hi && b_lo < b_hi && self.a[a_hi - 1] == self.b[b_hi - 1] {
+            a_hi -= 1;
+            b_hi -= 1;
+            suffix += 1;
+        }
+        // With the ends trimmed, both sides left means at least two edits,
+        // so each half of the split is strictly smaller.
+        if a_lo < a_hi && b_lo < b_hi {
+            let (x, y, u, v) = self.middle_snake(a_lo, a_hi, b_lo, b_hi);
+            self.compare(a_lo, x, b_lo, y);
+            self.matches.extend((x..u).zip(y..v));
+            self.compare(u, a_hi, v, b_hi);
+        }
+        self.matches
+            .extend((a_hi..a_hi + suffix).zip(b_hi..b_hi + suffix));
+    }
+
+    /*
+        Runs Myers' search forwards from the start and backwards from the
+        end until the two meet, returning the snake (x, y) to (u, v) where
+        they overlap. That snake lies on a shortest path.
+    */
+    fn middle_snake(
+        &self,
+        a_lo: usize,
+        a_hi: usize,
+        b_lo: usize,
+        b_hi: usize,
+    ) -> (usize, usize, usize, usize) {
+        let (n, m) = ((a_hi - a_lo) as isize, (b_hi - b_lo) as isize);
+        let max = (n + m + 1) / 2;
+        let offset = |k: isize| (k + max + 1) as usize;
+        let delta = n - m;
+        let odd = delta % 2 != 0;
+        let mut forward = vec![0isize; 2 * max as usize + 3];
+        let mut backward = vec![0isize; 2 * max as usize + 3];
+        for d in 0..=max {
+            for k in (-d..=d).step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && forward[offset(k - 1)] < forward[offset(k + 1)]) {
+                        forward[offset(k + 1)]
+                    } else {
+                        forward[offset(k - 1)] + 1
+                    };
+                let (start_x, start_y) = (x, x - k);
+                let mut y = start_y;
+                while x < n && y < m && self.a[a_lo + x as usize] == self.b[b_lo + y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                forward[offset(k)] = x;
+                let back_k = delta - k;
+                if odd && -d < back_k && back_k < d && x + backward[offset(back_k)] >= n {
+                    return (
+                        a_lo + start_x as usize,
+                        b_lo + start_y as usize,
+                        a_lo + x as usize,
+                        b_lo + y as usize,
+                    );
+                }
+            }
+            for k in (-d..=d).step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && backward[offset(k - 1)] < backward[offset(k + 1)]) {
+                        backward[offset(k + 1)]
+                    } else {
+                        backward[offset(k - 1)] + 1
+                    };
+                let (start_x, start_y) = (x, x - k);
+                let mut y = start_y;
+                while x < n
+                    && y < m
+                    && self.a[a_hi - 1 - x as usize] == self.b[b_hi - 1 - y as usize]
+                {
+                    x += 1;
+                    y += 1;
+                }
+                backward[offset(k)] = x;
+                let forward_k = delta - k;
+                if !odd && -d <= forward_k && forward_k <= d && x + forward[offset(forward_k)] >= n
+                {
+                    return (
+                        a_hi - x as usize,
+                        b_hi - y as usize,
+                        a_hi - start_x as usize,
+                        b_hi - start_y as usize,
+                    );
+                }
+            }
+        }
+        unreachable!("the searches meet within (n + m + 1) / 2 steps")
+    }
+}
+
+/*
+    A run of edits shown together, with up to context unchanged lines
+    around each change. a_start and b_start count the lines on each side
+    before the hunk begins.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk<'a> {
+    pub a_start: usize,
+    pub b_start: usize,
+    pub edits: Vec<Edit<'a>>,
+}
+
+impl<'a> Hunk<'a> {
+    /*
+        Groups edits into hunks. Changes separated by at most twice the
+        context share a hunk, as in git.
+    */
+    pub fn filter(edits: &[Edit<'a>], context: usize) -> Vec<Hunk<'a>> {
+        let mut hunks = Vec::new();
+        let mut offset = 0;
+        loop {
+            while offset < edits.len() && edits[offset].kind == EditKind::Eql {
+                offset += 1;
+            }
+            if offset >= edits.len() {
+                return hunks;
+            }
+            let start = offset.saturating_sub(context);
+            let (a_start, b_start) = match start.checked_sub(1) {
+                Some(before) => (
+                    edits[before].a_line.map_or(0, |line| line.number),
+                    edits[before].b_line.map_or(0, |line| line.number),
+                ),
+                None => (0, 0),
+            };
+            let mut end = offset;
+            let mut last_change = offset;
+            while end < edits.len() {
+                if edits[end].kind != EditKind::Eql {
+                    last_change = end;
+                } else if end - last_change > 2 * context {
+                    break;
+                }
+                end += 1;
+            }
+            let end = (last_change + context + 1).min(edits.len());
+            hunks.push(Hunk {
+                a_start,
+                b_start,
+                edits: edits[start..end].to_vec(),
+            });
+            offset = end;
+        }
+    }
+
+    /*
+        The "@@ -a,b +c,d @@" line, where a count of one is left out and
+        an empty side starts at the line before the hunk.
+    */
+    pub fn header(&self) -> String {
+        let a_lines = self.edits.iter().filter_map(|edit| edit.a_line);
+        let b_lines = self.edits.iter().filter_map(|edit| edit.b_line);
+        format!(
+            "@@ -{} +{} @@",
+            range(a_lines, self.a_start),
+            range(b_lines, self.b_start)
+        )
+    }
+}
+
+fn range<'a>(mut lines: impl Iterator<Item = Line<'a>>, before: usize) -> String {
+    let Some(first) = lines.next() else {
+        return format!("{},0", before);
+    };
+    let count = 1 + lines.count();
+    if count == 1 {
+        first.number.to_string()
+    } else {
+        format!("{},{}", first.number, count)
+    }
+}
+
+/*
+    Edits and hunks for two versions of a file's content.
+*/
+pub fn diff_hunks<'a>(a: &[&'a [u8]], b: &[&'a [u8]], context: usize) -> Vec<Hunk<'a>> {
+    Hunk::filter(&diff(a, b), context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A small xorshift generator, so the random cases are the same every run.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn numbered(prefix: &str, count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| format!("{} {}\n", prefix, i).into_bytes())
+            .collect()
+    }
+
+    fn slices(lines: &[Vec<u8>]) -> Vec<&[u8]> {
+        lines.iter().map(Vec::as_slice).collect()
+    }
+
+    // Checks the edits rebuild both sides and returns how many change lines.
+    fn assert_script(a: &[&[u8]], b: &[&[u8]]) -> usize {
+        let edits = diff(a, b);
+        let old: Vec<&[u8]> = edits
+            .iter()
+            .filter(|edit| edit.kind != EditKind::Ins)
+            .map(|edit| edit.a_line.unwrap().text)
+            .collect();
+        let new: Vec<&[u8]> = edits
+            .iter()
+            .filter(|edit| edit.kind != EditKind::Del)
+            .map(|edit| edit.b_line.unwrap().text)
+            .collect();
+        assert_eq!(old, a);
+        assert_eq!(new, b);
+        for pair in edits.windows(2) {
+            assert!(!(pair[0].kind == EditKind::Ins && pair[1].kind == EditKind::Del));
+        }
+        edits
+            .iter()
+            .filter(|edit| edit.kind != EditKind::Eql)
+            .count()
+    }
+
+    // The fewest insertions and deletions, from the longest common subsequence.
+    fn shortest_length(a: &[&[u8]], b: &[&[u8]]) -> usize {
+        let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        a.len() + b.len() - 2 * lcs[0][0]
+    }
+
+    #[test]
+    fn puts_deletions_before_insertions() {
+        let a: Vec<&[u8]> = vec![b"a\n", b"b\n", b"c\n"];
+        let b: Vec<&[u8]> = vec![b"a\n", b"x\n", b"c\n"];
+        let kinds: Vec<EditKind> = diff(&a, &b).iter().map(|edit| edit.kind).collect();
+        assert_eq!(
+            kinds,
+            [EditKind::Eql, EditKind::Del, EditKind::Ins, EditKind::Eql]
+        );
+    }
+
+    #[test]
+    fn handles_empty_sides() {
+        let lines: Vec<&[u8]> = vec![b"a\n", b"b\n"];
+        assert_eq!(assert_script(&[], &[]), 0);
+        assert_eq!(assert_script(&lines, &[]), 2);
+        assert_eq!(assert_script(&[], &lines), 2);
+    }
+
+    #[test]
+    fn finds_shortest_scripts() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let alphabet: Vec<&[u8]> = vec![b"a\n", b"b\n", b"c\n", b"d\n"];
+        for _ in 0..500 {
+            let a: Vec<&[u8]> = (0..random.below(20))
+                .map(|_| alphabet[random.below(alphabet.len())])
+                .collect();
+            let b: Vec<&[u8]> = (0..random.below(20))
+                .map(|_| alphabet[random.below(alphabet.len())])
+                .collect();
+            assert_eq!(assert_script(&a, &b), shortest_length(&a, &b));
+        }
+    }
+
+    #[test]
+    fn diffs_large_rewrites() {
+        let old = numbered("old", 20_000);
+        let new = numbered("new", 20_000);
+        assert_eq!(assert_script(&slices(&old), &slices(&new)), 40_000);
+
+        // Reversing shared lines leaves one in common; the search for it is
+        // quadratic in time, so this case is kept smaller.
+        let old = numbered("line", 2_000);
+        let reversed: Vec<Vec<u8>> = old.iter().rev().cloned().collect();
+        assert_eq!(assert_script(&slices(&old), &slices(&reversed)), 3_998);
+    }
+
+    #[test]
+    fn diffs_scattered_changes_in_large_files() {
+        let old = numbered("line", 20_000);
+        let mut new = old.clone();
+        for i in (0..new.len()).step_by(100) {
+            new[i] = format!("changed {}\n", i).into_bytes();
+        }
+        assert_eq!(assert_script(&slices(&old), &slices(&new)), 400);
+    }
+}
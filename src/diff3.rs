@@ -0,0 +1,521 @@
+use crate::diff::{self, EditKind};
+
+// Length of the "<<<<<<<", "=======" and ">>>>>>>" conflict markers.
+const MARKER_SIZE: usize = 7;
+// Conflicts separated by at most this many unchanged lines are joined.
+const MAX_GAP: usize = 3;
+
+/*
+    A run of lines that differs between base and one side, as line indexes
+    from 0: base_len lines of base at base_start became side_len lines of
+    the side at side_start.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Change {
+    base_start: usize,
+    base_len: usize,
+    side_start: usize,
+    side_len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    Conflict,
+    Ours,
+    Theirs,
+    // Both sides made the same change.
+    Both,
+}
+
+/*
+    A region where at least one side differs from base, with the lines it
+    covers in each version, like git's xdmerge_t.
+*/
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    resolution: Resolution,
+    base_start: usize,
+    base_len: usize,
+    ours_start: usize,
+    ours_len: usize,
+    theirs_start: usize,
+    theirs_len: usize,
+}
+
+impl Region {
+    // Built from signed offsets, as the arithmetic in combine needs them.
+    fn new(resolution: Resolution, starts: [isize; 3], lens: [isize; 3]) -> Self {
+        Region {
+            resolution,
+            base_start: starts[0] as usize,
+            base_len: lens[0] as usize,
+            ours_start: starts[1] as usize,
+            ours_len: lens[1] as usize,
+            theirs_start: starts[2] as usize,
+            theirs_len: lens[2] as usize,
+        }
+    }
+}
+
+/*
+    The result of merging two versions of a file. Conflicting regions are
+    written out between markers, ours first.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedFile {
+    pub data: Vec<u8>,
+    pub conflicts: usize,
+}
+
+impl MergedFile {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+/*
+    Merges the changes base -> ours and base -> theirs line by line, the
+    way git's xdl_merge does at its default level: changes to separate
+    lines are combined, identical changes are taken once, and overlapping
+    ones are narrowed down to the lines that really differ before being
+    marked as conflicts labelled with ours_label and theirs_label.
+*/
+pub fn merge(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> MergedFile {
+    let base_lines = diff::lines(base);
+    let ours_lines = diff::lines(ours);
+    let theirs_lines = diff::lines(theirs);
+
+    let mut regions = combine(&base_lines, &ours_lines, &theirs_lines);
+    refine_conflicts(&mut regions, &ours_lines, &theirs_lines);
+    simplify_conflicts(&mut regions);
+
+    let mut data = Vec::new();
+    let mut conflicts = 0;
+    let mut next = 0;
+    for region in &regions {
+        let ours_region = &ours_lines[region.ours_start..region.ours_start + region.ours_len];
+        let theirs_region =
+            &theirs_lines[region.theirs_start..region.theirs_start + region.theirs_len];
+        match region.resolution {
+            // Ours already has the change, so it is copied with what follows.
+            Resolution::Both => continue,
+            Resolution::Ours => {
+                copy_lines(&mut data, &ours_lines[next..region.ours_start], false);
+                copy_lines(&mut data, ours_region, false);
+            }
+            Resolution::Theirs => {
+                copy_lines(&mut data, &ours_lines[next..region.ours_start], false);
+                copy_lines(&mut data, theirs_region, false);
+            }
+            Resolution::Conflict => {
+                conflicts += 1;
+                copy_lines(&mut data, &ours_lines[next..region.ours_start], false);
+                write_marker(&mut data, b'<', Some(ours_label));
+                copy_lines(&mut data, ours_region, true);
+                write_marker(&mut data, b'=', None);
+                copy_lines(&mut data, theirs_region, true);
+                write_marker(&mut data, b'>', Some(theirs_label));
+            }
+        }
+        next = region.ours_start + region.ours_len;
+    }
+    copy_lines(&mut data, &ours_lines[next..], false);
+    MergedFile { data, conflicts }
+}
+
+/*
+    The runs of lines that differ between base and side, in order. Like
+    git, each run is slid as far down as equal lines allow, so that
+    ambiguous changes line up the same way on both sides of a merge.
+*/
+fn changes(base: &[&[u8]], side: &[&[u8]]) -> Vec<Change> {
+    let mut base_changed = vec![false; base.len()];
+    let mut side_changed = vec![false; side.len()];
+    let (mut base_index, mut side_index) = (0, 0);
+    for edit in diff::diff(base, side) {
+        match edit.kind {
+            EditKind::Eql => {
+                base_index += 1;
+                side_index += 1;
+            }
+            EditKind::Del => {
+                base_changed[base_index] = true;
+                base_index += 1;
+            }
+            EditKind::Ins => {
+                side_changed[side_index] = true;
+                side_index += 1;
+            }
+        }
+    }
+    compact(base, &mut base_changed, &side_changed);
+    compact(side, &mut side_changed, &base_changed);
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    loop {
+        while i < base.len() && j < side.len() && !base_changed[i] && !side_changed[j] {
+            i += 1;
+            j += 1;
+        }
+        if i >= base.len() && j >= side.len() {
+            return changes;
+        }
+        let (base_start, side_start) = (i, j);
+        while i < base.len() && base_changed[i] {
+            i += 1;
+        }
+        while j < side.len() && side_changed[j] {
+            j += 1;
+        }
+        changes.push(Change {
+            base_start,
+            base_len: i - base_start,
+            side_start,
+            side_len: j - side_start,
+        });
+    }
+}
+
+/*
+    A run of changed lines in one file, possibly empty: the gaps between
+    unchanged lines are groups too, so the groups of both files pair up.
+*/
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(changed: &[bool]) -> Self {
+        let mut group = Group { start: 0, end: 0 };
+        group.extend_down(changed);
+        group
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn extend_down(&mut self, changed: &[bool]) {
+        while self.end < changed.len() && changed[self.end] {
+            self.end += 1;
+        }
+    }
+
+    fn extend_up(&mut self, changed: &[bool]) {
+        while self.start > 0 && changed[self.start - 1] {
+            self.start -= 1;
+        }
+    }
+
+    fn next(&mut self, changed: &[bool]) -> bool {
+        if self.end == changed.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        self.extend_down(changed);
+        true
+    }
+
+    fn previous(&mut self, changed: &[bool]) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        self.extend_up(changed);
+        true
+    }
+
+    // Moves the group down a line when the line after it equals its first.
+    fn slide_down(&mut self, lines: &[&[u8]], changed: &mut [bool]) -> bool {
+        if self.end >= lines.len() || lines[self.start] != lines[self.end] {
+            return false;
+        }
+        changed[self.start] = false;
+        changed[self.end] = true;
+        self.start += 1;
+        self.end += 1;
+        self.extend_down(changed);
+        true
+    }
+
+    // Moves the group up a line when the line before it equals its last.
+    fn slide_up(&mut self, lines: &[&[u8]], changed: &mut [bool]) -> bool {
+        if self.start == 0 || lines[self.start - 1] != lines[self.end - 1] {
+            return false;
+        }
+        self.start -= 1;
+        changed[self.start] = true;
+        self.end -= 1;
+        changed[self.end] = false;
+        self.extend_up(changed);
+        true
+    }
+}
+
+/*
+    git's xdl_change_compact without the indent heuristic: slides every
+    group of changed lines down as far as it goes, merging it with groups
+    it runs into, unless sliding it back up lines it up with a change in
+    the other file.
+*/
+fn compact(lines: &[&[u8]], changed: &mut [bool], other_changed: &[bool]) {
+    let mut group = Group::first(changed);
+    let mut other = Group::first(other_changed);
+    loop {
+        if !group.is_empty() {
+            let mut earliest_end;
+            let mut end_matching_other;
+            loop {
+                let size = group.end - group.start;
+                end_matching_other = None;
+                while group.slide_up(lines, changed) {
+                    other.previous(other_changed);
+                }
+                earliest_end = group.end;
+                if !other.is_empty() {
+                    end_matching_other = Some(group.end);
+                }
+                while group.slide_down(lines, changed) {
+                    other.next(other_changed);
+                    if !other.is_empty() {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+            if group.end != earliest_end && end_matching_other.is_some() {
+                while other.is_empty() {
+                    group.slide_up(lines, changed);
+                    other.previous(other_changed);
+                }
+            }
+        }
+        if !group.next(changed) {
+            return;
+        }
+        other.next(other_changed);
+    }
+}
+
+/*
+    Walks the changes of both sides in base order. A change that ends
+    before the other side's next one starts is taken as it is; changes
+    that touch or overlap become a conflict spanning both, unless they
+    are the same change.
+*/
+fn combine(base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<Region> {
+    let ours_changes = changes(base, ours);
+    let theirs_changes = changes(base, theirs);
+    let mut regions = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < ours_changes.len() && j < theirs_changes.len() {
+        let (x1, x2) = (signed(&ours_changes[i]), signed(&theirs_changes[j]));
+        if x1[0] + x1[1] < x2[0] {
+            let theirs_start = x2[2] - x2[0] + x1[0];
+            let region = Region::new(
+                Resolution::Ours,
+                [x1[0], x1[2], theirs_start],
+                [x1[1], x1[3], x1[1]],
+            );
+            append(&mut regions, region);
+            i += 1;
+            continue;
+        }
+        if x2[0] + x2[1] < x1[0] {
+            let ours_start = x1[2] - x1[0] + x2[0];
+            let region = Region::new(
+                Resolution::Theirs,
+                [x2[0], ours_start, x2[2]],
+                [x2[1], x2[1], x2[3]],
+            );
+            append(&mut regions, region);
+            j += 1;
+            continue;
+        }
+        let same = x1[0] == x2[0]
+            && x1[1] == x2[1]
+            && x1[3] == x2[3]
+            && ours[x1[2] as usize..(x1[2] + x1[3]) as usize]
+                == theirs[x2[2] as usize..(x2[2] + x2[3]) as usize];
+        if !same {
+            let off = x1[0] - x2[0];
+            let ffo = off + x1[1] - x2[1];
+            let (mut i0, mut i1, mut i2) = (x1[0], x1[2], x2[2]);
+            if off > 0 {
+                i0 -= off;
+                i1 -= off;
+            } else {
+                i2 += off;
+            }
+            let mut chg0 = x1[0] + x1[1] - i0;
+            let mut chg1 = x1[2] + x1[3] - i1;
+            let mut chg2 = x2[2] + x2[3] - i2;
+            if ffo < 0 {
+                chg0 -= ffo;
+                chg1 -= ffo;
+            } else {
+                chg2 += ffo;
+            }
+            append(
+                &mut regions,
+                Region::new(Resolution::Conflict, [i0, i1, i2], [chg0, chg1, chg2]),
+            );
+        }
+        let (end1, end2) = (x1[0] + x1[1], x2[0] + x2[1]);
+        if end1 >= end2 {
+            j += 1;
+        }
+        if end2 >= end1 {
+            i += 1;
+        }
+    }
+
+    let ours_delta = ours.len() as isize - base.len() as isize;
+    let theirs_delta = theirs.len() as isize - base.len() as isize;
+    for change in &ours_changes[i..] {
+        let x1 = signed(change);
+        let region = Region::new(
+            Resolution::Ours,
+            [x1[0], x1[2], x1[0] + theirs_delta],
+            [x1[1], x1[3], x1[1]],
+        );
+        append(&mut regions, region);
+    }
+    for change in &theirs_changes[j..] {
+        let x2 = signed(change);
+        let region = Region::new(
+            Resolution::Theirs,
+            [x2[0], x2[0] + ours_delta, x2[2]],
+            [x2[1], x2[1], x2[3]],
+        );
+        append(&mut regions, region);
+    }
+    regions
+}
+
+fn signed(change: &Change) -> [isize; 4] {
+    [
+        change.base_start as isize,
+        change.base_len as isize,
+        change.side_start as isize,
+        change.side_len as isize,
+    ]
+}
+
+/*
+    Adds a region, growing the last one instead if the two overlap on
+    either side. Regions resolved differently grow into a conflict.
+*/
+fn append(regions: &mut Vec<Region>, region: Region) {
+    if let Some(last) = regions.last_mut() {
+        if region.ours_start <= last.ours_start + last.ours_len
+            || region.theirs_start <= last.theirs_start + last.theirs_len
+        {
+            if region.resolution != last.resolution {
+                last.resolution = Resolution::Conflict;
+            }
+            last.base_len = region.base_start + region.base_len - last.base_start;
+            last.ours_len = region.ours_start + region.ours_len - last.ours_start;
+            last.theirs_len = region.theirs_start + region.theirs_len - last.theirs_start;
+            return;
+        }
+    }
+    regions.push(region);
+}
+
+/*
+    Diffs the two sides of each conflict against each other, so that only
+    the lines where they disagree stay in conflict. A conflict where both
+    sides turn out equal is a change made on both sides.
+*/
+fn refine_conflicts(regions: &mut Vec<Region>, ours: &[&[u8]], theirs: &[&[u8]]) {
+    let mut refined = Vec::with_capacity(regions.len());
+    for region in regions.drain(..) {
+        if region.resolution != Resolution::Conflict
+            || region.ours_len == 0
+            || region.theirs_len == 0
+        {
+            refined.push(region);
+            continue;
+        }
+        let ours_region = &ours[region.ours_start..region.ours_start + region.ours_len];
+        let theirs_region = &theirs[region.theirs_start..region.theirs_start + region.theirs_len];
+        let differences = changes(ours_region, theirs_region);
+        if differences.is_empty() {
+            refined.push(Region {
+                resolution: Resolution::Both,
+                ..region
+            });
+            continue;
+        }
+        for difference in differences {
+            refined.push(Region {
+                ours_start: region.ours_start + difference.base_start,
+                ours_len: difference.base_len,
+                theirs_start: region.theirs_start + difference.side_start,
+                theirs_len: difference.side_len,
+                ..region
+            });
+        }
+    }
+    *regions = refined;
+}
+
+/*
+    Joins conflicts separated by only a few unchanged lines, which reads
+    more easily than several small conflicts.
+*/
+fn simplify_conflicts(regions: &mut Vec<Region>) {
+    let mut i = 0;
+    while i + 1 < regions.len() {
+        let (current, next) = (regions[i], regions[i + 1]);
+        let gap = next
+            .ours_start
+            .saturating_sub(current.ours_start + current.ours_len);
+        if current.resolution != Resolution::Conflict
+            || next.resolution != Resolution::Conflict
+            || gap > MAX_GAP
+        {
+            i += 1;
+            continue;
+        }
+        regions[i].ours_len = next.ours_start + next.ours_len - current.ours_start;
+        regions[i].theirs_len = next.theirs_start + next.theirs_len - current.theirs_start;
+        regions.remove(i + 1);
+    }
+}
+
+/*
+    Appends lines to data. Inside a conflict the last line always gets a
+    newline, so the marker after it starts a line of its own.
+*/
+fn copy_lines(data: &mut Vec<u8>, lines: &[&[u8]], add_newline: bool) {
+    for line in lines {
+        data.extend_from_slice(line);
+    }
+    if add_newline && lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        data.push(b'\n');
+    }
+}
+
+fn write_marker(data: &mut Vec<u8>, marker: u8, label: Option<&str>) {
+    data.extend(std::iter::repeat_n(marker, MARKER_SIZE));
+    if let Some(label) = label {
+        data.push(b' ');
+        data.extend_from_slice(label.as_bytes());
+    }
+    data.push(b'\n');
+}
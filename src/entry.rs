@@ -4,34 +4,47 @@ use std::{
     path::{Path, PathBuf},
 };
 
-#[derive(Debug, Clone)]
+use crate::object_id::ObjectId;
+
+pub const REGULAR_MODE: u32 = 0o100644;
+pub const EXECUTABLE_MODE: u32 = 0o100755;
+pub const DIRECTORY_MODE: u32 = 0o40000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Entry {
     pub filename: String,
     pub path: PathBuf,
-    pub object_id: String,
-    pub stat: Metadata,
+    pub object_id: ObjectId,
+    pub mode: u32,
 }
 
 impl Entry {
-    pub fn new(filename: String, path: PathBuf, object_id: &str, stat: Metadata) -> Self {
+    pub fn new(filename: String, path: PathBuf, object_id: ObjectId, mode: u32) -> Self {
         Entry {
             filename,
             path,
-            object_id: object_id.to_string(),
-            stat,
+            object_id,
+            mode,
         }
     }
 
-    pub fn mode(&self) -> String {
-        let permissions = self.stat.permissions();
+    pub fn mode_for_stat(stat: &Metadata) -> u32 {
         // Check if owner has executable permission set.
-        if permissions.mode() & 0o100 != 0 {
-            String::from("100755")
+        if stat.permissions().mode() & 0o100 != 0 {
+            EXECUTABLE_MODE
         } else {
-            String::from("100644")
+            REGULAR_MODE
         }
     }
 
+    pub fn mode(&self) -> String {
+        format!("{:o}", self.mode)
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.mode == DIRECTORY_MODE
+    }
+
     pub fn ancestor_directories(&self) -> Vec<PathBuf> {
         let ancestors = Path::new(self.path.as_path())
             .ancestors()
@@ -0,0 +1,402 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{self, Config};
+
+/*
+    A single line of an ignore file, after stripping the "!" negation,
+    the trailing "/" of directory-only patterns and the leading "/" of
+    anchored ones.
+*/
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub text: String,
+    pub line_number: usize,
+    glob: String,
+    negated: bool,
+    directory_only: bool,
+    anchored: bool,
+}
+
+impl Pattern {
+    pub fn parse(line: &str, line_number: usize) -> Option<Self> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let text = trim_trailing_spaces(line);
+        let mut glob = text.as_str();
+        let mut negated = false;
+        if let Some(rest) = glob.strip_prefix('!') {
+            negated = true;
+            glob = rest;
+        }
+        let mut directory_only = false;
+        if let Some(rest) = glob.strip_suffix('/') {
+            directory_only = true;
+            glob = rest;
+        }
+        let anchored = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Pattern {
+            text: text.clone(),
+            line_number,
+            glob: glob.to_string(),
+            negated,
+            directory_only,
+            anchored,
+        })
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /*
+        path is relative to the directory holding the ignore file.
+    */
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            wildmatch(self.glob.as_bytes(), path.as_bytes())
+        } else {
+            let basename = path.rsplit('/').next().unwrap_or(path);
+            wildmatch(self.glob.as_bytes(), basename.as_bytes())
+        }
+    }
+}
+
+/*
+    Trailing spaces are dropped unless escaped with a backslash.
+*/
+fn trim_trailing_spaces(line: &str) -> String {
+    let mut end = line.len();
+    while end > 0 && line.as_bytes()[end - 1] == b' ' {
+        if end > 1 && line.as_bytes()[end - 2] == b'\\' {
+            break;
+        }
+        end -= 1;
+    }
+    line[..end].to_string()
+}
+
+/*
+    The patterns read from one ignore file. base is the directory the
+    patterns are relative to, "" for the workspace root.
+*/
+#[derive(Debug, Clone)]
+pub struct PatternList {
+    pub source: String,
+    pub base: String,
+    pub patterns: Vec<Pattern>,
+}
+
+impl PatternList {
+    pub fn parse(source: String, base: String, content: &str) -> Self {
+        let patterns = content
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| Pattern::parse(line, i + 1))
+            .collect();
+        PatternList {
+            source,
+            base,
+            patterns,
+        }
+    }
+
+    fn read(path: &Path, source: String, base: String) -> io::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(content) => Ok(Some(Self::parse(
+                source,
+                base,
+                &String::from_utf8_lossy(&content),
+            ))),
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                    || err.kind() == io::ErrorKind::NotADirectory =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /*
+        The last pattern in the file that matches wins.
+    */
+    fn last_match(&self, path: &str, is_dir: bool) -> Option<&Pattern> {
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            path.strip_prefix(&self.base)?.strip_prefix('/')?
+        };
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(relative, is_dir))
+    }
+}
+
+/*
+    The pattern deciding whether a path is ignored, and the file it came from.
+*/
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub source: String,
+    pub pattern: Pattern,
+}
+
+impl Match {
+    pub fn is_ignored(&self) -> bool {
+        !self.pattern.negated
+    }
+}
+
+/*
+    Applies gitignore rules to workspace paths. .gitignore files nearer the
+    path take precedence over those in parent directories, which take
+    precedence over .git/info/exclude, then core.excludesFile.
+    Per-directory files are read lazily and cached.
+*/
+#[derive(Debug)]
+pub struct Ignore {
+    root: PathBuf,
+    global: Vec<PatternList>,
+    per_directory: RefCell<HashMap<String, Option<PatternList>>>,
+}
+
+impl Ignore {
+    pub fn new(root: PathBuf) -> Self {
+        let git_path = root.join(".git");
+        let mut global = Vec::new();
+
+        let exclude = git_path.join("info").join("exclude");
+        if let Ok(Some(list)) =
+            PatternList::read(&exclude, String::from(".git/info/exclude"), String::new())
+        {
+            global.push(list);
+        }
+
+        let excludes_file = Config::load(&git_path)
+            .ok()
+            .and_then(|config| config.get_path("core.excludesFile"))
+            .or_else(|| config::xdg_config_home().map(|dir| dir.join("git").join("ignore")));
+        if let Some(path) = excludes_file {
+            let source = path.display().to_string();
+            if let Ok(Some(list)) = PatternList::read(&path, source, String::new()) {
+                global.push(list);
+            }
+        }
+
+        Ignore {
+            root,
+            global,
+            per_directory: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /*
+        Finds the pattern deciding path's fate, looking only at the path itself.
+        Callers walking the workspace never descend into ignored directories,
+        so parents have already been checked.
+    */
+    pub fn match_path(&self, path: &str, is_dir: bool) -> Option<Match> {
+        let mut dirs = vec![String::new()];
+        for (i, b) in path.bytes().enumerate() {
+            if b == b'/' {
+                dirs.push(path[..i].to_string());
+            }
+        }
+        for dir in dirs.iter().rev() {
+            if let Some(found) = self.match_in_directory(dir, path, is_dir) {
+                return Some(found);
+            }
+        }
+        self.global.iter().find_map(|list| {
+            list.last_match(path, is_dir).map(|pattern| Match {
+                source: list.source.clone(),
+                pattern: pattern.clone(),
+            })
+        })
+    }
+
+    /*
+        Like match_path, but a path inside an ignored directory is ignored by
+        the directory's pattern, since git cannot re-include it.
+    */
+    pub fn match_with_parents(&self, path: &str, is_dir: bool) -> Option<Match> {
+        for (i, b) in path.bytes().enumerate() {
+            if b == b'/' {
+                if let Some(found) = self.match_path(&path[..i], true) {
+                    if found.is_ignored() {
+                        return Some(found);
+                    }
+                }
+            }
+        }
+        self.match_path(path, is_dir)
+    }
+
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.match_path(path, is_dir)
+            .is_some_and(|found| found.is_ignored())
+    }
+
+    fn match_in_directory(&self, dir: &str, path: &str, is_dir: bool) -> Option<Match> {
+        let mut cache = self.per_directory.borrow_mut();
+        let list = cache.entry(dir.to_string()).or_insert_with(|| {
+            let source = if dir.is_empty() {
+                String::from(".gitignore")
+            } else {
+                format!("{}/.gitignore", dir)
+            };
+            PatternList::read(&self.root.join(&source), source, dir.to_string())
+                .ok()
+                .flatten()
+        });
+        let list = list.as_ref()?;
+        list.last_match(path, is_dir).map(|pattern| Match {
+            source: list.source.clone(),
+            pattern: pattern.clone(),
+        })
+    }
+}
+
+/*
+    Glob matching with git's wildmatch rules for pathnames: "*" and "?" do not
+    match "/", while "**" between slashes (or at either end) matches any
+    number of directories.
+*/
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                let mut end = p;
+                while end < pattern.len() && pattern[end] == b'*' {
+                    end += 1;
+                }
+                let rest = &pattern[end..];
+                let double = end - p >= 2
+                    && (p == 0 || pattern[p - 1] == b'/')
+                    && (rest.is_empty() || rest[0] == b'/');
+                if double {
+                    if rest.is_empty() {
+                        return true;
+                    }
+                    // "**/" matches zero or more leading directories.
+                    let after = &rest[1..];
+                    if wildmatch(after, &text[t..]) {
+                        return true;
+                    }
+                    return (t..text.len())
+                        .any(|i| text[i] == b'/' && wildmatch(after, &text[i + 1..]));
+                }
+                if rest.is_empty() {
+                    return !text[t..].contains(&b'/');
+                }
+                for i in t..=text.len() {
+                    if wildmatch(rest, &text[i..]) {
+                        return true;
+                    }
+                    if i < text.len() && text[i] == b'/' {
+                        break;
+                    }
+                }
+                return false;
+            }
+            b'?' => {
+                if t >= text.len() || text[t] == b'/' {
+                    return false;
+                }
+                p += 1;
+                t += 1;
+            }
+            b'[' => {
+                if t >= text.len() {
+                    return false;
+                }
+                match match_class(pattern, p + 1, text[t]) {
+                    Some((true, next)) => {
+                        p = next;
+                        t += 1;
+                    }
+                    Some((false, _)) => return false,
+                    // No closing bracket, so "[" is literal.
+                    None => {
+                        if text[t] != b'[' {
+                            return false;
+                        }
+                        p += 1;
+                        t += 1;
+                    }
+                }
+            }
+            c => {
+                let (literal, width) = if c == b'\\' && p + 1 < pattern.len() {
+                    (pattern[p + 1], 2)
+                } else {
+                    (c, 1)
+                };
+                if t >= text.len() || text[t] != literal {
+                    return false;
+                }
+                p += width;
+                t += 1;
+            }
+        }
+    }
+    t == text.len()
+}
+
+/*
+    Matches c against the bracket expression starting at pattern[start]
+    (just after the "["). Returns whether it matched and the index after
+    the closing "]", or None if the bracket is never closed.
+*/
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<(bool, usize)> {
+    let mut i = start;
+    let negated = i < pattern.len() && (pattern[i] == b'!' || pattern[i] == b'^');
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let mut low = pattern[i];
+        if low == b']' && !first {
+            return Some((matched != negated && c != b'/', i + 1));
+        }
+        first = false;
+        if low == b'\\' && i + 1 < pattern.len() {
+            i += 1;
+            low = pattern[i];
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let mut high = pattern[i + 2];
+            i += 2;
+            if high == b'\\' && i + 1 < pattern.len() {
+                i += 1;
+                high = pattern[i];
+            }
+            if low <= c && c <= high {
+                matched = true;
+            }
+        } else if low == c {
+            matched = true;
+        }
+        i += 1;
+    }
+    None
+}
@@ -0,0 +1,546 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, Metadata},
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::entry::Entry;
+use crate::lockfile::LockFile;
+use crate::object_id::ObjectId;
+use crate::utils;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 12;
+const CHECKSUM_SIZE: usize = 20;
+// ctime .. oid (10 * 4 bytes + 20 bytes) plus the 2 byte flags field.
+const ENTRY_FIXED_SIZE: usize = 62;
+const ENTRY_BLOCK: usize = 8;
+const MAX_PATH_SIZE: usize = 0xfff;
+
+/*
+    One record of the index file, holding the stat data git uses to decide
+    whether a workspace file needs rehashing.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub object_id: ObjectId,
+    pub flags: u16,
+    pub path: String,
+}
+
+impl IndexEntry {
+    pub fn create(path: &str, object_id: ObjectId, stat: &Metadata) -> Self {
+        let mut entry = IndexEntry {
+            ctime: 0,
+            ctime_nsec: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            dev: 0,
+            ino: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            object_id,
+            flags: path.len().min(MAX_PATH_SIZE) as u16,
+            path: path.to_string(),
+        };
+        entry.update_stat(stat);
+        entry
+    }
+
+    /*
+        One side of a conflicted path. git writes these without stat
+        data, since the workspace holds neither version.
+    */
+    pub fn for_stage(entry: &Entry, stage: u8) -> Self {
+        let path = entry.path.to_string_lossy().to_string();
+        IndexEntry {
+            ctime: 0,
+            ctime_nsec: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            dev: 0,
+            ino: 0,
+            mode: entry.mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            object_id: entry.object_id,
+            flags: (stage as u16) << 12 | path.len().min(MAX_PATH_SIZE) as u16,
+            path,
+        }
+    }
+
+    pub fn update_stat(&mut self, stat: &Metadata) {
+        self.ctime = stat.ctime() as u32;
+        self.ctime_nsec = stat.ctime_nsec() as u32;
+        self.mtime = stat.mtime() as u32;
+        self.mtime_nsec = stat.mtime_nsec() as u32;
+        self.dev = stat.dev() as u32;
+        self.ino = stat.ino() as u32;
+        self.mode = Entry::mode_for_stat(stat);
+        self.uid = stat.uid();
+        self.gid = stat.gid();
+        self.size = stat.size() as u32;
+    }
+
+    /*
+        Merge stage stored in bits 12-13 of the flags, 0 for a normal entry.
+    */
+    pub fn stage(&self) -> u8 {
+        ((self.flags >> 12) & 0x3) as u8
+    }
+
+    pub fn basename(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /*
+        Directories containing this entry, outermost first: "a/b/c.txt"
+        yields "a" and "a/b".
+    */
+    pub fn parent_directories(&self) -> Vec<String> {
+        let mut dirs = Vec::new();
+        for (i, b) in self.path.bytes().enumerate() {
+            if b == b'/' {
+                dirs.push(self.path[..i].to_string());
+            }
+        }
+        dirs
+    }
+
+    fn key(&self) -> (String, u8) {
+        (self.path.clone(), self.stage())
+    }
+
+    fn parse(data: &[u8]) -> io::Result<(Self, usize)> {
+        if data.len() < ENTRY_FIXED_SIZE {
+            return Err(utils::invalid_data("truncated index entry"));
+        }
+        let word = |i: usize| u32::from_be_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        let object_id = ObjectId::from_bytes(&data[40..60])
+            .map_err(|err| utils::invalid_data(&err.to_string()))?;
+        let flags = u16::from_be_bytes([data[60], data[61]]);
+
+        let nul = data[ENTRY_FIXED_SIZE..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| utils::invalid_data("unterminated index entry path"))?;
+        let path = String::from_utf8(data[ENTRY_FIXED_SIZE..ENTRY_FIXED_SIZE + nul].to_vec())
+            .map_err(|_| utils::invalid_data("index entry path is not valid UTF-8"))?;
+
+        let entry = IndexEntry {
+            ctime: word(0),
+            ctime_nsec: word(1),
+            mtime: word(2),
+            mtime_nsec: word(3),
+            dev: word(4),
+            ino: word(5),
+            mode: word(6),
+            uid: word(7),
+            gid: word(8),
+            size: word(9),
+            object_id,
+            flags,
+            path,
+        };
+        Ok((entry, Self::padded_size(nul)))
+    }
+
+    /*
+        Entries are NUL padded to a multiple of 8 bytes, always with
+        at least one NUL terminating the path.
+    */
+    fn padded_size(path_len: usize) -> usize {
+        let unpadded = ENTRY_FIXED_SIZE + path_len;
+        (unpadded / ENTRY_BLOCK + 1) * ENTRY_BLOCK
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::padded_size(self.path.len()));
+        for word in [
+            self.ctime,
+            self.ctime_nsec,
+            self.mtime,
+            self.mtime_nsec,
+            self.dev,
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+        ] {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes.extend_from_slice(self.object_id.as_bytes());
+        bytes.extend_from_slice(&self.flags.to_be_bytes());
+        bytes.extend_from_slice(self.path.as_bytes());
+        bytes.resize(Self::padded_size(self.path.len()), 0);
+        bytes
+    }
+}
+
+impl From<&IndexEntry> for Entry {
+    fn from(index_entry: &IndexEntry) -> Self {
+        Entry::new(
+            index_entry.basename().to_string(),
+            PathBuf::from(&index_entry.path),
+            index_entry.object_id,
+            index_entry.mode,
+        )
+    }
+}
+
+/*
+    The staging area stored in .git/index using git's DIRC version 2 format.
+    Entries are kept sorted by path and stage, which is the order git requires
+    on disk and the order Tree::build expects.
+*/
+pub struct Index {
+    pathname: PathBuf,
+    entries: BTreeMap<(String, u8), IndexEntry>,
+    lockfile: LockFile,
+    changed: bool,
+}
+
+impl Index {
+    pub fn new(pathname: PathBuf) -> Self {
+        Index {
+            lockfile: LockFile::new(pathname.clone()),
+            pathname,
+            entries: BTreeMap::new(),
+            changed: false,
+        }
+    }
+
+    /*
+        Takes the index lock before reading, so the entries can be modified
+        and saved with write_updates.
+    */
+    pub fn load_for_update(&mut self) -> io::Result<()> {
+        match self.lockfile.hold_for_update() {
+            Ok(true) => self.load(),
+            Ok(false) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "Unable to create '{}': File exists.",
+                    self.lockfile.lock_path().display()
+                ),
+            )),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    pub fn load(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.changed = false;
+        let data = match fs::read(&self.pathname) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(utils::invalid_data("index file is too short"));
+        }
+
+        let (content, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if utils::hash_content(content).as_bytes() != checksum {
+            return Err(utils::invalid_data(
+                "Checksum does not match value stored on disk",
+            ));
+        }
+
+        if &content[0..4] != SIGNATURE {
+            return Err(utils::invalid_data("bad index file signature"));
+        }
+        let version = u32::from_be_bytes(content[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(utils::invalid_data(&format!(
+                "index file version {} is not supported",
+                version
+            )));
+        }
+        let count = u32::from_be_bytes(content[8..12].try_into().unwrap());
+
+        let mut offset = HEADER_SIZE;
+        for _ in 0..count {
+            let (entry, size) = IndexEntry::parse(&content[offset..])?;
+            offset += size;
+            // parse only checks the path is terminated, not that its
+            // padding fits.
+            if offset > content.len() {
+                return Err(utils::invalid_data("truncated index entry"));
+            }
+            self.entries.insert(entry.key(), entry);
+        }
+        // Anything after the entries is an extension (TREE, REUC, ...), which
+        // we do not understand; it is dropped the next time the index is written.
+        Ok(())
+    }
+
+    pub fn write_updates(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return self.release_lock();
+        }
+        let mut content = Vec::new();
+        content.extend_from_slice(SIGNATURE);
+        content.extend_from_slice(&VERSION.to_be_bytes());
+        content.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in self.entries.values() {
+            content.extend_from_slice(&entry.to_bytes());
+        }
+        let checksum = utils::hash_content(&content);
+        content.extend_from_slice(checksum.as_bytes());
+
+        if let Err(err) = self.lockfile.write(&content) {
+            self.release_lock()?;
+            return Err(io::Error::other(err));
+        }
+        self.lockfile.commit().map_err(io::Error::other)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    pub fn release_lock(&mut self) -> io::Result<()> {
+        match self.lockfile.rollback() {
+            Ok(()) | Err(crate::lockfile::LockfileError::StaleLock) => Ok(()),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    pub fn add(&mut self, path: &Path, object_id: ObjectId, stat: &Metadata) {
+        let entry = IndexEntry::create(&utils::path_to_index_key(path), object_id, stat);
+        self.discard_conflicts(&entry);
+        self.entries.insert(entry.key(), entry);
+        self.changed = true;
+    }
+
+    /*
+        Replaces whatever is at path with the base, ours and theirs versions
+        of a conflict as stages 1, 2 and 3, leaving out missing ones.
+    */
+    pub fn add_conflict_set(&mut self, path: &str, entries: [Option<&Entry>; 3]) {
+        self.remove_entry(path);
+        for (stage, entry) in (1..).zip(entries) {
+            if let Some(entry) = entry {
+                let entry = IndexEntry::for_stage(entry, stage);
+                self.entries.insert(entry.key(), entry);
+            }
+        }
+        self.changed = true;
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.keys().any(|(_, stage)| *stage > 0)
+    }
+
+    /*
+        Paths with entries at a merge stage, each listed once.
+    */
+    pub fn conflict_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|(_, stage)| *stage > 0)
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.dedup();
+        paths
+    }
+
+    /*
+        Removes the entry at path, or every entry below it if path is a directory.
+    */
+    pub fn remove(&mut self, path: &Path) {
+        let path = utils::path_to_index_key(path);
+        self.remove_entry(&path);
+        self.remove_children(&path);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    /*
+        Paths of the entries at or below path, every entry for the root "".
+    */
+    pub fn paths_under(&self, path: &str) -> Vec<String> {
+        let prefix = format!("{}/", path);
+        let mut paths: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|(p, _)| path.is_empty() || p == path || p.starts_with(&prefix))
+            .map(|(p, _)| p.clone())
+            .collect();
+        paths.dedup();
+        paths
+    }
+
+    pub fn entry_for_path(&self, path: &str) -> Option<&IndexEntry> {
+        self.entries.get(&(path.to_string(), 0))
+    }
+
+    pub fn update_entry_stat(&mut self, path: &str, stat: &Metadata) {
+        if let Some(entry) = self.entries.get_mut(&(path.to_string(), 0)) {
+            entry.update_stat(stat);
+            self.changed = true;
+        }
+    }
+
+    /*
+        True if path is a tracked file, or a directory containing tracked files.
+    */
+    pub fn is_tracked(&self, path: &str) -> bool {
+        self.is_tracked_file(path) || self.has_children(path)
+    }
+
+    pub fn is_tracked_file(&self, path: &str) -> bool {
+        self.entries.keys().any(|(p, _)| p == path)
+    }
+
+    fn has_children(&self, path: &str) -> bool {
+        let prefix = format!("{}/", path);
+        self.entries
+            .range((prefix.clone(), 0)..)
+            .next()
+            .is_some_and(|((p, _), _)| p.starts_with(&prefix))
+    }
+
+    /*
+        A file replaces any directory of the same name and vice versa, and
+        a resolved entry replaces the stages of a conflict at its path.
+    */
+    fn discard_conflicts(&mut self, entry: &IndexEntry) {
+        for parent in entry.parent_directories() {
+            self.remove_entry(&parent);
+        }
+        self.remove_children(&entry.path);
+        self.remove_entry(&entry.path);
+    }
+
+    fn remove_entry(&mut self, path: &str) {
+        let before = self.entries.len();
+        self.entries.retain(|(p, _), _| p != path);
+        if self.entries.len() != before {
+            self.changed = true;
+        }
+    }
+
+    fn remove_children(&mut self, path: &str) {
+        let prefix = format!("{}/", path);
+        let before = self.entries.len();
+        self.entries.retain(|(p, _), _| !p.starts_with(&prefix));
+        if self.entries.len() != before {
+            self.changed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Paths of every length up to three blocks, so each amount of padding,
+    // including a full block of NULs, is written at least once.
+    fn sample_paths() -> Vec<String> {
+        (1..=3 * ENTRY_BLOCK)
+            .map(|len| format!("d/{}", "f".repeat(len)))
+            .collect()
+    }
+
+    fn write_index(dir: &Path, paths: &[String]) -> PathBuf {
+        let stat = fs::metadata(file!()).unwrap();
+        let pathname = dir.join("index");
+        let mut index = Index::new(pathname.clone());
+        index.load_for_update().unwrap();
+        for (i, path) in paths.iter().enumerate() {
+            let object_id = ObjectId::from_bytes(&[i as u8; 20]).unwrap();
+            index.add(Path::new(path), object_id, &stat);
+        }
+        index.write_updates().unwrap();
+        pathname
+    }
+
+    // Rewrites an index file's contents with a fresh checksum.
+    fn rewrite(pathname: &Path, content: &[u8]) {
+        let mut data = content.to_vec();
+        data.extend_from_slice(utils::hash_content(content).as_bytes());
+        fs::write(pathname, data).unwrap();
+    }
+
+    #[test]
+    fn write_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = sample_paths();
+        let pathname = write_index(dir.path(), &paths);
+
+        let stat = fs::metadata(file!()).unwrap();
+        let expected: Vec<IndexEntry> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                IndexEntry::create(path, ObjectId::from_bytes(&[i as u8; 20]).unwrap(), &stat)
+            })
+            .collect();
+        let mut index = Index::new(pathname);
+        index.load().unwrap();
+        assert_eq!(index.entries().cloned().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn entries_are_padded_to_blocks() {
+        let object_id = ObjectId::from_bytes(&[1; 20]).unwrap();
+        let stat = fs::metadata(file!()).unwrap();
+        for path in sample_paths() {
+            let bytes = IndexEntry::create(&path, object_id, &stat).to_bytes();
+            let unpadded = ENTRY_FIXED_SIZE + path.len();
+            assert_eq!(bytes.len() % ENTRY_BLOCK, 0, "{}", path);
+            assert!(bytes.len() > unpadded && bytes.len() <= unpadded + ENTRY_BLOCK);
+            assert!(bytes[unpadded..].iter().all(|&b| b == 0));
+        }
+        // 62 + 8n - 2 bytes leaves 4 bytes of padding, and 62 + 8n + 2 a whole block.
+        let bytes = IndexEntry::create("abcdef", object_id, &stat).to_bytes();
+        assert_eq!(bytes.len(), 72);
+        let bytes = IndexEntry::create("abcdefghij", object_id, &stat).to_bytes();
+        assert_eq!(bytes.len(), 80);
+    }
+
+    #[test]
+    fn load_rejects_truncated_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let pathname = write_index(dir.path(), &sample_paths());
+        let data = fs::read(&pathname).unwrap();
+        let content = &data[..data.len() - CHECKSUM_SIZE];
+        for len in HEADER_SIZE..content.len() {
+            rewrite(&pathname, &content[..len]);
+            let err = Index::new(pathname.clone()).load().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn load_rejects_entry_count_past_the_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let pathname = write_index(dir.path(), &sample_paths());
+        let data = fs::read(&pathname).unwrap();
+        let mut content = data[..data.len() - CHECKSUM_SIZE].to_vec();
+        content[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        rewrite(&pathname, &content);
+        let err = Index::new(pathname).load().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,33 @@
+pub mod author;
+pub mod blob;
+pub mod command;
+pub mod commit;
+pub mod commit_graph;
+pub mod config;
+pub mod database;
+pub mod delta;
+pub mod diff;
+pub mod diff3;
+pub mod entry;
+pub mod ignore;
+pub mod index;
+pub mod lockfile;
+pub mod merge;
+pub mod merge_base;
+pub mod migration;
+pub mod object_id;
+pub mod pack;
+pub mod pack_writer;
+pub mod pending_commit;
+pub mod refs;
+pub mod rename;
+pub mod repository;
+pub mod rev_list;
+pub mod revision;
+pub mod status;
+pub mod tag;
+pub mod traits;
+pub mod tree;
+pub mod tree_diff;
+pub mod utils;
+pub mod workspace;
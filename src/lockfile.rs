@@ -10,14 +10,19 @@ pub enum LockfileError {
     MissingParent,
     NoPermission,
     StaleLock,
+    Io(io::Error),
 }
 
 impl error::Error for LockfileError {}
 
 impl fmt::Display for LockfileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let err = self;
-        write!(f, "{}", err)
+        match self {
+            LockfileError::MissingParent => write!(f, "lock file directory does not exist"),
+            LockfileError::NoPermission => write!(f, "permission denied creating lock file"),
+            LockfileError::StaleLock => write!(f, "lock file is not held"),
+            LockfileError::Io(err) => write!(f, "{}", err),
+        }
     }
 }
 
@@ -28,14 +33,24 @@ pub struct LockFile {
 }
 
 impl LockFile {
+    /*
+        path is the file being updated, the lock is taken on a sibling
+        file with a .lock extension appended.
+    */
     pub fn new(path: PathBuf) -> Self {
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
         Self {
-            file_path: path.join("HEAD"),
-            lock_path: path.join("HEAD.lock").clone(),
+            file_path: path,
+            lock_path: PathBuf::from(lock_path),
             lock: None,
         }
     }
 
+    pub fn lock_path(&self) -> &PathBuf {
+        &self.lock_path
+    }
+
     pub fn hold_for_update(&mut self) -> Result<bool, LockfileError> {
         match &self.lock {
             Some(_) => Ok(true),
@@ -75,16 +90,14 @@ impl LockFile {
         }
     }
 
-    pub fn write(&self, s: String) -> Result<(), LockfileError> {
-        match self.raise_on_stale_lock() {
-            Ok(_) => {
-                let mut lock = self.lock.as_ref().unwrap();
-                let _ = lock.write_all(s.as_bytes());
-                //unchecked result
-                Ok(())
-            }
-            Err(err) => Err(err),
-        }
+    /*
+        Writes data to the lock. A failed write leaves the lock held, so
+        the caller can roll it back rather than commit a partial file.
+    */
+    pub fn write(&self, data: &[u8]) -> Result<(), LockfileError> {
+        self.raise_on_stale_lock()?;
+        let mut lock = self.lock.as_ref().unwrap();
+        lock.write_all(data).map_err(LockfileError::Io)
     }
 
     pub fn commit(&mut self) -> Result<(), LockfileError> {
@@ -101,6 +114,13 @@ impl LockFile {
         }
     }
 
+    pub fn rollback(&mut self) -> Result<(), LockfileError> {
+        self.raise_on_stale_lock()?;
+        let _ = fs::remove_file(&self.lock_path);
+        self.lock = None;
+        Ok(())
+    }
+
     pub fn raise_on_stale_lock(&self) -> Result<(), LockfileError> {
         match &self.lock {
             Some(_) => Ok(()),
@@ -1,139 +1,36 @@
-use std::{env, fs, io, path::PathBuf, process};
+use std::{env, io, process};
 
-use chrono::Local;
+use shit::command;
 
-mod author;
-mod blob;
-mod commit;
-mod database;
-mod entry;
-mod lockfile;
-mod refs;
-mod traits;
-mod tree;
-mod utils;
-mod workspace;
-
-fn initialize_repo_directory(mut path_buf: PathBuf) -> io::Result<()> {
-    path_buf.push(".git");
-    let dirs = ["objects", "refs"];
-    for dir in dirs.into_iter() {
-        path_buf.push(dir);
-        fs::create_dir_all(&path_buf)?;
-        path_buf.pop();
+fn main() {
+    if let Err(err) = run() {
+        command::fatal(err);
     }
-    Ok(())
-}
-
-fn init(dir: &str) -> io::Result<()> {
-    let path: PathBuf = fs::canonicalize(dir).or_else(|_| {
-        fs::create_dir_all(dir)?;
-        Ok::<PathBuf, io::Error>(PathBuf::from(dir))
-    })?;
-    println!("Initialized empty Git repository in {}", path.display());
-    initialize_repo_directory(path)?;
-    Ok(())
 }
 
-fn main() -> io::Result<()> {
+fn run() -> io::Result<()> {
     let args = env::args().collect::<Vec<String>>();
     let cmd = args.get(1).expect("Usage: {} <command> [<directory>]");
+    let cmd_args = &args[2..];
     match Command::from(&cmd[..]) {
-        Command::Init => {
-            let default_dir = &"./".to_string();
-            let dir = args.get(2).unwrap_or(default_dir);
-            match init(dir) {
-                Ok(_) => {
-                    println!("init success");
-                }
-                Err(_) => {
-                    eprintln!("init failure");
-                    std::process::exit(1);
-                }
-            }
-        }
-        Command::Commit => {
-            // set up paths.
-            let git_path = utils::get_git_path();
-            let db_path = utils::get_db_path();
-            let root_path = utils::get_root_path();
-
-            // set up git data structures.
-            let workspace = workspace::Workspace::new(root_path.clone());
-            let database = database::Database::new(db_path);
-            let refs = refs::Refs::new(git_path.clone());
-
-            // Read current workspace files into Entry vector (used to construct Tree).
-
-            let files = workspace.list_files(&root_path.clone())?;
-            dbg!(&files);
-            let mut entries = Vec::new();
-            for file in files {
-                let data = workspace.read_data(&file)?;
-                let mut blob = blob::Blob::new(&data);
-                database.store(&mut blob)?;
-                let filename = file
-                    .clone()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string();
-
-                let stat = workspace.stat_file(file.clone());
-                let entry = entry::Entry::new(filename, file, &blob.object_id, stat);
-                entries.push(entry);
-            }
-            // TODO: can possibly delete moved to Tree::build
-            entries.sort_by_key(|e| e.path.clone().to_str().unwrap().to_string());
-
-            // Create and store tree for commit.
-            //let mut tree = tree::Tree::_new(entries.clone());
-
-            let mut tree = tree::Tree::build(entries);
-            tree.store_tree(&database);
-
-            // Get parent of current commit.
-            let parent = refs.read_head().unwrap();
-
-            // Create Author.
-            let now = Local::now();
-            let formatted_datetime = now.format("%s %z").to_string();
-            let author_name = env::var("GIT_AUTHOR_NAME").expect("GIT_AUTHOR_NAME not set");
-            let author_email = env::var("GIT_AUTHOR_EMAIL").expect("GIT_AUTHOR_EMAIL not set");
-            let author = author::Author::new(author_name, author_email, formatted_datetime);
-
-            // Read commit message, create commit, store it.
-            let mut commit_message = String::new();
-            io::stdin().read_line(&mut commit_message)?;
-            let mut commit = commit::Commit::new(
-                parent.clone(),
-                tree.object_id,
-                author,
-                commit_message.clone(),
-            );
-            database.store(&mut commit).unwrap();
-
-            // Write commit id to HEAD.
-            let commit_hex_str = utils::u8_to_hex_str(commit.object_id.as_bytes().to_vec());
-
-            let _ = match refs.update_head(commit_hex_str.clone()) {
-                Ok(_) => Ok(()),
-                Err(err) => {
-                    dbg!(&err);
-                    println!("can't acquire lock");
-                    Err(err)
-                }
-            };
-
-            let first_line = commit.message.lines().next().unwrap();
-
-            let mut is_root = String::from("");
-            if parent.is_empty() {
-                is_root = String::from("(root-commit) ");
-            }
-            println!("[{}{}] {}", is_root, commit_hex_str, first_line);
-        }
+        Command::Init => command::init::run(cmd_args)?,
+        Command::Add => command::add::run(cmd_args)?,
+        Command::Branch => command::branch::run(cmd_args)?,
+        Command::CheckIgnore => command::check_ignore::run(cmd_args)?,
+        Command::Checkout => command::checkout::run(cmd_args)?,
+        Command::Commit => command::commit::run(cmd_args)?,
+        Command::Diff => command::diff::run(cmd_args)?,
+        Command::Gc => command::gc::run(cmd_args)?,
+        Command::Log => command::log::run(cmd_args)?,
+        Command::Merge => command::merge::run(cmd_args)?,
+        Command::MergeBase => command::merge_base::run(cmd_args)?,
+        Command::PackRefs => command::pack_refs::run(cmd_args)?,
+        Command::Reflog => command::reflog::run(cmd_args)?,
+        Command::Repack => command::repack::run(cmd_args)?,
+        Command::RevParse => command::rev_parse::run(cmd_args)?,
+        Command::Status => command::status::run(cmd_args)?,
+        Command::Switch => command::switch::run(cmd_args)?,
+        Command::UpdateRef => command::update_ref::run(cmd_args)?,
         Command::Unknown => {
             eprintln!("Usage: {} <command> [<directory>]", args[0]);
             process::exit(1);
@@ -145,7 +42,23 @@ fn main() -> io::Result<()> {
 #[derive(Debug)]
 enum Command {
     Init,
+    Add,
+    Branch,
+    CheckIgnore,
+    Checkout,
     Commit,
+    Diff,
+    Gc,
+    Log,
+    Merge,
+    MergeBase,
+    PackRefs,
+    Reflog,
+    Repack,
+    RevParse,
+    Status,
+    Switch,
+    UpdateRef,
     Unknown,
 }
 
@@ -153,7 +66,23 @@ impl From<&str> for Command {
     fn from(s: &str) -> Self {
         match s {
             "init" => Command::Init,
+            "add" => Command::Add,
+            "branch" => Command::Branch,
+            "check-ignore" => Command::CheckIgnore,
+            "checkout" => Command::Checkout,
             "commit" => Command::Commit,
+            "diff" => Command::Diff,
+            "gc" => Command::Gc,
+            "log" => Command::Log,
+            "merge" => Command::Merge,
+            "merge-base" => Command::MergeBase,
+            "pack-refs" => Command::PackRefs,
+            "reflog" => Command::Reflog,
+            "repack" => Command::Repack,
+            "rev-parse" => Command::RevParse,
+            "status" => Command::Status,
+            "switch" => Command::Switch,
+            "update-ref" => Command::UpdateRef,
             _ => Command::Unknown,
         }
     }
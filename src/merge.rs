@@ -0,0 +1,354 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::PathBuf,
+};
+
+use crate::blob::Blob;
+use crate::database::Database;
+use crate::diff;
+use crate::diff3;
+use crate::entry::Entry;
+use crate::object_id::ObjectId;
+use crate::tree_diff::TYPE_MASK;
+
+/*
+    The versions of a path that could not be merged, which become its
+    index stages 1 (base), 2 (ours) and 3 (theirs).
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConflictEntries {
+    pub base: Option<Entry>,
+    pub ours: Option<Entry>,
+    pub theirs: Option<Entry>,
+}
+
+impl ConflictEntries {
+    pub fn stages(&self) -> [Option<&Entry>; 3] {
+        [self.base.as_ref(), self.ours.as_ref(), self.theirs.as_ref()]
+    }
+}
+
+/*
+    The outcome of merging two trees. files is what the workspace should
+    hold afterwards, conflicted files included with their conflict
+    markers; conflicts lists the paths left unmerged. messages are the
+    "Auto-merging" and "CONFLICT" lines git prints, in path order.
+*/
+#[derive(Debug, Default)]
+pub struct TreeMerge {
+    pub files: BTreeMap<String, Entry>,
+    pub conflicts: BTreeMap<String, ConflictEntries>,
+    pub messages: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl TreeMerge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/*
+    Three-way merges the trees of two commits against the tree of their
+    merge base, None when they have no history in common. A path changed
+    on one side only takes that side's version; a file changed on both
+    sides has its content merged line by line. Conflicted files are
+    stored in the database with their markers. The labels name the two
+    sides in markers and messages.
+*/
+pub fn merge_trees(
+    database: &Database,
+    base: Option<ObjectId>,
+    ours: ObjectId,
+    theirs: ObjectId,
+    labels: (&str, &str),
+) -> io::Result<TreeMerge> {
+    let base_entries = match base {
+        Some(base) => database.load_tree_entries(&base)?,
+        None => BTreeMap::new(),
+    };
+    let ours_entries = database.load_tree_entries(&ours)?;
+    let theirs_entries = database.load_tree_entries(&theirs)?;
+    let paths: BTreeSet<&String> = base_entries
+        .keys()
+        .chain(ours_entries.keys())
+        .chain(theirs_entries.keys())
+        .collect();
+
+    let mut merger = Merger {
+        database,
+        labels,
+        result: TreeMerge::default(),
+        messages: BTreeMap::new(),
+    };
+    for path in paths {
+        merger.merge_path(
+            path,
+            base_entries.get(path),
+            ours_entries.get(path),
+            theirs_entries.get(path),
+        )?;
+    }
+    merger.move_files_out_of_the_way(&ours_entries);
+    let mut result = merger.result;
+    result.messages = merger.messages.into_values().flatten().collect();
+    Ok(result)
+}
+
+struct Merger<'a> {
+    database: &'a Database,
+    labels: (&'a str, &'a str),
+    result: TreeMerge,
+    messages: BTreeMap<String, Vec<String>>,
+}
+
+impl Merger<'_> {
+    fn merge_path(
+        &mut self,
+        path: &str,
+        base: Option<&Entry>,
+        ours: Option<&Entry>,
+        theirs: Option<&Entry>,
+    ) -> io::Result<()> {
+        if ours == theirs || base == theirs {
+            self.take(path, ours);
+            return Ok(());
+        }
+        if base == ours {
+            self.take(path, theirs);
+            return Ok(());
+        }
+        let (ours_label, theirs_label) = self.labels;
+        match (base, ours, theirs) {
+            (Some(base), Some(ours), Some(theirs)) => {
+                self.merge_files(path, Some(base), ours, theirs, "content")
+            }
+            (None, Some(ours), Some(theirs)) => {
+                self.merge_files(path, None, ours, theirs, "add/add")
+            }
+            (Some(_), Some(modified), None) | (Some(_), None, Some(modified)) => {
+                let (deleted_in, modified_in) = if ours.is_some() {
+                    (theirs_label, ours_label)
+                } else {
+                    (ours_label, theirs_label)
+                };
+                self.modify_delete_message(path, path, deleted_in, modified_in);
+                self.take(path, Some(modified));
+                self.conflict(path, base, ours, theirs);
+                Ok(())
+            }
+            // Both deleted, or both unchanged, was handled above.
+            _ => Ok(()),
+        }
+    }
+
+    /*
+        Merges the content and mode of a file changed on both sides. kind
+        is how git describes a content conflict: "content" when base had
+        the file, "add/add" when both sides created it.
+    */
+    fn merge_files(
+        &mut self,
+        path: &str,
+        base: Option<&Entry>,
+        ours: &Entry,
+        theirs: &Entry,
+        kind: &str,
+    ) -> io::Result<()> {
+        let base_mode = base.map(|entry| entry.mode);
+        let (mode, mode_clean) = if ours.mode == theirs.mode || base_mode == Some(theirs.mode) {
+            (ours.mode, true)
+        } else if base_mode == Some(ours.mode) {
+            (theirs.mode, true)
+        } else {
+            (ours.mode, false)
+        };
+
+        let base_id = base.map(|entry| entry.object_id);
+        let (object_id, content_clean) =
+            if ours.object_id == theirs.object_id || base_id == Some(theirs.object_id) {
+                (ours.object_id, true)
+            } else if base_id == Some(ours.object_id) {
+                (theirs.object_id, true)
+            } else {
+                self.message(path, format!("Auto-merging {}", path));
+                self.merge_content(path, base, ours, theirs)?
+            };
+
+        if !content_clean || ours.mode & TYPE_MASK != theirs.mode & TYPE_MASK {
+            self.message(
+                path,
+                format!("CONFLICT ({}): Merge conflict in {}", kind, path),
+            );
+        }
+        let entry = entry_at(path, object_id, mode);
+        self.take(path, Some(&entry));
+        if !content_clean || !mode_clean {
+            self.conflict(path, base, Some(ours), Some(theirs));
+        }
+        Ok(())
+    }
+
+    fn merge_content(
+        &mut self,
+        path: &str,
+        base: Option<&Entry>,
+        ours: &Entry,
+        theirs: &Entry,
+    ) -> io::Result<(ObjectId, bool)> {
+        let base_data = match base {
+            Some(base) => self.database.read_object(&base.object_id)?.1,
+            None => Vec::new(),
+        };
+        let (_, ours_data) = self.database.read_object(&ours.object_id)?;
+        let (_, theirs_data) = self.database.read_object(&theirs.object_id)?;
+        let (ours_label, theirs_label) = self.labels;
+        if [&base_data, &ours_data, &theirs_data]
+            .iter()
+            .any(|data| diff::is_binary(data))
+        {
+            self.result.warnings.push(format!(
+                "Cannot merge binary files: {} ({} vs. {})",
+                path, ours_label, theirs_label
+            ));
+            return Ok((ours.object_id, false));
+        }
+        let merged = diff3::merge(
+            &base_data,
+            &ours_data,
+            &theirs_data,
+            ours_label,
+            theirs_label,
+        );
+        let clean = merged.is_clean();
+        let object_id = self.database.store(&mut Blob::new(merged.data))?;
+        Ok((object_id, clean))
+    }
+
+    /*
+        A file on one side where the other side has a directory can stay
+        where it is in neither, so like git it is moved aside to
+        "<path>~<label>" and left unmerged there. A modify/delete conflict
+        keeps its base stage and is reported again under the new name,
+        after the file/directory message.
+    */
+    fn move_files_out_of_the_way(&mut self, ours_entries: &BTreeMap<String, Entry>) {
+        let in_the_way: Vec<String> = self
+            .result
+            .files
+            .keys()
+            .filter(|path| {
+                let prefix = format!("{}/", path);
+                self.result
+                    .files
+                    .range(prefix.clone()..)
+                    .next()
+                    .is_some_and(|(other, _)| other.starts_with(&prefix))
+            })
+            .cloned()
+            .collect();
+        for path in in_the_way {
+            let entry = self.result.files.remove(&path).expect("path is in files");
+            let from_ours = ours_entries.get(&path) == Some(&entry);
+            let label = if from_ours {
+                self.labels.0
+            } else {
+                self.labels.1
+            };
+            let new_path = format!("{}~{}", path, label.replace('/', "_"));
+            let entry = entry_at(&new_path, entry.object_id, entry.mode);
+            self.messages.remove(&path);
+            self.message(
+                &path,
+                format!(
+                    "CONFLICT (file/directory): directory in the way of {} from {}; \
+                     moving it to {} instead.",
+                    path, label, new_path
+                ),
+            );
+            let mut conflict = self.result.conflicts.remove(&path).unwrap_or_default();
+            // The other side has a directory here, so with a base the file
+            // was deleted there: a modify/delete.
+            if let Some(base) = &conflict.base {
+                conflict.base = Some(entry_at(&new_path, base.object_id, base.mode));
+                let (ours_label, theirs_label) = self.labels;
+                let (deleted_in, modified_in) = if from_ours {
+                    (theirs_label, ours_label)
+                } else {
+                    (ours_label, theirs_label)
+                };
+                self.modify_delete_message(&path, &new_path, deleted_in, modified_in);
+            }
+            if from_ours {
+                conflict.ours = Some(entry.clone());
+                conflict.theirs = None;
+            } else {
+                conflict.ours = None;
+                conflict.theirs = Some(entry.clone());
+            }
+            self.result.conflicts.insert(new_path.clone(), conflict);
+            self.result.files.insert(new_path, entry);
+        }
+    }
+
+    fn take(&mut self, path: &str, entry: Option<&Entry>) {
+        if let Some(entry) = entry {
+            self.result.files.insert(path.to_string(), entry.clone());
+        }
+    }
+
+    fn conflict(
+        &mut self,
+        path: &str,
+        base: Option<&Entry>,
+        ours: Option<&Entry>,
+        theirs: Option<&Entry>,
+    ) {
+        self.result.conflicts.insert(
+            path.to_string(),
+            ConflictEntries {
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            },
+        );
+    }
+
+    /*
+        Reports a file deleted on one side and modified on the other,
+        which is left in the tree at file_path and ordered under path.
+    */
+    fn modify_delete_message(
+        &mut self,
+        path: &str,
+        file_path: &str,
+        deleted_in: &str,
+        modified_in: &str,
+    ) {
+        self.message(
+            path,
+            format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  \
+                 Version {} of {} left in tree.",
+                file_path, deleted_in, modified_in, modified_in, file_path
+            ),
+        );
+    }
+
+    fn message(&mut self, path: &str, message: String) {
+        self.messages
+            .entry(path.to_string())
+            .or_default()
+            .push(message);
+    }
+}
+
+fn entry_at(path: &str, object_id: ObjectId, mode: u32) -> Entry {
+    let path = PathBuf::from(path);
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Entry::new(filename, path, object_id, mode)
+}
@@ -0,0 +1,260 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    io,
+};
+
+use crate::commit_graph::CommitGraph;
+use crate::database::Database;
+use crate::object_id::ObjectId;
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+// The generation of a commit missing from the commit-graph. Such a commit
+// was made after the graph was written, so nothing in the graph reaches it.
+const GENERATION_INFINITY: u32 = u32::MAX;
+
+struct Node {
+    parents: Vec<ObjectId>,
+    date: i64,
+    generation: u32,
+}
+
+/*
+    The commit graph as far as it has been walked. Commits are read from
+    the commit-graph file when git has written one, which also gives their
+    generation numbers; the others are loaded from the database.
+*/
+pub struct History<'a> {
+    database: &'a Database,
+    graph: Option<CommitGraph>,
+    nodes: HashMap<ObjectId, Node>,
+}
+
+impl<'a> History<'a> {
+    pub fn new(database: &'a Database) -> io::Result<Self> {
+        Ok(History {
+            database,
+            graph: CommitGraph::load(&database.path_buf)?,
+            nodes: HashMap::new(),
+        })
+    }
+
+    /*
+        The best common ancestors of one and the others: the common
+        ancestors that are not themselves ancestors of another, newest
+        first.
+    */
+    pub fn merge_bases(&mut self, one: &ObjectId, twos: &[ObjectId]) -> io::Result<Vec<ObjectId>> {
+        if twos.contains(one) {
+            return Ok(vec![*one]);
+        }
+        let (candidates, _) = self.paint_down_to_common(one, twos, 0)?;
+        if candidates.len() <= 1 {
+            return Ok(candidates);
+        }
+        self.remove_redundant(candidates)
+    }
+
+    /*
+        Whether ancestor is reachable from descendant, counting a commit
+        as its own ancestor.
+    */
+    pub fn is_ancestor(&mut self, ancestor: &ObjectId, descendant: &ObjectId) -> io::Result<bool> {
+        let generation = self.node(ancestor)?.generation;
+        if generation > self.node(descendant)?.generation {
+            return Ok(false);
+        }
+        let (_, flags) = self.paint_down_to_common(ancestor, &[*descendant], generation)?;
+        Ok(is_marked(&flags, ancestor, PARENT2))
+    }
+
+    /*
+        The merge bases of all the commits together, as an octopus merge
+        would use: the bases of the first two, then the bases of those
+        with the third, and so on.
+    */
+    pub fn octopus_merge_bases(&mut self, commits: &[ObjectId]) -> io::Result<Vec<ObjectId>> {
+        let Some((first, rest)) = commits.split_first() else {
+            return Ok(Vec::new());
+        };
+        let mut bases = vec![*first];
+        for commit in rest {
+            let mut next = Vec::new();
+            for base in &bases {
+                next.extend(self.merge_bases(base, &[*commit])?);
+            }
+            bases = next;
+        }
+        Ok(bases)
+    }
+
+    /*
+        The commits that are not ancestors of another one in the list,
+        without duplicates and in their original order.
+    */
+    pub fn reduce_heads(&mut self, commits: &[ObjectId]) -> io::Result<Vec<ObjectId>> {
+        let mut unique = Vec::new();
+        for commit in commits {
+            if !unique.contains(commit) {
+                unique.push(*commit);
+            }
+        }
+        if unique.len() <= 1 {
+            return Ok(unique);
+        }
+        self.remove_redundant(unique)
+    }
+
+    /*
+        Paints the history of one and of twos, the way git's
+        paint_down_to_common does. A commit reached from both sides is a
+        common ancestor; everything below it is marked stale, and the walk
+        stops once only stale commits are left in the queue. Commits come
+        off the queue highest generation first, then newest date, so the
+        walk can also stop at the first commit below min_generation: none
+        of the commits it was asked about can be reached from there.
+        Returns the common ancestors found, newest first, and the flags
+        painted on each commit.
+    */
+    fn paint_down_to_common(
+        &mut self,
+        one: &ObjectId,
+        twos: &[ObjectId],
+        min_generation: u32,
+    ) -> io::Result<(Vec<ObjectId>, HashMap<ObjectId, u8>)> {
+        let mut queue = BinaryHeap::new();
+        let mut flags = HashMap::new();
+        let mut counter = 0;
+        let mut enqueue = |history: &mut Self,
+                           queue: &mut BinaryHeap<_>,
+                           flags: &mut HashMap<ObjectId, u8>,
+                           object_id: ObjectId,
+                           flag: u8|
+         -> io::Result<()> {
+            let node = history.node(&object_id)?;
+            *flags.entry(object_id).or_insert(0) |= flag;
+            queue.push((node.generation, node.date, Reverse(counter), object_id));
+            counter += 1;
+            Ok(())
+        };
+        enqueue(self, &mut queue, &mut flags, *one, PARENT1)?;
+        for two in twos {
+            enqueue(self, &mut queue, &mut flags, *two, PARENT2)?;
+        }
+
+        let mut results = Vec::new();
+        while queue
+            .iter()
+            .any(|(_, _, _, object_id)| !is_marked(&flags, object_id, STALE))
+        {
+            let (generation, _, _, object_id) = queue.pop().expect("queue is not empty");
+            if generation < min_generation {
+                break;
+            }
+            let mut painted = flags[&object_id] & (PARENT1 | PARENT2 | STALE);
+            if painted == PARENT1 | PARENT2 {
+                if !is_marked(&flags, &object_id, RESULT) {
+                    *flags.entry(object_id).or_insert(0) |= RESULT;
+                    results.push(object_id);
+                }
+                painted |= STALE;
+            }
+            for parent in self.node(&object_id)?.parents.clone() {
+                if flags.get(&parent).copied().unwrap_or(0) & painted == painted {
+                    continue;
+                }
+                enqueue(self, &mut queue, &mut flags, parent, painted)?;
+            }
+        }
+        results.sort_by_key(|object_id| Reverse(self.nodes[object_id].date));
+        Ok((results, flags))
+    }
+
+    /*
+        Drops every candidate reachable from another one, keeping the order.
+    */
+    fn remove_redundant(&mut self, candidates: Vec<ObjectId>) -> io::Result<Vec<ObjectId>> {
+        let mut redundant = vec![false; candidates.len()];
+        for (i, candidate) in candidates.iter().enumerate() {
+            if redundant[i] {
+                continue;
+            }
+            let others: Vec<ObjectId> = candidates
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i && !redundant[*j])
+                .map(|(_, other)| *other)
+                .collect();
+            let mut min_generation = self.node(candidate)?.generation;
+            for other in &others {
+                min_generation = min_generation.min(self.node(other)?.generation);
+            }
+            let (_, flags) = self.paint_down_to_common(candidate, &others, min_generation)?;
+            if is_marked(&flags, candidate, PARENT2) {
+                redundant[i] = true;
+            }
+            for (j, other) in candidates.iter().enumerate() {
+                if j != i && is_marked(&flags, other, PARENT1) {
+                    redundant[j] = true;
+                }
+            }
+        }
+        Ok(candidates
+            .into_iter()
+            .zip(redundant)
+            .filter(|(_, redundant)| !redundant)
+            .map(|(candidate, _)| candidate)
+            .collect())
+    }
+
+    fn node(&mut self, object_id: &ObjectId) -> io::Result<&Node> {
+        if !self.nodes.contains_key(object_id) {
+            let in_graph = match &self.graph {
+                Some(graph) => graph.lookup(object_id)?,
+                None => None,
+            };
+            let node = match in_graph {
+                Some(commit) => Node {
+                    parents: commit.parents,
+                    date: commit.date,
+                    generation: commit.generation,
+                },
+                None => {
+                    let commit = self.database.load_commit(object_id)?;
+                    Node {
+                        date: commit.committer.timestamp(),
+                        parents: commit.parents,
+                        generation: GENERATION_INFINITY,
+                    }
+                }
+            };
+            self.nodes.insert(*object_id, node);
+        }
+        Ok(&self.nodes[object_id])
+    }
+}
+
+fn is_marked(flags: &HashMap<ObjectId, u8>, object_id: &ObjectId, flag: u8) -> bool {
+    flags
+        .get(object_id)
+        .is_some_and(|flags| flags & flag == flag)
+}
+
+pub fn merge_bases(
+    database: &Database,
+    one: &ObjectId,
+    twos: &[ObjectId],
+) -> io::Result<Vec<ObjectId>> {
+    History::new(database)?.merge_bases(one, twos)
+}
+
+pub fn is_ancestor(
+    database: &Database,
+    ancestor: &ObjectId,
+    descendant: &ObjectId,
+) -> io::Result<bool> {
+    History::new(database)?.is_ancestor(ancestor, descendant)
+}
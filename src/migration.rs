@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error, fmt, io,
+    path::Path,
+};
+
+use crate::blob::Blob;
+use crate::entry::Entry;
+use crate::index::IndexEntry;
+use crate::repository::Repository;
+use crate::tree_diff::{ChangeKind, TreeChange};
+use crate::utils;
+
+/*
+    Ways moving to another tree could lose work, in the order git reports
+    them.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Conflict {
+    // A tracked file with changes in the index or workspace would be replaced.
+    StaleFile,
+    // A directory holding untracked files would become a file.
+    StaleDirectory,
+    // An untracked file is where a file or its directory would go.
+    UntrackedOverwritten,
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Conflicts(BTreeMap<Conflict, BTreeSet<String>>),
+    Io(io::Error),
+}
+
+impl error::Error for MigrationError {}
+
+impl MigrationError {
+    /*
+        One message per kind of conflict, each of which git reports as a
+        separate error. command is the one moving the files, "checkout"
+        or "merge", which git names in the messages.
+    */
+    pub fn messages(&self, command: &str) -> Vec<String> {
+        let MigrationError::Conflicts(conflicts) = self else {
+            return vec![self.to_string()];
+        };
+        let action = match command {
+            "checkout" => "switch branches",
+            other => other,
+        };
+        let mut messages = Vec::new();
+        for (conflict, paths) in conflicts {
+            let (header, footer) = match conflict {
+                Conflict::StaleFile => (
+                    format!(
+                        "Your local changes to the following files would be overwritten by {}:",
+                        command
+                    ),
+                    format!(
+                        "Please commit your changes or stash them before you {}.",
+                        action
+                    ),
+                ),
+                Conflict::StaleDirectory => (
+                    String::from(
+                        "Updating the following directories would lose untracked files in them:",
+                    ),
+                    String::new(),
+                ),
+                Conflict::UntrackedOverwritten => (
+                    format!(
+                        "The following untracked working tree files would be overwritten by {}:",
+                        command
+                    ),
+                    format!("Please move or remove them before you {}.", action),
+                ),
+            };
+            let mut message = header;
+            for path in paths {
+                message.push_str(&format!("\n\t{}", path));
+            }
+            message.push_str(&format!("\n{}", footer));
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Conflicts(_) => {
+                write!(f, "{}", self.messages("checkout").join("\n"))
+            }
+            MigrationError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for MigrationError {
+    fn from(err: io::Error) -> Self {
+        MigrationError::Io(err)
+    }
+}
+
+/*
+    Moves the workspace and index from one tree to another, touching only
+    the paths that differ between the two. Files with changes that are not
+    in either tree are left alone unless the move would overwrite them, in
+    which case nothing is changed at all.
+*/
+pub struct Migration {
+    changes: Vec<TreeChange>,
+}
+
+impl Migration {
+    pub fn new(changes: Vec<TreeChange>) -> Self {
+        Migration { changes }
+    }
+
+    /*
+        Expects the index to be loaded for update; the caller writes it.
+        Paths already staged as they are in the new tree are left as they
+        are, workspace changes included.
+    */
+    pub fn apply(&self, repo: &mut Repository) -> Result<(), MigrationError> {
+        let changes: Vec<&TreeChange> = self
+            .changes
+            .iter()
+            .filter(|change| {
+                let entry = repo.index.entry_for_path(&change.path);
+                !index_matches(entry, change.new.as_ref())
+            })
+            .collect();
+        self.check_conflicts(repo, &changes)?;
+        self.update_workspace(repo, &changes)?;
+        self.update_index(repo, &changes)?;
+        Ok(())
+    }
+
+    fn check_conflicts(
+        &self,
+        repo: &Repository,
+        changes: &[&TreeChange],
+    ) -> Result<(), MigrationError> {
+        let mut conflicts: BTreeMap<Conflict, BTreeSet<String>> = BTreeMap::new();
+        for change in changes {
+            if let Some((conflict, path)) = self.check_change(repo, change)? {
+                conflicts.entry(conflict).or_default().insert(path);
+            }
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(MigrationError::Conflicts(conflicts))
+        }
+    }
+
+    fn check_change(
+        &self,
+        repo: &Repository,
+        change: &TreeChange,
+    ) -> io::Result<Option<(Conflict, String)>> {
+        let path = &change.path;
+        let entry = repo.index.entry_for_path(path);
+        if !index_matches(entry, change.old.as_ref()) {
+            return Ok(Some((Conflict::StaleFile, path.clone())));
+        }
+
+        let Ok(stat) = repo.workspace.stat_file(Path::new(path)) else {
+            return Ok(self
+                .untracked_parent(repo, path)
+                .map(|parent| (Conflict::UntrackedOverwritten, parent)));
+        };
+        if stat.is_dir() {
+            let untracked = repo
+                .workspace
+                .list_files(Path::new(path))?
+                .iter()
+                .any(|file| !repo.index.is_tracked_file(&utils::path_to_index_key(file)));
+            return Ok(untracked.then(|| (Conflict::StaleDirectory, path.clone())));
+        }
+        match entry {
+            Some(entry) if !workspace_matches(repo, entry)? => {
+                Ok(Some((Conflict::StaleFile, path.clone())))
+            }
+            Some(_) => Ok(None),
+            // An untracked file is only in the way of a file being written.
+            None if change.new.is_some() => {
+                Ok(Some((Conflict::UntrackedOverwritten, path.clone())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /*
+        An untracked file in the workspace where the change needs a
+        directory.
+    */
+    fn untracked_parent(&self, repo: &Repository, path: &str) -> Option<String> {
+        for (i, b) in path.bytes().enumerate() {
+            if b != b'/' {
+                continue;
+            }
+            let parent = &path[..i];
+            let is_file = repo
+                .workspace
+                .stat_file(Path::new(parent))
+                .is_ok_and(|stat| stat.is_file());
+            if is_file && !repo.index.is_tracked_file(parent) {
+                return Some(parent.to_string());
+            }
+        }
+        None
+    }
+
+    /*
+        Removals go first, then directories they emptied, deepest first, so
+        a file can replace a directory and the other way round.
+    */
+    fn update_workspace(&self, repo: &Repository, changes: &[&TreeChange]) -> io::Result<()> {
+        let workspace = &repo.workspace;
+        let mut emptied = BTreeSet::new();
+        for change in changes {
+            let path = Path::new(&change.path);
+            let replaced = change.old.is_some() && change.new.is_none()
+                || change.kind == ChangeKind::TypeChanged;
+            if replaced && repo.index.is_tracked_file(&change.path) {
+                workspace.remove_file(path)?;
+            }
+            emptied.extend(path.ancestors().skip(1).filter(|dir| *dir != Path::new("")));
+        }
+        for dir in emptied.iter().rev() {
+            workspace.remove_directory(dir)?;
+        }
+
+        for change in changes {
+            let Some(new) = &change.new else {
+                continue;
+            };
+            let path = Path::new(&change.path);
+            let mut parents: Vec<&Path> = path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| *dir != Path::new(""))
+                .collect();
+            parents.reverse();
+            for dir in parents {
+                workspace.make_directory(dir)?;
+            }
+            // Anything left in a directory being replaced is ignored, and
+            // git removes ignored files.
+            if workspace.stat_file(path).is_ok_and(|stat| stat.is_dir()) {
+                workspace.remove_tree(path)?;
+            }
+            let (_, data) = repo.database.read_object(&new.object_id)?;
+            workspace.write_file(path, &data, new.mode)?;
+        }
+        Ok(())
+    }
+
+    fn update_index(&self, repo: &mut Repository, changes: &[&TreeChange]) -> io::Result<()> {
+        for change in changes {
+            let path = Path::new(&change.path);
+            match &change.new {
+                Some(new) => {
+                    let stat = repo.workspace.stat_file(path)?;
+                    repo.index.add(path, new.object_id, &stat);
+                }
+                None => repo.index.remove(path),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn index_matches(entry: Option<&IndexEntry>, item: Option<&Entry>) -> bool {
+    match (entry, item) {
+        (None, None) => true,
+        (Some(entry), Some(item)) => entry.object_id == item.object_id && entry.mode == item.mode,
+        _ => false,
+    }
+}
+
+/*
+    Whether the workspace file still has the content and mode the index
+    records for it.
+*/
+fn workspace_matches(repo: &Repository, entry: &IndexEntry) -> io::Result<bool> {
+    let path = Path::new(&entry.path);
+    let stat = repo.workspace.stat_file(path)?;
+    if Entry::mode_for_stat(&stat) != entry.mode {
+        return Ok(false);
+    }
+    let data = repo.workspace.read_data(path)?;
+    Ok(repo.database.hash_object(&Blob::new(data)) == entry.object_id)
+}
@@ -0,0 +1,99 @@
+use std::{error, fmt, str::FromStr};
+
+/*
+    A SHA-1 object ID stored as its 20 raw bytes.
+    Tree entries serialize the raw bytes, everything else (commits, refs, output)
+    uses the 40 character lowercase hex form provided by Display.
+*/
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId([u8; ObjectId::LEN]);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ObjectIdError {
+    InvalidLength(usize),
+    InvalidHex(String),
+}
+
+impl error::Error for ObjectIdError {}
+
+impl fmt::Display for ObjectIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectIdError::InvalidLength(len) => {
+                write!(f, "invalid object id length: {}", len)
+            }
+            ObjectIdError::InvalidHex(hex) => write!(f, "invalid object id: {}", hex),
+        }
+    }
+}
+
+impl ObjectId {
+    pub const LEN: usize = 20;
+    pub const HEX_LEN: usize = 40;
+    pub const SHORT_LEN: usize = 7;
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ObjectIdError> {
+        let raw: [u8; Self::LEN] = bytes
+            .try_into()
+            .map_err(|_| ObjectIdError::InvalidLength(bytes.len()))?;
+        Ok(ObjectId(raw))
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, ObjectIdError> {
+        if hex.len() != Self::HEX_LEN {
+            return Err(ObjectIdError::InvalidLength(hex.len()));
+        }
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ObjectIdError::InvalidHex(hex.to_string()));
+        }
+        let mut raw = [0u8; Self::LEN];
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("validated hex digits");
+        }
+        Ok(ObjectId(raw))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn to_hex(self) -> String {
+        self.to_string()
+    }
+
+    /*
+        Returns the first len hex characters of the ID.
+    */
+    pub fn abbrev(self, len: usize) -> String {
+        let mut hex = self.to_hex();
+        hex.truncate(len.min(Self::HEX_LEN));
+        hex
+    }
+
+    pub fn short(self) -> String {
+        self.abbrev(Self::SHORT_LEN)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self)
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = ObjectIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ObjectId::from_hex(s)
+    }
+}
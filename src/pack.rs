@@ -0,0 +1,392 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use flate2::read::ZlibDecoder;
+
+use crate::blob::Kind;
+use crate::delta;
+use crate::object_id::ObjectId;
+use crate::utils;
+
+pub const PACK_SIGNATURE: &[u8] = b"PACK";
+pub const PACK_VERSION: u32 = 2;
+pub const IDX_SIGNATURE: &[u8] = b"\xfftOc";
+pub const IDX_VERSION: u32 = 2;
+
+// Object types as numbered in pack entry headers.
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+const IDX_HEADER_LEN: usize = 8;
+const FANOUT_LEN: usize = 256 * 4;
+// Offsets with this bit set index the table of 64-bit offsets instead.
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+// Bytes of delta bases kept inflated per pack, like core.deltaBaseCacheLimit.
+const DELTA_BASE_CACHE_LIMIT: usize = 16 << 20;
+// Enough for the longest entry header: a type and a 64-bit size.
+const MAX_ENTRY_HEADER_LEN: usize = 10;
+
+pub fn kind_from_type(object_type: u8) -> io::Result<Kind> {
+    match object_type {
+        OBJ_COMMIT => Ok(Kind::Commit),
+        OBJ_TREE => Ok(Kind::Tree),
+        OBJ_BLOB => Ok(Kind::Blob),
+        OBJ_TAG => Ok(Kind::Tag),
+        other => Err(utils::invalid_data(&format!(
+            "unknown pack object type {}",
+            other
+        ))),
+    }
+}
+
+/*
+    A version 2 pack index: the IDs of the objects in a pack, sorted and
+    fanned out by first byte, with the offset of each in the .pack file.
+*/
+pub struct PackIndex {
+    data: Vec<u8>,
+    count: usize,
+}
+
+impl PackIndex {
+    pub fn parse(data: Vec<u8>) -> io::Result<Self> {
+        if data.len() < IDX_HEADER_LEN + FANOUT_LEN || &data[..4] != IDX_SIGNATURE {
+            return Err(utils::invalid_data("pack index signature is missing"));
+        }
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if version != IDX_VERSION {
+            return Err(utils::invalid_data(&format!(
+                "unsupported pack index version {}",
+                version
+            )));
+        }
+        let mut index = PackIndex { data, count: 0 };
+        index.count = index.fanout_at(255) as usize;
+        // Names, CRCs and offsets, then the pack and index checksums.
+        let min_len = index.names_start() + index.count * (ObjectId::LEN + 8) + 2 * ObjectId::LEN;
+        if index.data.len() < min_len {
+            return Err(utils::invalid_data("pack index is truncated"));
+        }
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn object_id(&self, position: usize) -> ObjectId {
+        ObjectId::from_bytes(self.name_at(position)).expect("index names are 20 bytes")
+    }
+
+    pub fn offset(&self, position: usize) -> io::Result<u64> {
+        let at = self.names_start() + self.count * (ObjectId::LEN + 4) + position * 4;
+        let offset = u32::from_be_bytes(self.data[at..at + 4].try_into().unwrap());
+        if offset & LARGE_OFFSET_FLAG == 0 {
+            return Ok(offset as u64);
+        }
+        let at = self.names_start()
+            + self.count * (ObjectId::LEN + 8)
+            + (offset & !LARGE_OFFSET_FLAG) as usize * 8;
+        match self.data.get(at..at + 8) {
+            Some(bytes) => Ok(u64::from_be_bytes(bytes.try_into().unwrap())),
+            None => Err(utils::invalid_data("pack index offset is out of bounds")),
+        }
+    }
+
+    pub fn find(&self, object_id: &ObjectId) -> Option<usize> {
+        let range = self.first_byte_range(object_id.as_bytes()[0]);
+        let (mut low, mut high) = (range.start, range.end);
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.name_at(middle).cmp(object_id.as_bytes()) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(middle),
+            }
+        }
+        None
+    }
+
+    /*
+        The IDs in the pack whose first byte is first_byte, the same set a
+        loose fan-out directory holds.
+    */
+    pub fn ids_with_first_byte(&self, first_byte: u8) -> Vec<ObjectId> {
+        self.first_byte_range(first_byte)
+            .map(|position| self.object_id(position))
+            .collect()
+    }
+
+    fn first_byte_range(&self, first_byte: u8) -> std::ops::Range<usize> {
+        let start = match first_byte {
+            0 => 0,
+            byte => self.fanout_at(byte as usize - 1) as usize,
+        };
+        start..self.fanout_at(first_byte as usize) as usize
+    }
+
+    fn fanout_at(&self, index: usize) -> u32 {
+        let at = IDX_HEADER_LEN + index * 4;
+        u32::from_be_bytes(self.data[at..at + 4].try_into().unwrap())
+    }
+
+    fn names_start(&self) -> usize {
+        IDX_HEADER_LEN + FANOUT_LEN
+    }
+
+    fn name_at(&self, position: usize) -> &[u8] {
+        &self.data[self.names_start() + position * ObjectId::LEN..][..ObjectId::LEN]
+    }
+}
+
+/*
+    Inflated objects from a pack, kept by offset so that the bases shared
+    by many deltas are only rebuilt once. The oldest entries are dropped
+    once the cache holds more than DELTA_BASE_CACHE_LIMIT bytes.
+*/
+#[derive(Default)]
+struct DeltaBaseCache {
+    objects: HashMap<u64, (Kind, Rc<Vec<u8>>)>,
+    order: VecDeque<u64>,
+    size: usize,
+}
+
+impl DeltaBaseCache {
+    fn get(&self, offset: u64) -> Option<(Kind, Rc<Vec<u8>>)> {
+        self.objects.get(&offset).cloned()
+    }
+
+    fn insert(&mut self, offset: u64, kind: Kind, data: Rc<Vec<u8>>) {
+        if data.len() > DELTA_BASE_CACHE_LIMIT || self.objects.contains_key(&offset) {
+            return;
+        }
+        self.size += data.len();
+        self.objects.insert(offset, (kind, data));
+        self.order.push_back(offset);
+        while self.size > DELTA_BASE_CACHE_LIMIT {
+            let oldest = self.order.pop_front().expect("cache is not empty");
+            if let Some((_, data)) = self.objects.remove(&oldest) {
+                self.size -= data.len();
+            }
+        }
+    }
+}
+
+/*
+    A .pack file and its .idx, read as git writes them to
+    .git/objects/pack. Objects stored as deltas are rebuilt from their
+    base, which is found by offset in the same pack (OFS_DELTA) or by ID
+    (REF_DELTA).
+*/
+pub struct Pack {
+    path: PathBuf,
+    index: PackIndex,
+    file: RefCell<File>,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl Pack {
+    /*
+        Opens the pack at pack_path, whose index is the .idx file next to it.
+    */
+    pub fn open(pack_path: &Path) -> io::Result<Self> {
+        let index = PackIndex::parse(fs::read(pack_path.with_extension("idx"))?)?;
+        let mut file = File::open(pack_path)?;
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        if &header[..4] != PACK_SIGNATURE || !(2..=3).contains(&version) {
+            return Err(utils::invalid_data(&format!(
+                "{} is not a supported pack file",
+                pack_path.display()
+            )));
+        }
+        let count = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+        if count != index.len() {
+            return Err(utils::invalid_data(&format!(
+                "{} holds {} objects but its index lists {}",
+                pack_path.display(),
+                count,
+                index.len()
+            )));
+        }
+        Ok(Pack {
+            path: pack_path.to_path_buf(),
+            index,
+            file: RefCell::new(file),
+            cache: RefCell::new(DeltaBaseCache::default()),
+        })
+    }
+
+    /*
+        Every pack in the pack directory of an object database, the most
+        recently written first as git searches them.
+    */
+    pub fn load_all(objects_path: &Path) -> io::Result<Vec<Self>> {
+        let entries = match fs::read_dir(objects_path.join("pack")) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "pack")
+                && path.with_extension("idx").is_file()
+            {
+                let modified = fs::metadata(&path)?.modified()?;
+                paths.push((modified, path));
+            }
+        }
+        paths.sort_by(|a, b| b.cmp(a));
+        paths.iter().map(|(_, path)| Pack::open(path)).collect()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn contains(&self, object_id: &ObjectId) -> bool {
+        self.index.find(object_id).is_some()
+    }
+
+    pub fn read_object(&self, object_id: &ObjectId) -> io::Result<Option<(Kind, Vec<u8>)>> {
+        let Some(position) = self.index.find(object_id) else {
+            return Ok(None);
+        };
+        let (kind, data) = self.read_at(self.index.offset(position)?)?;
+        Ok(Some((
+            kind,
+            Rc::try_unwrap(data).unwrap_or_else(|data| (*data).clone()),
+        )))
+    }
+
+    fn read_at(&self, offset: u64) -> io::Result<(Kind, Rc<Vec<u8>>)> {
+        if let Some(cached) = self.cache.borrow().get(offset) {
+            return Ok(cached);
+        }
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&mut *file);
+        let (object_type, size) = read_entry_header(&mut reader)?;
+        match object_type {
+            OBJ_OFS_DELTA => {
+                let distance = read_offset_distance(&mut reader)?;
+                let delta = inflate(&mut reader, size)?;
+                drop(reader);
+                drop(file);
+                let base_offset = offset.checked_sub(distance).ok_or_else(|| {
+                    utils::invalid_data("delta base offset is before the start of the pack")
+                })?;
+                self.read_delta(base_offset, &delta)
+            }
+            OBJ_REF_DELTA => {
+                let mut base_id = [0; ObjectId::LEN];
+                reader.read_exact(&mut base_id)?;
+                let base_id = ObjectId::from_bytes(&base_id)
+                    .map_err(|err| utils::invalid_data(&err.to_string()))?;
+                let delta = inflate(&mut reader, size)?;
+                drop(reader);
+                drop(file);
+                let Some(position) = self.index.find(&base_id) else {
+                    return Err(utils::invalid_data(&format!(
+                        "delta base {} is missing from {}",
+                        base_id,
+                        self.path.display()
+                    )));
+                };
+                self.read_delta(self.index.offset(position)?, &delta)
+            }
+            object_type => {
+                let kind = kind_from_type(object_type)?;
+                Ok((kind, Rc::new(inflate(&mut reader, size)?)))
+            }
+        }
+    }
+
+    fn read_delta(&self, base_offset: u64, delta: &[u8]) -> io::Result<(Kind, Rc<Vec<u8>>)> {
+        let (kind, base) = self.read_at(base_offset)?;
+        self.cache
+            .borrow_mut()
+            .insert(base_offset, kind, Rc::clone(&base));
+        Ok((kind, Rc::new(delta::apply(&base, delta)?)))
+    }
+}
+
+/*
+    An entry header: the type in bits 4-6 of the first byte and the size,
+    little-endian in groups of 4 then 7 bits, continued while the high bit
+    is set.
+*/
+fn read_entry_header(reader: &mut impl Read) -> io::Result<(u8, usize)> {
+    let mut byte = read_byte(reader)?;
+    let object_type = (byte >> 4) & 0b111;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        if shift >= MAX_ENTRY_HEADER_LEN * 7 {
+            return Err(utils::invalid_data("pack entry header is too long"));
+        }
+        byte = read_byte(reader)?;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((object_type, size))
+}
+
+/*
+    How far back an OFS_DELTA's base starts, big-endian in groups of 7
+    bits, with one added at each continuation so no two encodings share a
+    value.
+*/
+fn read_offset_distance(reader: &mut impl Read) -> io::Result<u64> {
+    let mut byte = read_byte(reader)?;
+    let mut distance = (byte & 0x7f) as u64;
+    while byte & 0x80 != 0 {
+        if distance >= 1 << 56 {
+            return Err(utils::invalid_data("delta base offset is too large"));
+        }
+        byte = read_byte(reader)?;
+        distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+    }
+    Ok(distance)
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn inflate(reader: &mut impl Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(size);
+    ZlibDecoder::new(reader).read_to_end(&mut data)?;
+    if data.len() != size {
+        return Err(utils::invalid_data(&format!(
+            "pack entry inflated to {} bytes but its header says {}",
+            data.len(),
+            size
+        )));
+    }
+    Ok(data)
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use deflate::write::ZlibEncoder;
+use deflate::Compression;
+use flate2::Crc;
+use sha1::{Digest, Sha1};
+use tempfile::NamedTempFile;
+
+use crate::blob::Kind;
+use crate::database::Database;
+use crate::delta::DeltaIndex;
+use crate::entry::DIRECTORY_MODE;
+use crate::object_id::ObjectId;
+use crate::pack::{
+    IDX_SIGNATURE, IDX_VERSION, OBJ_BLOB, OBJ_COMMIT, OBJ_OFS_DELTA, OBJ_TAG, OBJ_TREE,
+    PACK_SIGNATURE, PACK_VERSION,
+};
+use crate::rev_list::RevList;
+use crate::tree::{EntryOrTree, Tree};
+
+// How many of the preceding objects each one is tried as a delta against.
+const DELTA_WINDOW: usize = 10;
+// The longest chain of deltas an object may sit at the end of.
+const MAX_DELTA_DEPTH: usize = 50;
+// Objects smaller than this are never worth storing as deltas.
+const MIN_DELTA_SIZE: usize = 50;
+const GITLINK_MODE: u32 = 0o160000;
+const LARGE_OFFSET_LIMIT: u64 = 0x8000_0000;
+const PACK_FILE_MODE: u32 = 0o444;
+
+struct PackEntry {
+    object_id: ObjectId,
+    kind: Kind,
+    data: Vec<u8>,
+    name_hash: u32,
+    // The index of the base entry and the delta from it.
+    delta: Option<(usize, Vec<u8>)>,
+    depth: usize,
+}
+
+/*
+    Collects objects and writes them out as a pack with its index, the
+    way git's pack-objects does: objects are compared against the ones
+    before them in a sliding window, sorted so that objects of the same
+    type at similar paths and with similar sizes end up close together,
+    and stored as a delta from the best match found.
+*/
+pub struct PackWriter<'a> {
+    database: &'a Database,
+    entries: Vec<PackEntry>,
+    seen: HashSet<ObjectId>,
+    skip_packed: bool,
+}
+
+impl<'a> PackWriter<'a> {
+    /*
+        A writer for objects from database. With skip_packed, objects
+        that are in a pack already are walked through but left out.
+    */
+    pub fn new(database: &'a Database, skip_packed: bool) -> Self {
+        PackWriter {
+            database,
+            entries: Vec::new(),
+            seen: HashSet::new(),
+            skip_packed,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /*
+        Adds every commit reachable from the given commits, newest first,
+        and then the trees and blobs they hold.
+    */
+    pub fn add_history(&mut self, commits: &[ObjectId]) -> io::Result<()> {
+        let mut trees = Vec::new();
+        for commit in RevList::new(self.database, commits)? {
+            let commit = commit?;
+            let object_id = commit.object_id.expect("loaded commits have an ID");
+            if self.seen.contains(&object_id) {
+                continue;
+            }
+            self.add_object(&object_id, "")?;
+            trees.push(commit.tree_object_id);
+        }
+        for tree in trees {
+            self.add_tree(&tree, "")?;
+        }
+        Ok(())
+    }
+
+    /*
+        Adds a tree and, recursively, everything in it. path is where it
+        sits, which groups versions of the same file as delta candidates.
+    */
+    pub fn add_tree(&mut self, object_id: &ObjectId, path: &str) -> io::Result<()> {
+        if self.seen.contains(object_id) {
+            return Ok(());
+        }
+        self.add_object(object_id, path)?;
+        let (_, data) = self.database.read_object(object_id)?;
+        let tree = Tree::parse(&data)?;
+        for name in &tree.entries_order {
+            let EntryOrTree::Entry(entry) = &tree.entries[name] else {
+                continue;
+            };
+            let entry_path = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", path, name)
+            };
+            match entry.mode {
+                DIRECTORY_MODE => self.add_tree(&entry.object_id, &entry_path)?,
+                GITLINK_MODE => {}
+                _ => self.add_object(&entry.object_id, &entry_path)?,
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Adds a single object, unless it has been added already.
+    */
+    pub fn add_object(&mut self, object_id: &ObjectId, path: &str) -> io::Result<()> {
+        if !self.seen.insert(*object_id) {
+            return Ok(());
+        }
+        if self.skip_packed
+            && self
+                .database
+                .packs()?
+                .iter()
+                .any(|pack| pack.contains(object_id))
+        {
+            return Ok(());
+        }
+        let (kind, data) = self.database.read_object(object_id)?;
+        self.entries.push(PackEntry {
+            object_id: *object_id,
+            kind,
+            data,
+            name_hash: name_hash(path),
+            delta: None,
+            depth: 0,
+        });
+        Ok(())
+    }
+
+    /*
+        Writes the pack and its index to pack_dir as pack-<checksum>.pack
+        and .idx, and returns the path of the pack. The index is written
+        last, so a reader never finds a pack without all of its objects.
+    */
+    pub fn write(mut self, pack_dir: &Path) -> io::Result<PathBuf> {
+        self.find_deltas();
+
+        let mut pack = Vec::new();
+        pack.extend_from_slice(PACK_SIGNATURE);
+        pack.extend_from_slice(&PACK_VERSION.to_be_bytes());
+        pack.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        let mut offsets = vec![None; self.entries.len()];
+        let mut crcs = vec![0; self.entries.len()];
+        for index in 0..self.entries.len() {
+            self.write_entry(index, &mut pack, &mut offsets, &mut crcs)?;
+        }
+        let checksum = Sha1::digest(&pack);
+        pack.extend_from_slice(&checksum);
+
+        let mut objects: Vec<(ObjectId, u64, u32)> = self
+            .entries
+            .iter()
+            .zip(offsets)
+            .zip(crcs)
+            .map(|((entry, offset), crc)| {
+                (
+                    entry.object_id,
+                    offset.expect("every entry is written"),
+                    crc,
+                )
+            })
+            .collect();
+        objects.sort();
+        let index = build_index(&objects, &checksum);
+
+        fs::create_dir_all(pack_dir)?;
+        let checksum_id = ObjectId::from_bytes(&checksum).expect("SHA-1 digests are 20 bytes");
+        let name = format!("pack-{}", checksum_id);
+        let pack_path = pack_dir.join(format!("{}.pack", name));
+        write_file(pack_dir, &pack_path, &pack)?;
+        write_file(pack_dir, &pack_dir.join(format!("{}.idx", name)), &index)?;
+        Ok(pack_path)
+    }
+
+    /*
+        Picks a delta base for each object from the DELTA_WINDOW objects
+        before it in (type, path hash, size) order, largest first, so
+        deltas mostly remove data from a bigger, often newer, version.
+    */
+    fn find_deltas(&mut self) {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&index| {
+            let entry = &self.entries[index];
+            (
+                type_number(entry.kind),
+                entry.name_hash,
+                std::cmp::Reverse(entry.data.len()),
+                std::cmp::Reverse(index),
+            )
+        });
+
+        // Indexes of the bases in the window, each built on first use.
+        let mut indexes: HashMap<usize, DeltaIndex> = HashMap::new();
+        for position in 0..order.len() {
+            if position > DELTA_WINDOW {
+                indexes.remove(&order[position - DELTA_WINDOW - 1]);
+            }
+            let target = order[position];
+            let target_size = self.entries[target].data.len();
+            if target_size < MIN_DELTA_SIZE {
+                continue;
+            }
+            let mut best: Option<(usize, Vec<u8>)> = None;
+            for &base in order[position.saturating_sub(DELTA_WINDOW)..position]
+                .iter()
+                .rev()
+            {
+                let (base_entry, target_entry) = (&self.entries[base], &self.entries[target]);
+                if base_entry.kind != target_entry.kind
+                    || base_entry.depth >= MAX_DELTA_DEPTH
+                    || base_entry.data.len() < target_size / 32
+                {
+                    continue;
+                }
+                // Deeper bases must save more to be worth the longer chain.
+                let mut max_size = (target_size / 2).saturating_sub(ObjectId::LEN)
+                    * (MAX_DELTA_DEPTH - base_entry.depth)
+                    / MAX_DELTA_DEPTH;
+                if let Some((_, delta)) = &best {
+                    max_size = max_size.min(delta.len().saturating_sub(1));
+                }
+                let index = indexes
+                    .entry(base)
+                    .or_insert_with(|| DeltaIndex::new(&base_entry.data));
+                if let Some(delta) =
+                    index.create_delta(&base_entry.data, &target_entry.data, Some(max_size))
+                {
+                    best = Some((base, delta));
+                }
+            }
+            if let Some((base, delta)) = best {
+                self.entries[target].depth = self.entries[base].depth + 1;
+                self.entries[target].delta = Some((base, delta));
+            }
+        }
+    }
+
+    // Writes an entry, after its delta base if it has one.
+    fn write_entry(
+        &self,
+        index: usize,
+        pack: &mut Vec<u8>,
+        offsets: &mut [Option<u64>],
+        crcs: &mut [u32],
+    ) -> io::Result<()> {
+        if offsets[index].is_some() {
+            return Ok(());
+        }
+        let entry = &self.entries[index];
+        if let Some((base, _)) = entry.delta {
+            self.write_entry(base, pack, offsets, crcs)?;
+        }
+        let offset = pack.len() as u64;
+        let start = pack.len();
+        let data = match &entry.delta {
+            Some((base, delta)) => {
+                write_entry_header(pack, OBJ_OFS_DELTA, delta.len());
+                let distance = offset - offsets[*base].expect("bases are written first");
+                write_offset_distance(pack, distance);
+                delta
+            }
+            None => {
+                write_entry_header(pack, type_number(entry.kind), entry.data.len());
+                &entry.data
+            }
+        };
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+        encoder.write_all(data)?;
+        pack.extend_from_slice(&encoder.finish()?);
+
+        let mut crc = Crc::new();
+        crc.update(&pack[start..]);
+        crcs[index] = crc.sum();
+        offsets[index] = Some(offset);
+        Ok(())
+    }
+}
+
+/*
+    git's pack_name_hash: mostly the last sixteen non-space characters of
+    the path, so files with the same name sort together wherever they are.
+*/
+fn name_hash(path: &str) -> u32 {
+    let mut hash: u32 = 0;
+    for byte in path.bytes().filter(|byte| !byte.is_ascii_whitespace()) {
+        hash = (hash >> 2).wrapping_add((byte as u32) << 24);
+    }
+    hash
+}
+
+fn type_number(kind: Kind) -> u8 {
+    match kind {
+        Kind::Commit => OBJ_COMMIT,
+        Kind::Tree => OBJ_TREE,
+        Kind::Blob => OBJ_BLOB,
+        Kind::Tag => OBJ_TAG,
+    }
+}
+
+fn write_entry_header(pack: &mut Vec<u8>, object_type: u8, size: usize) {
+    let mut byte = (object_type << 4) | (size & 0x0f) as u8;
+    let mut rest = size >> 4;
+    while rest > 0 {
+        pack.push(byte | 0x80);
+        byte = (rest & 0x7f) as u8;
+        rest >>= 7;
+    }
+    pack.push(byte);
+}
+
+fn write_offset_distance(pack: &mut Vec<u8>, mut distance: u64) {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    pack.extend_from_slice(&bytes);
+}
+
+/*
+    A version 2 index for the objects of a pack, sorted by ID, each with
+    its offset in the pack and the CRC-32 of its packed bytes.
+*/
+fn build_index(objects: &[(ObjectId, u64, u32)], pack_checksum: &[u8]) -> Vec<u8> {
+    let mut index = Vec::new();
+    index.extend_from_slice(IDX_SIGNATURE);
+    index.extend_from_slice(&IDX_VERSION.to_be_bytes());
+    let mut fanout = [0u32; 256];
+    for (object_id, _, _) in objects {
+        fanout[object_id.as_bytes()[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        index.extend_from_slice(&total.to_be_bytes());
+    }
+    for (object_id, _, _) in objects {
+        index.extend_from_slice(object_id.as_bytes());
+    }
+    for (_, _, crc) in objects {
+        index.extend_from_slice(&crc.to_be_bytes());
+    }
+    let mut large_offsets = Vec::new();
+    for (_, offset, _) in objects {
+        if *offset < LARGE_OFFSET_LIMIT {
+            index.extend_from_slice(&(*offset as u32).to_be_bytes());
+        } else {
+            let position = (large_offsets.len() / 8) as u32;
+            index.extend_from_slice(&(position | LARGE_OFFSET_LIMIT as u32).to_be_bytes());
+            large_offsets.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+    index.extend_from_slice(&large_offsets);
+    index.extend_from_slice(pack_checksum);
+    let checksum = Sha1::digest(&index);
+    index.extend_from_slice(&checksum);
+    index
+}
+
+// Packs are never modified once written, so like git they are read-only.
+fn write_file(dir: &Path, path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temp_file = NamedTempFile::new_in(dir)?;
+    temp_file.write_all(data)?;
+    temp_file
+        .as_file()
+        .set_permissions(fs::Permissions::from_mode(PACK_FILE_MODE))?;
+    temp_file.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
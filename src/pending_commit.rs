@@ -0,0 +1,73 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::object_id::ObjectId;
+use crate::utils;
+
+const MERGE_HEAD: &str = "MERGE_HEAD";
+const MERGE_MSG: &str = "MERGE_MSG";
+const MERGE_MODE: &str = "MERGE_MODE";
+
+/*
+    A merge stopped by conflicts, remembered in .git the way git does:
+    MERGE_HEAD holds the commit being merged, MERGE_MSG the message the
+    merge commit will get and MERGE_MODE the merge options (none here).
+*/
+pub struct PendingCommit {
+    head_path: PathBuf,
+    message_path: PathBuf,
+    mode_path: PathBuf,
+}
+
+impl PendingCommit {
+    pub fn new(git_path: &Path) -> Self {
+        PendingCommit {
+            head_path: git_path.join(MERGE_HEAD),
+            message_path: git_path.join(MERGE_MSG),
+            mode_path: git_path.join(MERGE_MODE),
+        }
+    }
+
+    pub fn start(&self, object_id: &ObjectId, message: &str) -> io::Result<()> {
+        fs::write(&self.head_path, format!("{}\n", object_id))?;
+        fs::write(&self.message_path, message)?;
+        fs::write(&self.mode_path, "")
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.head_path.is_file()
+    }
+
+    pub fn merge_object_id(&self) -> io::Result<Option<ObjectId>> {
+        match fs::read_to_string(&self.head_path) {
+            Ok(contents) => ObjectId::from_hex(contents.trim_end())
+                .map(Some)
+                .map_err(|err| utils::invalid_data(&err.to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn merge_message(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(&self.message_path) {
+            Ok(message) => Ok(Some(message)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /*
+        Forgets the merge, once its commit is made.
+    */
+    pub fn clear(&self) -> io::Result<()> {
+        for path in [&self.head_path, &self.message_path, &self.mode_path] {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
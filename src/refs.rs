@@ -1,26 +1,193 @@
 use std::{
-    error, fmt,
-    fs::File,
-    io::{self, Read},
-    path::PathBuf,
+    env, error, fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
 
+use chrono::Local;
+
+use crate::author::Author;
+use crate::database::{Database, GitObject};
 use crate::lockfile;
+use crate::object_id::ObjectId;
+use crate::utils;
+
+pub const HEAD: &str = "HEAD";
+pub const REFS_DIR: &str = "refs";
+pub const HEADS_DIR: &str = "refs/heads";
+pub const TAGS_DIR: &str = "refs/tags";
+// Same limit git uses when following chains of symbolic refs.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+pub const LOGS_DIR: &str = "logs";
+pub const PACKED_REFS: &str = "packed-refs";
+// The traits a packed-refs file starts with. fully-peeled promises that
+// every ref which peels to another object has a "^" line; without it git
+// peels refs that have none itself.
+const PACKED_REFS_TRAITS: &str = "# pack-refs with: ";
+const FULLY_PEELED_TRAITS: &str = "peeled fully-peeled sorted ";
+const SORTED_TRAITS: &str = "sorted ";
+// Refs whose updates are logged even before they have a reflog, as with
+// git's default core.logAllRefUpdates in a repository with a work tree.
+const LOGGED_PREFIXES: [&str; 3] = ["refs/heads/", "refs/remotes/", "refs/notes/"];
 
 #[derive(Debug)]
 pub enum RefsError {
-    LockDenied,
+    LockDenied(PathBuf),
+    SymrefCycle(String),
+    InvalidBranchName(String),
+    BranchExists(String),
+    BranchNotFound(String),
+    RefExists(String),
+    RefMissing(String),
+    RefMoved {
+        name: String,
+        actual: ObjectId,
+        expected: ObjectId,
+    },
+    DuplicateUpdate(String),
+    TransactionPrepared,
+    TransactionClosed,
+    Io(io::Error),
 }
 
 impl error::Error for RefsError {}
 
 impl fmt::Display for RefsError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let err = self;
-        write!(f, "{}", err)
+        match self {
+            RefsError::LockDenied(path) => {
+                write!(f, "Unable to create '{}': File exists.", path.display())
+            }
+            RefsError::SymrefCycle(name) => {
+                write!(f, "symbolic ref cycle detected at '{}'", name)
+            }
+            RefsError::InvalidBranchName(name) => {
+                write!(f, "'{}' is not a valid branch name", name)
+            }
+            RefsError::BranchExists(name) => {
+                write!(f, "a branch named '{}' already exists", name)
+            }
+            RefsError::BranchNotFound(name) => write!(f, "branch '{}' not found.", name),
+            RefsError::RefExists(name) => {
+                write!(f, "cannot lock ref '{}': reference already exists", name)
+            }
+            RefsError::RefMissing(name) => write!(
+                f,
+                "cannot lock ref '{}': unable to resolve reference '{}'",
+                name, name
+            ),
+            RefsError::RefMoved {
+                name,
+                actual,
+                expected,
+            } => write!(
+                f,
+                "cannot lock ref '{}': is at {} but expected {}",
+                name, actual, expected
+            ),
+            RefsError::DuplicateUpdate(name) => {
+                write!(f, "multiple updates for ref '{}' not allowed", name)
+            }
+            RefsError::TransactionPrepared => {
+                write!(f, "prepared transactions can only be closed")
+            }
+            RefsError::TransactionClosed => write!(f, "transaction is closed"),
+            RefsError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for RefsError {
+    fn from(err: io::Error) -> Self {
+        RefsError::Io(err)
+    }
+}
+
+/*
+    The contents of a ref file: either "ref: <name>" pointing at another ref,
+    or the hex ID of an object.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ref {
+    Symbolic(String),
+    Direct(ObjectId),
+}
+
+impl Ref {
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        let contents = contents.trim_end();
+        match contents.strip_prefix("ref:") {
+            Some(target) => Ok(Ref::Symbolic(target.trim().to_string())),
+            None => ObjectId::from_hex(contents)
+                .map(Ref::Direct)
+                .map_err(|err| utils::invalid_data(&err.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Ref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ref::Symbolic(target) => write!(f, "ref: {}", target),
+            Ref::Direct(object_id) => write!(f, "{}", object_id),
+        }
+    }
+}
+
+/*
+    One line of a reflog: "<old> <new> <name> <<email>> <time> <tz>\t<message>".
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub old_object_id: ObjectId,
+    pub new_object_id: ObjectId,
+    pub committer: Author,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    pub fn parse(line: &str) -> Option<Self> {
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = header.splitn(3, ' ');
+        let old_object_id = ObjectId::from_hex(fields.next()?).ok()?;
+        let new_object_id = ObjectId::from_hex(fields.next()?).ok()?;
+        let committer = Author::parse(fields.next()?)?;
+        Some(ReflogEntry {
+            old_object_id,
+            new_object_id,
+            committer,
+            message: message.to_string(),
+        })
     }
 }
 
+impl fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.old_object_id, self.new_object_id, self.committer
+        )?;
+        // Like git, an entry without a message has no tab either.
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/*
+    One ref in packed-refs: "<id> <name>", followed by "^<id>" when the
+    object is an annotated tag, giving the object the tag peels to.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub object_id: ObjectId,
+    pub peeled: Option<ObjectId>,
+}
+
 #[derive(Debug)]
 pub struct Refs {
     pub pathname: PathBuf,
@@ -31,42 +198,1021 @@ impl Refs {
         Self { pathname }
     }
 
-    pub fn update_head(&self, commit_hex_str: String) -> Result<(), RefsError> {
-        let mut lockfile = lockfile::LockFile::new(self.pathname.clone());
+    pub fn head_path(&self) -> PathBuf {
+        self.pathname.join(HEAD)
+    }
+
+    pub fn ref_path(&self, name: &str) -> PathBuf {
+        self.pathname.join(name)
+    }
+
+    /*
+        Reads a single ref without following symbolic refs: its loose file
+        if there is one, otherwise its line in packed-refs.
+    */
+    pub fn read_ref_file(&self, name: &str) -> io::Result<Option<Ref>> {
+        if let Some(value) = self.read_loose_ref(name)? {
+            return Ok(Some(value));
+        }
+        Ok(self
+            .read_packed_refs()?
+            .into_iter()
+            .find(|packed| packed.name == name)
+            .map(|packed| Ref::Direct(packed.object_id)))
+    }
+
+    fn read_loose_ref(&self, name: &str) -> io::Result<Option<Ref>> {
+        match fs::read_to_string(self.ref_path(name)) {
+            Ok(contents) => Ref::parse(&contents).map(Some),
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                    || err.kind() == io::ErrorKind::IsADirectory =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /*
+        The refs in packed-refs, in file order. A missing file has none.
+    */
+    pub fn read_packed_refs(&self) -> io::Result<Vec<PackedRef>> {
+        Ok(self.load_packed_refs()?.0)
+    }
+
+    /*
+        The refs in packed-refs, and whether its header says they are
+        fully peeled.
+    */
+    fn load_packed_refs(&self) -> io::Result<(Vec<PackedRef>, bool)> {
+        let contents = match fs::read_to_string(self.pathname.join(PACKED_REFS)) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), false)),
+            Err(err) => return Err(err),
+        };
+        let mut packed_refs: Vec<PackedRef> = Vec::new();
+        let mut fully_peeled = false;
+        for line in contents.lines() {
+            if let Some(traits) = line.strip_prefix(PACKED_REFS_TRAITS) {
+                fully_peeled = traits.split_whitespace().any(|name| name == "fully-peeled");
+                continue;
+            }
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let invalid =
+                || utils::invalid_data(&format!("unexpected line in packed-refs: '{}'", line));
+            if let Some(hex) = line.strip_prefix('^') {
+                let peeled = ObjectId::from_hex(hex).map_err(|_| invalid())?;
+                let last = packed_refs.last_mut().ok_or_else(invalid)?;
+                last.peeled = Some(peeled);
+                continue;
+            }
+            let (hex, name) = line.split_once(' ').ok_or_else(invalid)?;
+            packed_refs.push(PackedRef {
+                name: name.to_string(),
+                object_id: ObjectId::from_hex(hex).map_err(|_| invalid())?,
+                peeled: None,
+            });
+        }
+        Ok((packed_refs, fully_peeled))
+    }
+
+    /*
+        Follows symbolic refs starting at name, returning the name of the ref
+        that finally holds (or would hold) an object ID. For an attached HEAD
+        this is the current branch, for a detached HEAD it is HEAD itself.
+    */
+    pub fn resolve_name(&self, name: &str) -> io::Result<String> {
+        let mut current = name.to_string();
+        for _ in 0..=MAX_SYMREF_DEPTH {
+            match self.read_ref_file(&current)? {
+                Some(Ref::Symbolic(target)) => current = target,
+                _ => return Ok(current),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            RefsError::SymrefCycle(name.to_string()),
+        ))
+    }
+
+    /*
+        Resolves name to an object ID, following symbolic refs. An unborn
+        branch, or a missing ref, reads as None.
+    */
+    pub fn read_ref(&self, name: &str) -> io::Result<Option<ObjectId>> {
+        let resolved = self.resolve_name(name)?;
+        match self.read_ref_file(&resolved)? {
+            Some(Ref::Direct(object_id)) => Ok(Some(object_id)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn read_head(&self) -> io::Result<Option<ObjectId>> {
+        self.read_ref(HEAD)
+    }
+
+    /*
+        The full name of the ref HEAD points at, or "HEAD" when detached.
+    */
+    pub fn current_ref(&self) -> io::Result<String> {
+        self.resolve_name(HEAD)
+    }
+
+    /*
+        The short name of the checked out branch, None when HEAD is detached.
+    */
+    pub fn current_branch(&self) -> io::Result<Option<String>> {
+        let current = self.current_ref()?;
+        Ok(current
+            .strip_prefix(HEADS_DIR)
+            .and_then(|name| name.strip_prefix('/'))
+            .map(String::from))
+    }
+
+    /*
+        Moves whatever HEAD resolves to: the current branch when HEAD is
+        symbolic, HEAD itself when detached.
+    */
+    pub fn update_head(&self, object_id: &ObjectId, message: &str) -> Result<(), RefsError> {
+        let target = self.current_ref()?;
+        self.update_ref(&target, object_id, message)
+    }
+
+    /*
+        Points name at object_id. A change is logged with message in the
+        ref's reflog, and in HEAD's too when name is the current branch.
+    */
+    pub fn update_ref(
+        &self,
+        name: &str,
+        object_id: &ObjectId,
+        message: &str,
+    ) -> Result<(), RefsError> {
+        let old = match self.read_ref_file(name)? {
+            Some(Ref::Direct(old)) => old,
+            _ => ObjectId::default(),
+        };
+        self.write_ref_file(name, &Ref::Direct(*object_id))?;
+        if old != *object_id {
+            self.log_ref_update(name, &old, object_id, message)?;
+            if name != HEAD && self.current_ref()? == name {
+                self.log_ref_update(HEAD, &old, object_id, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Points HEAD at a branch ("ref: refs/heads/<name>") or, for a detached
+        HEAD, directly at a commit. The move is logged in HEAD's reflog
+        unless HEAD is left on an unborn branch.
+    */
+    pub fn set_head(&self, value: &Ref, message: &str) -> Result<(), RefsError> {
+        let old = self.read_head()?.unwrap_or_default();
+        self.write_ref_file(HEAD, value)?;
+        if let Some(new) = self.read_head()? {
+            self.log_ref_update(HEAD, &old, &new, message)?;
+        }
+        Ok(())
+    }
+
+    pub fn reflog_path(&self, name: &str) -> PathBuf {
+        self.pathname.join(LOGS_DIR).join(name)
+    }
+
+    /*
+        The reflog of the full ref name, oldest entry first, or None when the
+        ref has no log. Lines that do not parse are skipped.
+    */
+    pub fn read_reflog(&self, name: &str) -> io::Result<Option<Vec<ReflogEntry>>> {
+        match fs::read_to_string(self.reflog_path(name)) {
+            Ok(contents) => Ok(Some(
+                contents.lines().filter_map(ReflogEntry::parse).collect(),
+            )),
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                    || err.kind() == io::ErrorKind::IsADirectory =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /*
+        Replaces the reflog of name with entries, under a lock on the log.
+    */
+    pub fn write_reflog(&self, name: &str, entries: &[ReflogEntry]) -> Result<(), RefsError> {
+        let mut lockfile = lockfile::LockFile::new(self.reflog_path(name));
         match lockfile.hold_for_update() {
             Ok(true) => {
-                // uncaught results!
-                let _ = lockfile.write(commit_hex_str);
-                let _ = lockfile.write(String::from("\n"));
-                let _ = lockfile.commit();
+                let content: String = entries.iter().map(|entry| format!("{}\n", entry)).collect();
+                write_lockfile(&mut lockfile, content.as_bytes())
+            }
+            Ok(false) => Err(RefsError::LockDenied(lockfile.lock_path().clone())),
+            Err(err) => Err(RefsError::Io(io::Error::other(err))),
+        }
+    }
+
+    /*
+        Appends an entry to the reflog of name, when the ref already has a
+        log or is one whose updates are always logged. Like git, whitespace
+        in the message is collapsed so the entry stays on one line.
+    */
+    fn log_ref_update(
+        &self,
+        name: &str,
+        old: &ObjectId,
+        new: &ObjectId,
+        message: &str,
+    ) -> io::Result<()> {
+        let path = self.reflog_path(name);
+        let logged = name == HEAD
+            || LOGGED_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        if !logged && !path.is_file() {
+            return Ok(());
+        }
+        let entry = ReflogEntry {
+            old_object_id: *old,
+            new_object_id: *new,
+            committer: reflog_identity(),
+            message: message.split_whitespace().collect::<Vec<_>>().join(" "),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        writeln!(file, "{}", entry)
+    }
+
+    /*
+        Removes the reflog of a deleted ref.
+    */
+    fn delete_reflog(&self, name: &str) -> io::Result<()> {
+        let log_path = self.reflog_path(name);
+        match fs::remove_file(&log_path) {
+            Ok(()) => {
+                self.prune_empty_parents(&log_path, &self.reflog_path(&self.ref_category(name)));
                 Ok(())
             }
-            Ok(false) => {
-                dbg!("false in update head");
-                Err(RefsError::LockDenied)
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /*
+        Starts a transaction that moves several refs together.
+    */
+    pub fn transaction(&self) -> RefTransaction<'_> {
+        RefTransaction {
+            refs: self,
+            updates: Vec::new(),
+            locked: Vec::new(),
+            state: TransactionState::Open,
+        }
+    }
+
+    pub fn branch_ref(name: &str) -> String {
+        format!("{}/{}", HEADS_DIR, name)
+    }
+
+    /*
+        Creates refs/heads/<name> pointing at object_id, refusing to replace
+        an existing branch unless force is set. start is the revision the
+        branch was created from, as the user named it, for the reflog.
+    */
+    pub fn create_branch(
+        &self,
+        name: &str,
+        object_id: &ObjectId,
+        start: &str,
+        force: bool,
+    ) -> Result<(), RefsError> {
+        if !is_valid_branch_name(name) {
+            return Err(RefsError::InvalidBranchName(name.to_string()));
+        }
+        let ref_name = Self::branch_ref(name);
+        let message = if self.read_ref_file(&ref_name)?.is_none() {
+            format!("branch: Created from {}", start)
+        } else if force {
+            format!("branch: Reset to {}", start)
+        } else {
+            return Err(RefsError::BranchExists(name.to_string()));
+        };
+        self.update_ref(&ref_name, object_id, &message)
+    }
+
+    /*
+        Deletes refs/heads/<name> and its reflog, returning the commit it
+        pointed at.
+    */
+    pub fn delete_branch(&self, name: &str) -> Result<ObjectId, RefsError> {
+        let ref_name = Self::branch_ref(name);
+        let Some(Ref::Direct(object_id)) = self.read_ref_file(&ref_name)? else {
+            return Err(RefsError::BranchNotFound(name.to_string()));
+        };
+        self.delete_ref_file(&ref_name)?;
+        self.delete_reflog(&ref_name)?;
+        Ok(object_id)
+    }
+
+    /*
+        Renames a branch along with its reflog, keeping HEAD attached
+        to it if it is the current branch.
+    */
+    pub fn rename_branch(&self, old: &str, new: &str, force: bool) -> Result<(), RefsError> {
+        let old_ref = Self::branch_ref(old);
+        let new_ref = Self::branch_ref(new);
+        let Some(Ref::Direct(object_id)) = self.read_ref_file(&old_ref)? else {
+            return Err(RefsError::BranchNotFound(old.to_string()));
+        };
+        if !is_valid_branch_name(new) {
+            return Err(RefsError::InvalidBranchName(new.to_string()));
+        }
+        if old == new {
+            return Ok(());
+        }
+        if !force && self.read_ref_file(&new_ref)?.is_some() {
+            return Err(RefsError::BranchExists(new.to_string()));
+        }
+
+        let is_current = self.current_ref()? == old_ref;
+        self.delete_ref_file(&old_ref)?;
+        self.write_ref_file(&new_ref, &Ref::Direct(object_id))?;
+
+        // The branch keeps its history; a log the new name had is dropped.
+        let old_log = self.reflog_path(&old_ref);
+        let new_log = self.reflog_path(&new_ref);
+        if old_log.exists() {
+            if let Some(parent) = new_log.parent() {
+                fs::create_dir_all(parent)?;
             }
-            Err(err) => {
-                dbg!(err);
-                Err(RefsError::LockDenied)
+            fs::rename(&old_log, &new_log)?;
+            self.prune_empty_parents(&old_log, &self.reflog_path(HEADS_DIR));
+        } else if new_log.exists() {
+            fs::remove_file(&new_log)?;
+        }
+        let message = format!("Branch: renamed {} to {}", old_ref, new_ref);
+        self.log_ref_update(&new_ref, &object_id, &object_id, &message)?;
+
+        if is_current {
+            self.write_ref_file(HEAD, &Ref::Symbolic(new_ref))?;
+            self.log_ref_update(HEAD, &object_id, &object_id, &message)?;
+        }
+        Ok(())
+    }
+
+    /*
+        Short names of every branch under refs/heads, sorted.
+    */
+    pub fn list_branches(&self) -> io::Result<Vec<String>> {
+        let prefix = format!("{}/", HEADS_DIR);
+        Ok(self
+            .list_refs()?
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(&prefix).map(String::from))
+            .collect())
+    }
+
+    /*
+        Full names of every ref under refs/, loose or packed, sorted.
+    */
+    pub fn list_refs(&self) -> io::Result<Vec<String>> {
+        let mut names = self.list_loose_refs()?;
+        names.extend(
+            self.read_packed_refs()?
+                .into_iter()
+                .map(|packed| packed.name),
+        );
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    fn list_loose_refs(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        self.collect_ref_names(&self.pathname.join(REFS_DIR), REFS_DIR, &mut names)?;
+        Ok(names)
+    }
+
+    /*
+        Moves loose refs into packed-refs, like git pack-refs: with all,
+        every ref under refs/, otherwise only tags and refs that are
+        packed already. Symbolic refs stay loose. With prune, the loose
+        files of the packed refs are removed. Every ref is peeled, so the
+        file is written fully peeled, as git writes it.
+    */
+    pub fn pack_refs(&self, database: &Database, all: bool, prune: bool) -> Result<(), RefsError> {
+        let mut lockfile = lockfile::LockFile::new(self.pathname.join(PACKED_REFS));
+        match lockfile.hold_for_update() {
+            Ok(true) => {}
+            Ok(false) => return Err(RefsError::LockDenied(lockfile.lock_path().clone())),
+            Err(err) => return Err(RefsError::Io(io::Error::other(err))),
+        }
+        let (mut packed_refs, fully_peeled) = self.load_packed_refs()?;
+        if !fully_peeled {
+            for packed in packed_refs
+                .iter_mut()
+                .filter(|packed| packed.peeled.is_none())
+            {
+                packed.peeled = peel_tag(database, &packed.object_id)?;
             }
         }
+        let mut packed_loose = Vec::new();
+        for name in self.list_loose_refs()? {
+            let Some(Ref::Direct(object_id)) = self.read_loose_ref(&name)? else {
+                continue;
+            };
+            let existing = packed_refs.iter().position(|packed| packed.name == name);
+            let is_tag = name
+                .strip_prefix(TAGS_DIR)
+                .is_some_and(|rest| rest.starts_with('/'));
+            if !all && !is_tag && existing.is_none() {
+                continue;
+            }
+            match existing {
+                Some(i) if packed_refs[i].object_id == object_id => {}
+                Some(i) => {
+                    packed_refs[i].object_id = object_id;
+                    packed_refs[i].peeled = peel_tag(database, &object_id)?;
+                }
+                None => {
+                    packed_refs.push(PackedRef {
+                        name: name.clone(),
+                        object_id,
+                        peeled: peel_tag(database, &object_id)?,
+                    });
+                }
+            }
+            packed_loose.push((name, object_id));
+        }
+        self.write_packed_refs(&mut lockfile, packed_refs, true)?;
+
+        if prune {
+            for (name, object_id) in packed_loose {
+                self.prune_loose_ref(&name, &object_id)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn head_path(&self) -> PathBuf {
-        self.pathname.join("HEAD")
-    }
-
-    pub fn read_head(&self) -> io::Result<String> {
-        let head_path = self.head_path();
-        let path = head_path.as_path();
-        if path.exists() {
-            let mut file = File::open(path)?;
-            let mut contents = String::new();
-            file.read_to_string(&mut contents)?;
-            let contents = contents.trim_end_matches('\n').to_string();
-            Ok(contents)
+    /*
+        Removes the loose file of a ref that has just been packed, unless
+        it was changed in the meantime.
+    */
+    fn prune_loose_ref(&self, name: &str, object_id: &ObjectId) -> Result<(), RefsError> {
+        let path = self.ref_path(name);
+        let mut lockfile = lockfile::LockFile::new(path.clone());
+        match lockfile.hold_for_update() {
+            Ok(true) => {
+                let result = match self.read_loose_ref(name) {
+                    Ok(Some(Ref::Direct(current))) if current == *object_id => {
+                        fs::remove_file(&path)
+                    }
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err),
+                };
+                let _ = lockfile.rollback();
+                result?;
+                self.prune_empty_parents(&path, &self.ref_category_dir(name));
+                Ok(())
+            }
+            // Somebody is updating the ref; its loose file stays.
+            Ok(false) => Ok(()),
+            Err(err) => Err(RefsError::Io(io::Error::other(err))),
+        }
+    }
+
+    /*
+        Rewrites packed-refs without the named refs, returning whether any
+        of them was there.
+    */
+    fn delete_packed_refs(&self, names: &[&str]) -> Result<bool, RefsError> {
+        if !self
+            .read_packed_refs()?
+            .iter()
+            .any(|packed| names.contains(&packed.name.as_str()))
+        {
+            return Ok(false);
+        }
+        let mut lockfile = lockfile::LockFile::new(self.pathname.join(PACKED_REFS));
+        match lockfile.hold_for_update() {
+            Ok(true) => {}
+            Ok(false) => return Err(RefsError::LockDenied(lockfile.lock_path().clone())),
+            Err(err) => return Err(RefsError::Io(io::Error::other(err))),
+        }
+        let (mut packed_refs, fully_peeled) = self.load_packed_refs()?;
+        packed_refs.retain(|packed| !names.contains(&packed.name.as_str()));
+        self.write_packed_refs(&mut lockfile, packed_refs, fully_peeled)?;
+        Ok(true)
+    }
+
+    /*
+        Writes packed-refs, sorted by name, through a lock already held.
+    */
+    fn write_packed_refs(
+        &self,
+        lockfile: &mut lockfile::LockFile,
+        mut packed_refs: Vec<PackedRef>,
+        fully_peeled: bool,
+    ) -> Result<(), RefsError> {
+        packed_refs.sort_by(|a, b| a.name.cmp(&b.name));
+        let traits = if fully_peeled {
+            FULLY_PEELED_TRAITS
         } else {
-            Ok(String::from(""))
+            SORTED_TRAITS
+        };
+        let mut content = format!("{}{}\n", PACKED_REFS_TRAITS, traits);
+        for packed in &packed_refs {
+            content.push_str(&format!("{} {}\n", packed.object_id, packed.name));
+            if let Some(peeled) = packed.peeled {
+                content.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        write_lockfile(lockfile, content.as_bytes())
+    }
+
+    fn collect_ref_names(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        names: &mut Vec<String>,
+    ) -> io::Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".lock") {
+                continue;
+            }
+            let full_name = if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            if entry.file_type()?.is_dir() {
+                self.collect_ref_names(&entry.path(), &full_name, names)?;
+            } else {
+                names.push(full_name);
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Deletes a ref from both its loose file and packed-refs, holding
+        the ref's lock throughout.
+    */
+    fn delete_ref_file(&self, name: &str) -> Result<(), RefsError> {
+        let path = self.ref_path(name);
+        // A packed ref may have no directory for its lock yet.
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut lockfile = lockfile::LockFile::new(path.clone());
+        match lockfile.hold_for_update() {
+            Ok(true) => {
+                let removed = self.remove_ref(name, &path);
+                let _ = lockfile.rollback();
+                removed?;
+                self.prune_empty_parents(&path, &self.ref_category_dir(name));
+                Ok(())
+            }
+            Ok(false) => Err(RefsError::LockDenied(lockfile.lock_path().clone())),
+            Err(err) => Err(RefsError::Io(io::Error::other(err))),
+        }
+    }
+
+    fn remove_ref(&self, name: &str, path: &Path) -> Result<(), RefsError> {
+        let loose = match fs::remove_file(path) {
+            Ok(()) => true,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err.into()),
+        };
+        if !self.delete_packed_refs(&[name])? && !loose {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("ref '{}' does not exist", name),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /*
+        The directory a ref lives in that is never removed when emptied:
+        refs/heads for branches, refs/tags for tags and so on.
+    */
+    fn ref_category_dir(&self, name: &str) -> PathBuf {
+        self.pathname.join(self.ref_category(name))
+    }
+
+    fn ref_category(&self, name: &str) -> String {
+        let category: Vec<&str> = name.splitn(3, '/').take(2).collect();
+        category.join("/")
+    }
+
+    /*
+        Removes directories left empty by deleting path, stopping at stop.
+    */
+    fn prune_empty_parents(&self, path: &Path, stop: &Path) {
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if current == stop || !current.starts_with(stop) || fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+
+    fn write_ref_file(&self, name: &str, value: &Ref) -> Result<(), RefsError> {
+        let path = self.ref_path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut lockfile = lockfile::LockFile::new(path);
+        match lockfile.hold_for_update() {
+            Ok(true) => {
+                let content = format!("{}\n", value);
+                write_lockfile(&mut lockfile, content.as_bytes())
+            }
+            Ok(false) => Err(RefsError::LockDenied(lockfile.lock_path().clone())),
+            Err(err) => Err(RefsError::Io(io::Error::other(err))),
+        }
+    }
+}
+
+/*
+    One change queued in a RefTransaction. new_object_id is None to only
+    verify the ref, and the null ID to delete it. old_object_id, when
+    given, is the value the ref must have, the null ID meaning that it
+    must not exist. Symbolic refs are followed unless no_deref is set.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub name: String,
+    pub new_object_id: Option<ObjectId>,
+    pub old_object_id: Option<ObjectId>,
+    pub no_deref: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransactionState {
+    Open,
+    Prepared,
+    Closed,
+}
+
+/*
+    A ref locked by a prepared transaction, with the value it had.
+*/
+struct LockedRef {
+    name: String,
+    lockfile: lockfile::LockFile,
+    old_object_id: Option<ObjectId>,
+    was_symbolic: bool,
+}
+
+/*
+    Moves several refs at once. prepare locks every ref, checks the
+    values they are expected to have and writes the new ones to the
+    locks; commit then renames all the locks into place. If anything
+    fails before that, or the transaction is aborted or dropped, every
+    lock is rolled back and no ref changes.
+*/
+pub struct RefTransaction<'a> {
+    refs: &'a Refs,
+    updates: Vec<RefUpdate>,
+    locked: Vec<LockedRef>,
+    state: TransactionState,
+}
+
+impl RefTransaction<'_> {
+    pub fn queue(&mut self, update: RefUpdate) -> Result<(), RefsError> {
+        match self.state {
+            TransactionState::Open => {}
+            TransactionState::Prepared => return Err(RefsError::TransactionPrepared),
+            TransactionState::Closed => return Err(RefsError::TransactionClosed),
         }
+        if self.updates.iter().any(|queued| queued.name == update.name) {
+            return Err(RefsError::DuplicateUpdate(update.name));
+        }
+        self.updates.push(update);
+        Ok(())
+    }
+
+    pub fn update(
+        &mut self,
+        name: &str,
+        new_object_id: &ObjectId,
+        old_object_id: Option<ObjectId>,
+        message: &str,
+    ) -> Result<(), RefsError> {
+        self.queue(RefUpdate {
+            name: name.to_string(),
+            new_object_id: Some(*new_object_id),
+            old_object_id,
+            no_deref: false,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn create(
+        &mut self,
+        name: &str,
+        new_object_id: &ObjectId,
+        message: &str,
+    ) -> Result<(), RefsError> {
+        self.update(name, new_object_id, Some(ObjectId::default()), message)
+    }
+
+    pub fn delete(
+        &mut self,
+        name: &str,
+        old_object_id: Option<ObjectId>,
+        message: &str,
+    ) -> Result<(), RefsError> {
+        self.update(name, &ObjectId::default(), old_object_id, message)
+    }
+
+    pub fn verify(&mut self, name: &str, old_object_id: Option<ObjectId>) -> Result<(), RefsError> {
+        self.queue(RefUpdate {
+            name: name.to_string(),
+            new_object_id: None,
+            old_object_id,
+            no_deref: false,
+            message: String::new(),
+        })
+    }
+
+    /*
+        Locks and checks every queued ref and writes the new values to the
+        locks. On failure all locks are released and the transaction is
+        closed.
+    */
+    pub fn prepare(&mut self) -> Result<(), RefsError> {
+        match self.state {
+            TransactionState::Open => {}
+            TransactionState::Prepared => return Ok(()),
+            TransactionState::Closed => return Err(RefsError::TransactionClosed),
+        }
+        match self.lock_all() {
+            Ok(()) => {
+                self.state = TransactionState::Prepared;
+                Ok(())
+            }
+            Err(err) => {
+                self.abort();
+                Err(err)
+            }
+        }
+    }
+
+    fn lock_all(&mut self) -> Result<(), RefsError> {
+        for update in &self.updates {
+            let name = if update.no_deref {
+                update.name.clone()
+            } else {
+                self.refs.resolve_name(&update.name)?
+            };
+            // Two names may lead to the same ref through a symbolic ref.
+            if self.locked.iter().any(|locked| locked.name == name) {
+                return Err(RefsError::DuplicateUpdate(update.name.clone()));
+            }
+            let path = self.refs.ref_path(&name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut lockfile = lockfile::LockFile::new(path);
+            match lockfile.hold_for_update() {
+                Ok(true) => {}
+                Ok(false) => return Err(RefsError::LockDenied(lockfile.lock_path().clone())),
+                Err(err) => return Err(RefsError::Io(io::Error::other(err))),
+            }
+            // Read only once the lock is held, so nobody moves the ref
+            // between the check and the write.
+            let was_symbolic = matches!(self.refs.read_ref_file(&name)?, Some(Ref::Symbolic(_)));
+            let current = self.refs.read_ref(&name)?;
+            self.locked.push(LockedRef {
+                name: name.clone(),
+                lockfile,
+                old_object_id: current,
+                was_symbolic,
+            });
+
+            match (update.old_object_id, current) {
+                (Some(expected), Some(_)) if expected == ObjectId::default() => {
+                    return Err(RefsError::RefExists(name));
+                }
+                (Some(expected), None) if expected != ObjectId::default() => {
+                    return Err(RefsError::RefMissing(name));
+                }
+                (Some(expected), Some(actual)) if expected != actual => {
+                    return Err(RefsError::RefMoved {
+                        name,
+                        actual,
+                        expected,
+                    });
+                }
+                _ => {}
+            }
+            if let Some(new) = update
+                .new_object_id
+                .filter(|new| *new != ObjectId::default())
+            {
+                let lockfile = &self.locked.last().expect("just locked").lockfile;
+                lockfile
+                    .write(format!("{}\n", new).as_bytes())
+                    .map_err(|err| RefsError::Io(io::Error::other(err)))?;
+            }
+        }
+        Ok(())
     }
+
+    /*
+        Prepares the transaction if need be, then moves every ref: new
+        values are renamed into place and deleted refs are removed from
+        packed-refs and their loose files. Changes are logged in the
+        reflogs as update_ref logs them.
+    */
+    pub fn commit(&mut self) -> Result<(), RefsError> {
+        self.prepare()?;
+        let head_target = self.refs.current_ref()?;
+        let deleted: Vec<&str> = self
+            .updates
+            .iter()
+            .zip(&self.locked)
+            .filter(|(update, _)| update.new_object_id == Some(ObjectId::default()))
+            .map(|(_, locked)| locked.name.as_str())
+            .collect();
+        if let Err(err) = self.refs.delete_packed_refs(&deleted) {
+            self.abort();
+            return Err(err);
+        }
+
+        let updates = std::mem::take(&mut self.updates);
+        let mut locked = std::mem::take(&mut self.locked).into_iter();
+        self.state = TransactionState::Closed;
+        let mut result = Ok(());
+        for (update, ref_lock) in updates.iter().zip(locked.by_ref()) {
+            result = self.commit_update(update, ref_lock, &head_target);
+            if result.is_err() {
+                break;
+            }
+        }
+        for ref_lock in locked {
+            self.release(ref_lock);
+        }
+        result
+    }
+
+    fn commit_update(
+        &self,
+        update: &RefUpdate,
+        mut ref_lock: LockedRef,
+        head_target: &str,
+    ) -> Result<(), RefsError> {
+        let name = ref_lock.name.clone();
+        match update.new_object_id {
+            None => self.release(ref_lock),
+            Some(new) if new == ObjectId::default() => {
+                let removed = fs::remove_file(self.refs.ref_path(&name));
+                self.release(ref_lock);
+                match removed {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                    _ => self.refs.delete_reflog(&name)?,
+                }
+            }
+            Some(new) => {
+                ref_lock
+                    .lockfile
+                    .commit()
+                    .map_err(|err| RefsError::Io(io::Error::other(err)))?;
+                let old = ref_lock.old_object_id.unwrap_or_default();
+                if old != new || ref_lock.was_symbolic {
+                    self.refs
+                        .log_ref_update(&name, &old, &new, &update.message)?;
+                    if name != HEAD && head_target == name {
+                        self.refs
+                            .log_ref_update(HEAD, &old, &new, &update.message)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Releases every lock without changing any ref.
+    */
+    pub fn abort(&mut self) {
+        for ref_lock in std::mem::take(&mut self.locked) {
+            self.release(ref_lock);
+        }
+        self.updates.clear();
+        self.state = TransactionState::Closed;
+    }
+
+    // Drops a lock along with directories made only to hold it.
+    fn release(&self, mut ref_lock: LockedRef) {
+        let _ = ref_lock.lockfile.rollback();
+        let path = self.refs.ref_path(&ref_lock.name);
+        self.refs
+            .prune_empty_parents(&path, &self.refs.ref_category_dir(&ref_lock.name));
+    }
+}
+
+impl Drop for RefTransaction<'_> {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
+
+/*
+    Writes data through a held lock and commits it, rolling the lock back
+    instead when the write fails.
+*/
+fn write_lockfile(lockfile: &mut lockfile::LockFile, data: &[u8]) -> Result<(), RefsError> {
+    if let Err(err) = lockfile.write(data) {
+        let _ = lockfile.rollback();
+        return Err(RefsError::Io(io::Error::other(err)));
+    }
+    lockfile
+        .commit()
+        .map_err(|err| RefsError::Io(io::Error::other(err)))
+}
+
+/*
+    Who moved a ref, for its reflog: the committer from GIT_COMMITTER_NAME
+    and GIT_COMMITTER_EMAIL, falling back to the author, dated now.
+*/
+fn reflog_identity() -> Author {
+    let identity = |kind: &str| {
+        env::var(format!("GIT_COMMITTER_{}", kind))
+            .or_else(|_| env::var(format!("GIT_AUTHOR_{}", kind)))
+            .unwrap_or_default()
+    };
+    let time = Local::now().format("%s %z").to_string();
+    Author::new(identity("NAME"), identity("EMAIL"), time)
+}
+
+/*
+    The object at the end of a chain of annotated tags starting at
+    object_id, or None when object_id is not a tag. Tags whose target
+    cannot be found are left unpeeled, as git leaves them.
+*/
+fn peel_tag(database: &Database, object_id: &ObjectId) -> io::Result<Option<ObjectId>> {
+    let mut peeled = None;
+    let mut current = *object_id;
+    loop {
+        match database.load(&current) {
+            Ok(GitObject::Tag(tag)) => {
+                current = tag.target_object_id;
+                peeled = Some(current);
+            }
+            Ok(_) => return Ok(peeled),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/*
+    Applies git's check-ref-format rules to a full or partial ref name.
+*/
+pub fn is_valid_ref_name(name: &str) -> bool {
+    if name.is_empty() || name == "@" || name.starts_with('/') || name.ends_with('/') {
+        return false;
+    }
+    if name.ends_with('.') || name.contains("..") || name.contains("@{") || name.contains("//") {
+        return false;
+    }
+    if name
+        .bytes()
+        .any(|b| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b))
+    {
+        return false;
+    }
+    name.split('/')
+        .all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/*
+    Branch names additionally may not start with "-" or be "HEAD".
+*/
+pub fn is_valid_branch_name(name: &str) -> bool {
+    !name.starts_with('-') && name != HEAD && is_valid_ref_name(name)
 }
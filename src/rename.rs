@@ -0,0 +1,404 @@
+use std::{collections::HashMap, io};
+
+use crate::database::Database;
+use crate::diff;
+use crate::entry::Entry;
+use crate::object_id::ObjectId;
+use crate::tree_diff::{ChangeKind, TreeChange, TYPE_MASK};
+
+// Similarity scores are fractions of MAX_SCORE, as in git's diffcore.
+pub const MAX_SCORE: u64 = 60000;
+pub const DEFAULT_SCORE: u64 = 30000;
+
+// Content is hashed in chunks ending at a newline or at this many bytes.
+const CHUNK_LEN: usize = 64;
+const HASH_BASE: u32 = 107927;
+
+/*
+    Which pairings to look for. Copies are only detected from files that
+    changed, unless find_copies_harder also offers unmodified files.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenameOptions {
+    pub renames: bool,
+    pub copies: bool,
+    pub find_copies_harder: bool,
+    pub min_score: u64,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            renames: true,
+            copies: false,
+            find_copies_harder: false,
+            min_score: DEFAULT_SCORE,
+        }
+    }
+}
+
+impl RenameOptions {
+    /*
+        Applies a -M[<n>], -C[<n>], --find-renames[=<n>], --find-copies[=<n>],
+        --find-copies-harder or --no-renames argument, returning false for
+        anything else.
+    */
+    pub fn parse_option(&mut self, arg: &str) -> bool {
+        let (copies, score) = if let Some(score) = arg.strip_prefix("--find-renames") {
+            (false, score.strip_prefix('='))
+        } else if let Some(score) = arg.strip_prefix("--find-copies") {
+            if score == "-harder" {
+                self.renames = true;
+                self.copies = true;
+                self.find_copies_harder = true;
+                return true;
+            }
+            (true, score.strip_prefix('='))
+        } else if let Some(score) = arg.strip_prefix("-M") {
+            (false, Some(score))
+        } else if let Some(score) = arg.strip_prefix("-C") {
+            (true, Some(score))
+        } else if arg == "--no-renames" {
+            self.renames = false;
+            self.copies = false;
+            return true;
+        } else {
+            return false;
+        };
+        self.renames = true;
+        self.copies |= copies;
+        if let Some(score) = score.filter(|score| !score.is_empty()) {
+            match parse_score(score) {
+                Some(score) => self.min_score = score,
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+/*
+    Reads a score the way git does: digits are a fraction ("5" and "50"
+    both mean 50%) unless followed by "%".
+*/
+pub fn parse_score(text: &str) -> Option<u64> {
+    let (mut num, mut scale) = (0, 1);
+    let mut dot = false;
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'.' if !dot => {
+                scale = 1;
+                dot = true;
+            }
+            b'%' => {
+                scale = if dot { scale * 100 } else { 100 };
+                if bytes.next().is_some() {
+                    return None;
+                }
+                break;
+            }
+            b'0'..=b'9' if scale < 100000 => {
+                scale *= 10;
+                num = num * 10 + (b - b'0') as u64;
+            }
+            b'0'..=b'9' => {}
+            _ => return None,
+        }
+    }
+    Some(if num >= scale {
+        MAX_SCORE
+    } else {
+        MAX_SCORE * num / scale
+    })
+}
+
+pub fn similarity_percent(score: u64) -> u32 {
+    (score * 100 / MAX_SCORE) as u32
+}
+
+struct Source {
+    entry: Entry,
+    deleted: bool,
+    uses: usize,
+}
+
+/*
+    Pairs added files with deleted ones (renames) and, if asked, with
+    changed or unchanged files (copies). Identical blobs are paired first
+    without reading them; the rest are scored by how many bytes of the
+    source's content chunks reappear in the destination. unchanged lists
+    the old tree's unmodified files, used by find_copies_harder.
+    Returns the changes sorted by their new path.
+*/
+pub fn detect_renames(
+    database: &Database,
+    changes: Vec<TreeChange>,
+    unchanged: &[Entry],
+    options: &RenameOptions,
+) -> io::Result<Vec<TreeChange>> {
+    if !options.renames {
+        return Ok(changes);
+    }
+    let mut sources = Vec::new();
+    for change in &changes {
+        let Some(old) = &change.old else {
+            continue;
+        };
+        let deleted = change.kind == ChangeKind::Deleted;
+        if deleted || options.copies {
+            sources.push(Source {
+                entry: old.clone(),
+                deleted,
+                uses: 0,
+            });
+        }
+    }
+    if options.find_copies_harder {
+        for entry in unchanged {
+            sources.push(Source {
+                entry: entry.clone(),
+                deleted: false,
+                uses: 0,
+            });
+        }
+    }
+    let destinations: Vec<usize> = changes
+        .iter()
+        .enumerate()
+        .filter(|(_, change)| change.kind == ChangeKind::Added)
+        .map(|(i, _)| i)
+        .collect();
+    if sources.is_empty() || destinations.is_empty() {
+        return Ok(changes);
+    }
+
+    let mut matches: HashMap<usize, (usize, u64)> = HashMap::new();
+    find_exact_matches(&changes, &destinations, &mut sources, &mut matches, options);
+
+    let mut contents = HashMap::new();
+    find_inexact_matches(
+        database,
+        &changes,
+        &destinations,
+        &mut sources,
+        &mut matches,
+        &mut contents,
+        options.min_score,
+        false,
+    )?;
+    if options.copies {
+        find_inexact_matches(
+            database,
+            &changes,
+            &destinations,
+            &mut sources,
+            &mut matches,
+            &mut contents,
+            options.min_score,
+            true,
+        )?;
+    }
+
+    // The last destination to take a deleted file is its rename, earlier
+    // ones are copies of it.
+    let mut remaining: Vec<usize> = sources.iter().map(|source| source.uses).collect();
+    let mut result = Vec::new();
+    for (i, change) in changes.iter().enumerate() {
+        if let Some(&(source_index, score)) = matches.get(&i) {
+            let source = &sources[source_index];
+            remaining[source_index] -= 1;
+            let percent = similarity_percent(score);
+            let kind = if source.deleted && remaining[source_index] == 0 {
+                ChangeKind::Renamed(percent)
+            } else {
+                ChangeKind::Copied(percent)
+            };
+            result.push(TreeChange {
+                path: change.path.clone(),
+                kind,
+                old: Some(source.entry.clone()),
+                new: change.new.clone(),
+            });
+        } else if change.kind == ChangeKind::Deleted {
+            let renamed = sources.iter().any(|source| {
+                source.deleted && source.uses > 0 && Some(&source.entry) == change.old.as_ref()
+            });
+            if !renamed {
+                result.push(change.clone());
+            }
+        } else {
+            result.push(change.clone());
+        }
+    }
+    result.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(result)
+}
+
+// A file is never paired with a symlink, whatever their content.
+fn same_type(a: &Entry, b: &Entry) -> bool {
+    a.mode & TYPE_MASK == b.mode & TYPE_MASK
+}
+
+fn basename(entry: &Entry) -> String {
+    entry
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/*
+    Destinations whose blob already exists among the sources. Unused
+    deleted files and files with the same basename are preferred.
+*/
+fn find_exact_matches(
+    changes: &[TreeChange],
+    destinations: &[usize],
+    sources: &mut [Source],
+    matches: &mut HashMap<usize, (usize, u64)>,
+    options: &RenameOptions,
+) {
+    for &i in destinations {
+        let Some(new) = &changes[i].new else {
+            continue;
+        };
+        let name = basename(new);
+        let best = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.entry.object_id == new.object_id)
+            .filter(|(_, source)| same_type(&source.entry, new))
+            .filter(|(_, source)| options.copies || (source.deleted && source.uses == 0))
+            .max_by_key(|(index, source)| {
+                (
+                    source.deleted && source.uses == 0,
+                    basename(&source.entry) == name,
+                    std::cmp::Reverse(*index),
+                )
+            })
+            .map(|(index, _)| index);
+        if let Some(index) = best {
+            sources[index].uses += 1;
+            matches.insert(i, (index, MAX_SCORE));
+        }
+    }
+}
+
+/*
+    Scores every remaining destination against the sources and takes the
+    best pairs first. The rename pass only uses deleted files nobody has
+    taken yet; the copy pass may reuse any source.
+*/
+#[allow(clippy::too_many_arguments)]
+fn find_inexact_matches(
+    database: &Database,
+    changes: &[TreeChange],
+    destinations: &[usize],
+    sources: &mut [Source],
+    matches: &mut HashMap<usize, (usize, u64)>,
+    contents: &mut HashMap<ObjectId, Vec<u8>>,
+    min_score: u64,
+    copies: bool,
+) -> io::Result<()> {
+    let mut candidates = Vec::new();
+    for &i in destinations {
+        if matches.contains_key(&i) {
+            continue;
+        }
+        let Some(new) = &changes[i].new else {
+            continue;
+        };
+        for (index, source) in sources.iter().enumerate() {
+            if !copies && (!source.deleted || source.uses > 0) || !same_type(&source.entry, new) {
+                continue;
+            }
+            let src = load(database, contents, &source.entry)?;
+            let dst = load(database, contents, new)?;
+            let score = estimate_similarity(&src, &dst, min_score);
+            if score >= min_score && score > 0 {
+                candidates.push((score, basename(&source.entry) == basename(new), i, index));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+    for (score, _, i, index) in candidates {
+        if matches.contains_key(&i) || (!copies && sources[index].uses > 0) {
+            continue;
+        }
+        sources[index].uses += 1;
+        matches.insert(i, (index, score));
+    }
+    Ok(())
+}
+
+fn load(
+    database: &Database,
+    contents: &mut HashMap<ObjectId, Vec<u8>>,
+    entry: &Entry,
+) -> io::Result<Vec<u8>> {
+    if let Some(data) = contents.get(&entry.object_id) {
+        return Ok(data.clone());
+    }
+    let (_, data) = database.read_object(&entry.object_id)?;
+    contents.insert(entry.object_id, data.clone());
+    Ok(data)
+}
+
+/*
+    git's estimate: the bytes of src's chunks that also appear in dst,
+    as a fraction of the larger file. Pairs whose sizes alone rule out
+    reaching min_score are not hashed at all.
+*/
+pub fn estimate_similarity(src: &[u8], dst: &[u8], min_score: u64) -> u64 {
+    let max_size = src.len().max(dst.len()) as u64;
+    let base_size = src.len().min(dst.len()) as u64;
+    if dst.is_empty() || max_size == 0 {
+        return 0;
+    }
+    let delta_size = max_size - base_size;
+    if max_size * (MAX_SCORE - min_score) < delta_size * MAX_SCORE {
+        return 0;
+    }
+    let src_chunks = hash_chunks(src);
+    let dst_chunks = hash_chunks(dst);
+    let copied: u64 = src_chunks
+        .iter()
+        .map(|(hash, &count)| count.min(dst_chunks.get(hash).copied().unwrap_or(0)))
+        .sum();
+    copied * MAX_SCORE / max_size
+}
+
+/*
+    Byte counts per chunk hash, using git's diffcore-delta hashing. For
+    text, the CR of a CRLF pair is skipped so line endings do not matter.
+*/
+fn hash_chunks(data: &[u8]) -> HashMap<u32, u64> {
+    let is_text = !diff::is_binary(data);
+    let mut chunks = HashMap::new();
+    let (mut accum1, mut accum2): (u32, u32) = (0, 0);
+    let mut n = 0;
+    for (i, &c) in data.iter().enumerate() {
+        if is_text && c == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        let old_1 = accum1;
+        accum1 = (accum1 << 7) ^ (accum2 >> 25);
+        accum2 = (accum2 << 7) ^ (old_1 >> 25);
+        accum1 = accum1.wrapping_add(c as u32);
+        n += 1;
+        if n < CHUNK_LEN && c != b'\n' {
+            continue;
+        }
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASH_BASE;
+        *chunks.entry(hash).or_insert(0) += n as u64;
+        n = 0;
+        accum1 = 0;
+        accum2 = 0;
+    }
+    if n > 0 {
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASH_BASE;
+        *chunks.entry(hash).or_insert(0) += n as u64;
+    }
+    chunks
+}
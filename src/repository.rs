@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use crate::database::Database;
+use crate::index::Index;
+use crate::pending_commit::PendingCommit;
+use crate::refs::Refs;
+use crate::workspace::Workspace;
+
+/*
+    Bundles the data structures a command works with, all rooted at the
+    same working directory.
+*/
+pub struct Repository {
+    pub git_path: PathBuf,
+    pub database: Database,
+    pub index: Index,
+    pub refs: Refs,
+    pub workspace: Workspace,
+}
+
+impl Repository {
+    pub fn new(root_path: PathBuf) -> Self {
+        let git_path = root_path.join(".git");
+        Repository {
+            database: Database::new(git_path.join("objects")),
+            index: Index::new(git_path.join("index")),
+            refs: Refs::new(git_path.clone()),
+            workspace: Workspace::new(root_path),
+            git_path,
+        }
+    }
+
+    pub fn pending_commit(&self) -> PendingCommit {
+        PendingCommit::new(&self.git_path)
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    io,
+};
+
+use crate::commit::Commit;
+use crate::database::Database;
+use crate::object_id::ObjectId;
+
+/*
+    Walks the history reachable from a set of starting commits, newest
+    committer date first, visiting each commit once. Commits with equal
+    dates come out in the order they were found.
+*/
+pub struct RevList<'a> {
+    database: &'a Database,
+    queue: BinaryHeap<(i64, Reverse<u64>, ObjectId)>,
+    seen: HashSet<ObjectId>,
+    pending: HashMap<ObjectId, Commit>,
+    counter: u64,
+}
+
+impl<'a> RevList<'a> {
+    pub fn new(database: &'a Database, starts: &[ObjectId]) -> io::Result<Self> {
+        let mut rev_list = RevList {
+            database,
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            pending: HashMap::new(),
+            counter: 0,
+        };
+        for start in starts {
+            rev_list.enqueue(*start)?;
+        }
+        Ok(rev_list)
+    }
+
+    fn enqueue(&mut self, object_id: ObjectId) -> io::Result<()> {
+        if !self.seen.insert(object_id) {
+            return Ok(());
+        }
+        let commit = self.database.load_commit(&object_id)?;
+        self.queue.push((
+            commit.committer.timestamp(),
+            Reverse(self.counter),
+            object_id,
+        ));
+        self.pending.insert(object_id, commit);
+        self.counter += 1;
+        Ok(())
+    }
+}
+
+impl Iterator for RevList<'_> {
+    type Item = io::Result<Commit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, _, object_id) = self.queue.pop()?;
+        let commit = self.pending.remove(&object_id)?;
+        for parent in &commit.parents {
+            if let Err(err) = self.enqueue(*parent) {
+                return Some(Err(err));
+            }
+        }
+        Some(Ok(commit))
+    }
+}
@@ -0,0 +1,415 @@
+use std::{error, fmt, io};
+
+use crate::blob::Kind;
+use crate::commit::Commit;
+use crate::database::{GitObject, LookupError};
+use crate::object_id::ObjectId;
+use crate::refs::{self, HEAD};
+use crate::repository::Repository;
+use crate::tag::Tag;
+use crate::tree::EntryOrTree;
+
+#[derive(Debug)]
+pub enum RevisionError {
+    Unknown(String),
+    // Holds the LookupError::Ambiguous listing the candidates.
+    AmbiguousObjectId(LookupError),
+    NotCommit(ObjectId, Kind),
+    WrongType {
+        expression: String,
+        expected: String,
+        actual: Kind,
+    },
+    PathNotFound {
+        path: String,
+        revision: String,
+    },
+    ReflogTooShort {
+        name: String,
+        entries: usize,
+    },
+    Io(io::Error),
+}
+
+impl error::Error for RevisionError {}
+
+impl fmt::Display for RevisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevisionError::Unknown(expression) => write!(
+                f,
+                "ambiguous argument '{}': unknown revision or path not in the working tree.\n\
+                 Use '--' to separate paths from revisions, like this:\n\
+                 'git <command> [<revision>...] -- [<file>...]'",
+                expression
+            ),
+            RevisionError::AmbiguousObjectId(err) => write!(f, "{}", err),
+            RevisionError::NotCommit(object_id, kind) => {
+                write!(f, "object {} is a {}, not a commit", object_id, kind)
+            }
+            RevisionError::WrongType {
+                expression,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected {} type, but the object dereferences to {} type",
+                expression, expected, actual
+            ),
+            RevisionError::PathNotFound { path, revision } => {
+                write!(f, "path '{}' does not exist in '{}'", path, revision)
+            }
+            RevisionError::ReflogTooShort { name, entries } => {
+                write!(f, "log for '{}' only has {} entries", name, entries)
+            }
+            RevisionError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<LookupError> for RevisionError {
+    fn from(err: LookupError) -> Self {
+        match err {
+            LookupError::Io(err) => RevisionError::Io(err),
+            err => RevisionError::AmbiguousObjectId(err),
+        }
+    }
+}
+
+impl RevisionError {
+    /*
+        git reports these with "error:" and then carries on as though the
+        revision were unknown.
+    */
+    pub fn is_reported_as_unknown(&self) -> bool {
+        matches!(
+            self,
+            RevisionError::AmbiguousObjectId(_)
+                | RevisionError::NotCommit(..)
+                | RevisionError::WrongType { .. }
+        )
+    }
+}
+
+impl From<io::Error> for RevisionError {
+    fn from(err: io::Error) -> Self {
+        RevisionError::Io(err)
+    }
+}
+
+/*
+    The syntax tree of a revision expression. Suffix operators bind from
+    the right, so "main~2^2" is Parent(Ancestor(Ref("main"), 2), 2).
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rev {
+    // A ref name, "HEAD" or a full or abbreviated object ID.
+    Ref(String),
+    // <rev>^N, the Nth parent; ^0 is the commit itself.
+    Parent(Box<Rev>, usize),
+    // <rev>~N, following first parents N times.
+    Ancestor(Box<Rev>, usize),
+    // <rev>^{type}, with an empty type meaning "any".
+    Peel(Box<Rev>, String),
+    // <rev>:<path>, the object at path in the rev's tree.
+    Path(Box<Rev>, String),
+    // <ref>@{N}, the Nth previous value of the ref; None is the current branch.
+    Reflog(Option<String>, usize),
+}
+
+impl Rev {
+    pub fn parse(expression: &str) -> Option<Self> {
+        if let Some((revision, path)) = expression.split_once(':') {
+            if revision.is_empty() {
+                return None;
+            }
+            return Some(Rev::Path(Box::new(Rev::parse(revision)?), path.to_string()));
+        }
+        if let Some(rest) = expression.strip_suffix('}') {
+            let open = rest.rfind('{')?;
+            let inner = &rest[open + 1..];
+            let base = &rest[..open];
+            if let Some(base) = base.strip_suffix('^') {
+                return Some(Rev::Peel(Box::new(Rev::parse(base)?), inner.to_string()));
+            }
+            let base = base.strip_suffix('@')?;
+            let count = inner.parse().ok()?;
+            let name = match base {
+                "" => None,
+                "@" => Some(HEAD.to_string()),
+                name => Some(name.to_string()),
+            };
+            return Some(Rev::Reflog(name, count));
+        }
+        if let Some(index) = expression.rfind(['^', '~']) {
+            let (base, operator) = (&expression[..index], &expression[index + 1..]);
+            if base.is_empty() {
+                return None;
+            }
+            let count = if operator.is_empty() {
+                1
+            } else if operator.bytes().all(|b| b.is_ascii_digit()) {
+                operator.parse().ok()?
+            } else {
+                return None;
+            };
+            let base = Box::new(Rev::parse(base)?);
+            return Some(if expression.as_bytes()[index] == b'^' {
+                Rev::Parent(base, count)
+            } else {
+                Rev::Ancestor(base, count)
+            });
+        }
+        if expression == "@" {
+            return Some(Rev::Ref(HEAD.to_string()));
+        }
+        if refs::is_valid_ref_name(expression) {
+            return Some(Rev::Ref(expression.to_string()));
+        }
+        None
+    }
+}
+
+/*
+    A revision expression as typed by the user, resolved against a
+    repository's refs, reflogs and object database.
+*/
+pub struct Revision<'a> {
+    repo: &'a Repository,
+    expression: String,
+}
+
+impl<'a> Revision<'a> {
+    pub fn new(repo: &'a Repository, expression: &str) -> Self {
+        Revision {
+            repo,
+            expression: expression.to_string(),
+        }
+    }
+
+    /*
+        Resolves the expression to an object ID, peeled to kind if given.
+    */
+    pub fn resolve(&self, kind: Option<Kind>) -> Result<ObjectId, RevisionError> {
+        let rev = Rev::parse(&self.expression).ok_or_else(|| self.unknown())?;
+        let object_id = self.resolve_rev(&rev)?;
+        match kind {
+            Some(kind) => self.peel(object_id, &kind.to_string()),
+            None => Ok(object_id),
+        }
+    }
+
+    fn unknown(&self) -> RevisionError {
+        RevisionError::Unknown(self.expression.clone())
+    }
+
+    fn resolve_rev(&self, rev: &Rev) -> Result<ObjectId, RevisionError> {
+        match rev {
+            Rev::Ref(name) => self.resolve_name(name),
+            Rev::Parent(base, n) => {
+                let commit = self.load_commit(self.resolve_rev(base)?)?;
+                if *n == 0 {
+                    return Ok(commit.object_id.expect("loaded commits have an ID"));
+                }
+                commit
+                    .parents
+                    .get(n - 1)
+                    .copied()
+                    .ok_or_else(|| self.unknown())
+            }
+            Rev::Ancestor(base, n) => {
+                let mut commit = self.load_commit(self.resolve_rev(base)?)?;
+                for _ in 0..*n {
+                    let parent = commit.parent().ok_or_else(|| self.unknown())?;
+                    commit = self.load_commit(parent)?;
+                }
+                Ok(commit.object_id.expect("loaded commits have an ID"))
+            }
+            Rev::Peel(base, kind) => {
+                let object_id = self.resolve_rev(base)?;
+                self.peel(object_id, kind)
+            }
+            Rev::Path(base, path) => {
+                let object_id = self.resolve_rev(base)?;
+                let tree_id = self.peel(object_id, "tree")?;
+                let revision = self
+                    .expression
+                    .split_once(':')
+                    .map(|(revision, _)| revision)
+                    .unwrap_or_default();
+                self.lookup_path(tree_id, path)
+                    .ok_or(RevisionError::PathNotFound {
+                        path: path.clone(),
+                        revision: revision.to_string(),
+                    })
+            }
+            Rev::Reflog(name, n) => self.resolve_reflog(name.as_deref(), *n),
+        }
+    }
+
+    /*
+        A full object ID wins over refs, which win over abbreviations, as in git.
+    */
+    fn resolve_name(&self, name: &str) -> Result<ObjectId, RevisionError> {
+        if let Ok(object_id) = ObjectId::from_hex(name) {
+            return Ok(object_id);
+        }
+        if let Some(ref_name) = self.find_ref(name)? {
+            if let Some(object_id) = self.repo.refs.read_ref(&ref_name)? {
+                return Ok(object_id);
+            }
+        }
+        match self.repo.database.find_by_prefix(name)? {
+            Some(object_id) => Ok(object_id),
+            None => Err(self.unknown()),
+        }
+    }
+
+    /*
+        Expands a short ref name using git's search order: the name itself
+        for HEAD-like names, then under refs/, refs/tags/ and refs/heads/.
+    */
+    fn find_ref(&self, name: &str) -> io::Result<Option<String>> {
+        let mut candidates = Vec::new();
+        if name.starts_with("refs/") || name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_') {
+            candidates.push(name.to_string());
+        }
+        candidates.push(format!("refs/{}", name));
+        candidates.push(format!("{}/{}", refs::TAGS_DIR, name));
+        candidates.push(format!("{}/{}", refs::HEADS_DIR, name));
+        for candidate in candidates {
+            if self.repo.refs.read_ref_file(&candidate)?.is_some() {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
+    /*
+        <ref>@{0} is the ref's current value and <ref>@{N} the value N moves
+        ago, read from its reflog; one past the oldest entry is the value the
+        ref had before the log began.
+    */
+    fn resolve_reflog(&self, name: Option<&str>, n: usize) -> Result<ObjectId, RevisionError> {
+        let (display_name, ref_name) = match name {
+            Some(name) => (
+                name.to_string(),
+                self.find_ref(name)?.ok_or_else(|| self.unknown())?,
+            ),
+            None => {
+                let current = self.repo.refs.current_ref()?;
+                let short = self
+                    .repo
+                    .refs
+                    .current_branch()?
+                    .unwrap_or_else(|| current.clone());
+                (short, current)
+            }
+        };
+        let entries = self
+            .repo
+            .refs
+            .read_reflog(&ref_name)?
+            .filter(|entries| !entries.is_empty())
+            .ok_or_else(|| self.unknown())?;
+        let object_id = if n < entries.len() {
+            entries[entries.len() - 1 - n].new_object_id
+        } else if n == entries.len() && entries[0].old_object_id != ObjectId::default() {
+            entries[0].old_object_id
+        } else {
+            // A log that begins with the ref's creation has no value before it.
+            return Err(RevisionError::ReflogTooShort {
+                name: display_name,
+                entries: entries.len(),
+            });
+        };
+        if object_id == ObjectId::default() {
+            return Err(self.unknown());
+        }
+        Ok(object_id)
+    }
+
+    /*
+        Loads the commit object_id names, following tags that point at one.
+    */
+    fn load_commit(&self, object_id: ObjectId) -> Result<Commit, RevisionError> {
+        match self.load(object_id)? {
+            GitObject::Commit(commit) => Ok(commit),
+            GitObject::Tag(tag) => self.load_commit(self.tag_target(object_id, &tag)?),
+            other => Err(RevisionError::NotCommit(object_id, other.kind())),
+        }
+    }
+
+    fn load(&self, object_id: ObjectId) -> Result<GitObject, RevisionError> {
+        match self.repo.database.load(&object_id) {
+            Ok(object) => Ok(object),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(self.unknown()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /*
+        Dereferences object_id until it has the wanted type: tags are
+        followed to what they point at, and a commit can be peeled to its
+        tree. "" peels tags until something else is found.
+    */
+    fn peel(&self, object_id: ObjectId, kind: &str) -> Result<ObjectId, RevisionError> {
+        let wanted = match kind {
+            "" => None,
+            kind => Some(Kind::parse(kind).ok_or_else(|| self.unknown())?),
+        };
+        let mut object_id = object_id;
+        loop {
+            let object = self.load(object_id)?;
+            if Some(object.kind()) == wanted {
+                return Ok(object_id);
+            }
+            let actual = match object {
+                GitObject::Tag(tag) => {
+                    object_id = self.tag_target(object_id, &tag)?;
+                    continue;
+                }
+                _ if wanted.is_none() => return Ok(object_id),
+                GitObject::Commit(commit) if wanted == Some(Kind::Tree) => {
+                    return Ok(commit.tree_object_id)
+                }
+                // git peels a commit to its tree before giving up.
+                GitObject::Commit(_) => Kind::Tree,
+                other => other.kind(),
+            };
+            return Err(RevisionError::WrongType {
+                expression: self.expression.clone(),
+                expected: kind.to_string(),
+                actual,
+            });
+        }
+    }
+
+    /*
+        The object a tag points at. When packed-refs records the tag as a
+        ref's value along with its peeled value, that is used instead,
+        skipping any tags in between.
+    */
+    fn tag_target(&self, object_id: ObjectId, tag: &Tag) -> io::Result<ObjectId> {
+        let peeled = self
+            .repo
+            .refs
+            .read_packed_refs()?
+            .into_iter()
+            .find(|packed| packed.object_id == object_id)
+            .and_then(|packed| packed.peeled);
+        Ok(peeled.unwrap_or(tag.target_object_id))
+    }
+
+    fn lookup_path(&self, tree_id: ObjectId, path: &str) -> Option<ObjectId> {
+        let mut object_id = tree_id;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let tree = self.repo.database.load_tree(&object_id).ok()?;
+            match tree.entries.get(name)? {
+                EntryOrTree::Entry(entry) => object_id = entry.object_id,
+                EntryOrTree::Tree(tree) => object_id = tree.object_id?,
+            }
+        }
+        Some(object_id)
+    }
+}
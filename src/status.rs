@@ -0,0 +1,250 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::Metadata,
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::blob::Blob;
+use crate::entry::Entry;
+use crate::index::IndexEntry;
+use crate::object_id::ObjectId;
+use crate::rename::{detect_renames, RenameOptions};
+use crate::repository::Repository;
+use crate::tree_diff::{change_kind, ChangeKind, TreeChange};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+}
+
+/*
+    Differences between HEAD's tree, the index and the workspace.
+    index_changes compares HEAD to the index, workspace_changes compares
+    the index to the workspace. renames maps the new path of each file
+    renamed in the index to its old path and similarity percentage.
+    conflicts maps each unmerged path to the merge stages it has.
+*/
+#[derive(Debug, Default)]
+pub struct Status {
+    pub changed: BTreeSet<String>,
+    pub index_changes: BTreeMap<String, ChangeType>,
+    pub workspace_changes: BTreeMap<String, ChangeType>,
+    pub untracked: BTreeSet<String>,
+    pub head_tree: BTreeMap<String, Entry>,
+    pub renames: BTreeMap<String, (String, u32)>,
+    pub conflicts: BTreeMap<String, Vec<u8>>,
+    pub stats: HashMap<String, Metadata>,
+}
+
+impl Status {
+    /*
+        Expects the index to be loaded. Entries whose content matches but whose
+        stat data is stale get their stat data refreshed in the index, so the
+        caller can write it back and avoid rehashing them next time.
+    */
+    pub fn new(repo: &mut Repository) -> io::Result<Self> {
+        let mut status = Status::default();
+        status.scan_workspace(repo)?;
+        status.load_head_tree(repo)?;
+        status.check_index_entries(repo)?;
+        status.collect_deleted_head_files(repo);
+        Ok(status)
+    }
+
+    /*
+        Ignore rules only apply to untracked files, so tracked ones are
+        stat'ed directly from the index rather than found by listing.
+    */
+    fn scan_workspace(&mut self, repo: &Repository) -> io::Result<()> {
+        let root = repo.workspace.root().to_path_buf();
+        for file in repo.workspace.list_files(&root)? {
+            let path = crate::utils::path_to_index_key(&file);
+            if !repo.index.is_tracked_file(&path) {
+                self.untracked.insert(untracked_name(repo, &path));
+            }
+        }
+        for entry in repo.index.entries() {
+            if let Ok(stat) = repo.workspace.stat_file(Path::new(&entry.path)) {
+                if stat.is_file() {
+                    self.stats.insert(entry.path.clone(), stat);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_head_tree(&mut self, repo: &Repository) -> io::Result<()> {
+        if let Some(head) = repo.refs.read_head()? {
+            let commit = repo.database.load_commit(&head)?;
+            self.head_tree = repo.database.load_tree_entries(&commit.tree_object_id)?;
+        }
+        Ok(())
+    }
+
+    fn check_index_entries(&mut self, repo: &mut Repository) -> io::Result<()> {
+        let entries: Vec<IndexEntry> = repo.index.entries().cloned().collect();
+        for entry in entries {
+            if entry.stage() > 0 {
+                self.changed.insert(entry.path.clone());
+                self.conflicts
+                    .entry(entry.path.clone())
+                    .or_default()
+                    .push(entry.stage());
+                continue;
+            }
+            self.check_index_against_workspace(repo, &entry)?;
+            self.check_index_against_head_tree(&entry);
+        }
+        Ok(())
+    }
+
+    fn check_index_against_workspace(
+        &mut self,
+        repo: &mut Repository,
+        entry: &IndexEntry,
+    ) -> io::Result<()> {
+        let Some(stat) = self.stats.get(&entry.path) else {
+            self.record_change(&entry.path, ChangeType::Deleted, false);
+            return Ok(());
+        };
+        if !stat_match(entry, stat) {
+            self.record_change(&entry.path, ChangeType::Modified, false);
+            return Ok(());
+        }
+        if times_match(entry, stat) {
+            return Ok(());
+        }
+
+        let data = repo.workspace.read_data(Path::new(&entry.path))?;
+        let object_id = repo.database.hash_object(&Blob::new(data));
+        if object_id == entry.object_id {
+            let stat = stat.clone();
+            repo.index.update_entry_stat(&entry.path, &stat);
+        } else {
+            self.record_change(&entry.path, ChangeType::Modified, false);
+        }
+        Ok(())
+    }
+
+    fn check_index_against_head_tree(&mut self, entry: &IndexEntry) {
+        match self.head_tree.get(&entry.path) {
+            Some(item) => {
+                if item.mode != entry.mode || item.object_id != entry.object_id {
+                    self.record_change(&entry.path, ChangeType::Modified, true);
+                }
+            }
+            None => self.record_change(&entry.path, ChangeType::Added, true),
+        }
+    }
+
+    fn collect_deleted_head_files(&mut self, repo: &Repository) {
+        let paths: Vec<String> = self.head_tree.keys().cloned().collect();
+        for path in paths {
+            if !repo.index.is_tracked_file(&path) {
+                self.record_change(&path, ChangeType::Deleted, true);
+            }
+        }
+    }
+
+    fn record_change(&mut self, path: &str, change: ChangeType, in_index: bool) {
+        self.changed.insert(path.to_string());
+        if in_index {
+            self.index_changes.insert(path.to_string(), change);
+        } else {
+            self.workspace_changes.insert(path.to_string(), change);
+        }
+    }
+
+    /*
+        Mode of the workspace file at path, if it exists.
+    */
+    pub fn workspace_mode(&self, path: &str) -> Option<u32> {
+        self.stats.get(path).map(Entry::mode_for_stat)
+    }
+
+    pub fn head_object_id(&self, path: &str) -> Option<ObjectId> {
+        self.head_tree.get(path).map(|entry| entry.object_id)
+    }
+
+    /*
+        index_changes as tree changes, with each side's entry attached.
+    */
+    pub fn index_tree_changes(&self, repo: &Repository) -> Vec<TreeChange> {
+        let mut changes = Vec::new();
+        for path in self.index_changes.keys() {
+            let old = self.head_tree.get(path).cloned();
+            let new = repo.index.entry_for_path(path).map(|entry| {
+                Entry::new(
+                    entry.basename().to_string(),
+                    PathBuf::from(&entry.path),
+                    entry.object_id,
+                    entry.mode,
+                )
+            });
+            if let Some(kind) = change_kind(old.as_ref(), new.as_ref()) {
+                changes.push(TreeChange {
+                    path: path.clone(),
+                    kind,
+                    old,
+                    new,
+                });
+            }
+        }
+        changes
+    }
+
+    /*
+        Pairs files deleted from the index with files added to it, using
+        git's default rename threshold. A renamed file is then listed under
+        its new path only.
+    */
+    pub fn detect_index_renames(&mut self, repo: &Repository) -> io::Result<()> {
+        let changes = detect_renames(
+            &repo.database,
+            self.index_tree_changes(repo),
+            &[],
+            &RenameOptions::default(),
+        )?;
+        for change in changes {
+            let (ChangeKind::Renamed(similarity), Some(old)) = (change.kind, change.old) else {
+                continue;
+            };
+            let old_path = old.path.to_string_lossy().to_string();
+            self.index_changes.remove(&old_path);
+            self.changed.remove(&old_path);
+            self.index_changes
+                .insert(change.path.clone(), ChangeType::Renamed);
+            self.renames.insert(change.path, (old_path, similarity));
+        }
+        Ok(())
+    }
+}
+
+/*
+    Untracked files inside a directory with no tracked content are reported
+    as the outermost such directory, with a trailing slash.
+*/
+fn untracked_name(repo: &Repository, path: &str) -> String {
+    for (i, b) in path.bytes().enumerate() {
+        if b == b'/' && !repo.index.is_tracked(&path[..i]) {
+            return format!("{}/", &path[..i]);
+        }
+    }
+    path.to_string()
+}
+
+fn stat_match(entry: &IndexEntry, stat: &Metadata) -> bool {
+    entry.mode == Entry::mode_for_stat(stat) && entry.size == stat.size() as u32
+}
+
+fn times_match(entry: &IndexEntry, stat: &Metadata) -> bool {
+    entry.ctime == stat.ctime() as u32
+        && entry.ctime_nsec == stat.ctime_nsec() as u32
+        && entry.mtime == stat.mtime() as u32
+        && entry.mtime_nsec == stat.mtime_nsec() as u32
+}
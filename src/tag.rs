@@ -0,0 +1,94 @@
+use std::io;
+
+use crate::author::Author;
+use crate::blob::Kind;
+use crate::object_id::ObjectId;
+use crate::traits::Object;
+use crate::utils;
+
+/*
+    An annotated tag: a name and message attached to another object,
+    usually a commit, and possibly to another tag.
+*/
+#[derive(Debug)]
+pub struct Tag {
+    pub target_object_id: ObjectId,
+    pub target_kind: Kind,
+    pub name: String,
+    // Tags made by very old versions of git have no tagger.
+    pub tagger: Option<Author>,
+    pub message: String,
+    pub object_id: Option<ObjectId>,
+}
+
+impl Tag {
+    /*
+        Parses the body of a tag object: the object, type, tag and tagger
+        headers, a blank line, and the message, which includes any
+        signature. Unknown headers are skipped, as in commits.
+    */
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let content = String::from_utf8_lossy(data);
+        let (headers, message) = match content.find("\n\n") {
+            Some(index) => (&content[..index], &content[index + 2..]),
+            None => (content.trim_end_matches('\n'), ""),
+        };
+
+        let mut target_object_id = None;
+        let mut target_kind = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "object" => {
+                    target_object_id = Some(
+                        ObjectId::from_hex(value)
+                            .map_err(|err| utils::invalid_data(&err.to_string()))?,
+                    )
+                }
+                "type" => {
+                    target_kind = Some(Kind::parse(value).ok_or_else(|| {
+                        utils::invalid_data(&format!("unknown tag target type {}", value))
+                    })?)
+                }
+                "tag" => name = Some(value.to_string()),
+                "tagger" => tagger = Author::parse(value),
+                _ => {}
+            }
+        }
+
+        Ok(Tag {
+            target_object_id: target_object_id
+                .ok_or_else(|| utils::invalid_data("tag is missing an object"))?,
+            target_kind: target_kind.ok_or_else(|| utils::invalid_data("tag is missing a type"))?,
+            name: name.ok_or_else(|| utils::invalid_data("tag is missing a name"))?,
+            tagger,
+            message: message.to_string(),
+            object_id: None,
+        })
+    }
+}
+
+impl Object for Tag {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut content_str = format!(
+            "object {}\ntype {}\ntag {}\n",
+            self.target_object_id, self.target_kind, self.name
+        );
+        if let Some(tagger) = &self.tagger {
+            content_str.push_str(&format!("tagger {}\n", tagger));
+        }
+        content_str.push('\n');
+        content_str.push_str(&self.message);
+        format!("tag {}\0{}", content_str.len(), content_str).into_bytes()
+    }
+
+    fn get_object_id(&self) -> Option<ObjectId> {
+        self.object_id
+    }
+
+    fn set_object_id(&mut self, object_id: ObjectId) {
+        self.object_id = Some(object_id);
+    }
+}
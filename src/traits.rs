@@ -1,5 +1,7 @@
+use crate::object_id::ObjectId;
+
 pub trait Object {
-    fn to_string(&self) -> String;
-    fn get_object_id(&self) -> String;
-    fn set_object_id(&mut self, object_id: String);
+    fn to_bytes(&self) -> Vec<u8>;
+    fn get_object_id(&self) -> Option<ObjectId>;
+    fn set_object_id(&mut self, object_id: ObjectId);
 }
@@ -1,10 +1,13 @@
 use std::collections::HashMap;
+use std::io;
 use std::path::PathBuf;
 
 use crate::blob::Kind;
 use crate::database::Database;
 use crate::entry::Entry;
+use crate::object_id::ObjectId;
 use crate::traits::Object;
+use crate::utils;
 
 #[derive(Debug)]
 pub enum EntryOrTree {
@@ -17,7 +20,13 @@ pub struct Tree {
     pub entries_order: Vec<String>,
     pub entries: HashMap<String, EntryOrTree>,
     pub kind: Kind,
-    pub object_id: String,
+    pub object_id: Option<ObjectId>,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Tree {
@@ -26,7 +35,7 @@ impl Tree {
             entries_order: Vec::new(),
             entries: HashMap::new(),
             kind: Kind::Tree,
-            object_id: String::new(),
+            object_id: None,
         }
     }
 
@@ -39,6 +48,46 @@ impl Tree {
         root
     }
 
+    /*
+        Parses the body of a tree object: a sequence of "<mode> <name>\0" headers,
+        each followed by the 20 raw bytes of the entry's object ID.
+        Subtrees are not loaded, they appear as entries with a directory mode.
+    */
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let mut tree = Self::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| utils::invalid_data("malformed tree entry mode"))?;
+            let mode = std::str::from_utf8(&rest[..space])
+                .ok()
+                .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+                .ok_or_else(|| utils::invalid_data("malformed tree entry mode"))?;
+            rest = &rest[space + 1..];
+
+            let nul = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| utils::invalid_data("malformed tree entry name"))?;
+            let filename = String::from_utf8_lossy(&rest[..nul]).to_string();
+            rest = &rest[nul + 1..];
+
+            if rest.len() < ObjectId::LEN {
+                return Err(utils::invalid_data("truncated tree entry object id"));
+            }
+            let object_id = ObjectId::from_bytes(&rest[..ObjectId::LEN])
+                .map_err(|err| utils::invalid_data(&err.to_string()))?;
+            rest = &rest[ObjectId::LEN..];
+
+            let entry = Entry::new(filename.clone(), PathBuf::from(&filename), object_id, mode);
+            tree.entries_order.push(filename.clone());
+            tree.entries.insert(filename, EntryOrTree::Entry(entry));
+        }
+        Ok(tree)
+    }
+
     pub fn store_tree(&mut self, db: &Database) {
         for entry_or_tree in self.entries.values_mut() {
             if let EntryOrTree::Tree(subtree) = entry_or_tree {
@@ -54,25 +103,23 @@ impl Tree {
             self.entries
                 .insert(entry.filename.clone(), EntryOrTree::Entry(entry));
         } else {
-            dbg!(&parents);
             let path = &parents[0];
             // foo/bar/world.txt   bar/world.txt
 
-            let first_component = path.components().last().unwrap();
-            let mut basename = String::new();
-            match first_component {
+            let first_component = path.components().next_back().unwrap();
+            let basename = match first_component {
                 std::path::Component::RootDir => {
                     eprintln!("The path starts with a root directory.");
                     panic!();
                 }
                 std::path::Component::Normal(component) => {
-                    basename = String::from(component.to_str().unwrap());
+                    String::from(component.to_str().unwrap())
                 }
                 _ => {
                     println!("The first component is not a directory.");
                     panic!();
                 }
-            }
+            };
             if !self.entries.contains_key(&basename) {
                 self.entries_order.push(basename.clone());
             }
@@ -92,27 +139,32 @@ impl Tree {
 }
 
 impl Object for Tree {
-    fn to_string(&self) -> String {
-        let kind = format!("{:?}", self.kind).to_lowercase();
-        let mut content = String::new();
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
 
         for filename in &self.entries_order {
             let entry_or_tree = self.entries.get(filename).unwrap();
             let (mode, object_id) = match entry_or_tree {
-                EntryOrTree::Entry(entry) => (entry.mode(), entry.object_id.clone()),
-                EntryOrTree::Tree(tree) => (String::from("40000"), tree.object_id.clone()),
+                EntryOrTree::Entry(entry) => (entry.mode(), entry.object_id),
+                EntryOrTree::Tree(tree) => (
+                    String::from("40000"),
+                    tree.get_object_id().expect("subtree must be stored first"),
+                ),
             };
 
-            content.push_str(&format!("{} {}\0{}", mode, filename, object_id,))
+            content.extend_from_slice(format!("{} {}\0", mode, filename).as_bytes());
+            content.extend_from_slice(object_id.as_bytes());
         }
-        format!("{} {}\0{}", kind, content.bytes().len(), content)
+        let mut bytes = format!("{} {}\0", self.kind, content.len()).into_bytes();
+        bytes.append(&mut content);
+        bytes
     }
 
-    fn get_object_id(&self) -> String {
-        self.object_id.clone()
+    fn get_object_id(&self) -> Option<ObjectId> {
+        self.object_id
     }
 
-    fn set_object_id(&mut self, object_id: String) {
-        self.object_id = object_id;
+    fn set_object_id(&mut self, object_id: ObjectId) {
+        self.object_id = Some(object_id);
     }
 }
@@ -0,0 +1,153 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::PathBuf,
+};
+
+use crate::database::Database;
+use crate::entry::Entry;
+use crate::object_id::ObjectId;
+use crate::tree::EntryOrTree;
+
+// The file type bits of a mode, which tell blobs, symlinks and trees apart.
+pub const TYPE_MASK: u32 = 0o170000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    // Same path, different kind of file, e.g. a regular file became a symlink.
+    TypeChanged,
+    // Same content, different permissions.
+    ModeChanged,
+    // Found by rename detection, with the similarity as a percentage.
+    Renamed(u32),
+    Copied(u32),
+}
+
+/*
+    A file that differs between two trees. old is None for additions and
+    new is None for deletions; entry paths are full "/" separated paths.
+    For renames and copies, path is the new path and old keeps its own.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub old: Option<Entry>,
+    pub new: Option<Entry>,
+}
+
+/*
+    Compares two trees file by file, descending only into subtrees whose
+    IDs differ. None stands for the empty tree, so a root commit can be
+    compared against nothing. A file replaced by a directory, or the other
+    way round, shows up as a deletion plus additions. Changes come back
+    sorted by path.
+*/
+pub fn tree_diff(
+    database: &Database,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+) -> io::Result<Vec<TreeChange>> {
+    let mut changes = Vec::new();
+    compare_trees(database, old, new, "", &mut changes)?;
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+fn compare_trees(
+    database: &Database,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    prefix: &str,
+    changes: &mut Vec<TreeChange>,
+) -> io::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let old_entries = load_entries(database, old, prefix)?;
+    let new_entries = load_entries(database, new, prefix)?;
+    let names: BTreeSet<&String> = old_entries.keys().chain(new_entries.keys()).collect();
+
+    for name in names {
+        let path = old_entries
+            .get(name)
+            .or_else(|| new_entries.get(name))
+            .map(|entry| entry.path.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let old_entry = old_entries.get(name);
+        let new_entry = new_entries.get(name);
+
+        let old_tree = old_entry.filter(|e| e.is_tree()).map(|e| e.object_id);
+        let new_tree = new_entry.filter(|e| e.is_tree()).map(|e| e.object_id);
+        if old_tree.is_some() || new_tree.is_some() {
+            compare_trees(database, old_tree, new_tree, &path, changes)?;
+        }
+
+        let old_file = old_entry.filter(|e| !e.is_tree());
+        let new_file = new_entry.filter(|e| !e.is_tree());
+        let Some(kind) = change_kind(old_file, new_file) else {
+            continue;
+        };
+        changes.push(TreeChange {
+            path,
+            kind,
+            old: old_file.cloned(),
+            new: new_file.cloned(),
+        });
+    }
+    Ok(())
+}
+
+/*
+    How a file changed between two versions, None when it did not.
+*/
+pub fn change_kind(old: Option<&Entry>, new: Option<&Entry>) -> Option<ChangeKind> {
+    match (old, new) {
+        (None, None) => None,
+        (None, Some(_)) => Some(ChangeKind::Added),
+        (Some(_), None) => Some(ChangeKind::Deleted),
+        (Some(a), Some(b)) => {
+            if a.mode & TYPE_MASK != b.mode & TYPE_MASK {
+                Some(ChangeKind::TypeChanged)
+            } else if a.object_id != b.object_id {
+                Some(ChangeKind::Modified)
+            } else if a.mode != b.mode {
+                Some(ChangeKind::ModeChanged)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/*
+    The immediate entries of a tree keyed by name, with paths under prefix.
+*/
+fn load_entries(
+    database: &Database,
+    object_id: Option<ObjectId>,
+    prefix: &str,
+) -> io::Result<BTreeMap<String, Entry>> {
+    let mut entries = BTreeMap::new();
+    let Some(object_id) = object_id else {
+        return Ok(entries);
+    };
+    let tree = database.load_tree(&object_id)?;
+    for (name, item) in &tree.entries {
+        let EntryOrTree::Entry(entry) = item else {
+            continue;
+        };
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let mut entry = entry.clone();
+        entry.path = PathBuf::from(path);
+        entries.insert(name.clone(), entry);
+    }
+    Ok(entries)
+}
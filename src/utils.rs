@@ -1,52 +1,40 @@
+use crate::object_id::ObjectId;
 use flate2::read::ZlibDecoder;
 use sha1::{Digest, Sha1};
-use std::{env, fs, io::Read, path::PathBuf, process};
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    process,
+};
 
 /*
     object_path is assumed to point to an object in the objects directory.
-    Inflates the file contents at PathBuf, raw bytes are returned as a String.
+    Inflates the file contents at PathBuf, raw bytes are returned as is.
 */
-pub fn inflate(object_path: PathBuf) -> String {
-    match fs::read(object_path.as_path()) {
-        Ok(content) => {
-            let mut decoder = ZlibDecoder::new(&content[..]);
-            let mut s = Vec::new();
-            let decompressed = decoder.read_to_end(&mut s);
-            match decompressed {
-                Ok(_) => unsafe { String::from_utf8_unchecked(s) },
-                Err(_) => panic!("error decompressing!"),
-            }
-        }
-        Err(e) => {
-            eprintln!("Could not read object data");
-            std::process::exit(1);
-        }
-    }
+pub fn inflate(object_path: PathBuf) -> io::Result<Vec<u8>> {
+    let content = fs::read(object_path.as_path())?;
+    let mut decoder = ZlibDecoder::new(&content[..]);
+    let mut s = Vec::new();
+    decoder.read_to_end(&mut s)?;
+    Ok(s)
 }
 
 /*
-    Compute Sha1 hash of content_str.
+    Builds the error returned when stored data (objects, refs, index) is malformed.
 */
-pub fn hash_content(content_str: &str) -> Vec<u8> {
-    let mut hasher = Sha1::new();
-    hasher.update(content_str.as_bytes());
-    let hash_result = hasher.finalize();
-    hash_result.as_slice().to_vec()
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
 }
 
 /*
-    Formats bytes as a displayable string. FOR DISPLAY PURPOSES ONLY.
-    The formatting expands each byte into a displayable character,
-    for example, a byte with contents (AB)_16 = (10101011)_2 would get converted
-    into "AB" as a displayable string, so the original bytes are lost.
+    Compute Sha1 hash of content.
 */
-pub fn u8_to_hex_str(content_hash: Vec<u8>) -> String {
-    let mut content_hash_hex = String::new();
-    for byte in &content_hash {
-        let byte_str = format!("{:02X}", byte).to_ascii_lowercase();
-        content_hash_hex.push_str(&byte_str);
-    }
-    content_hash_hex
+pub fn hash_content(content: &[u8]) -> ObjectId {
+    let mut hasher = Sha1::new();
+    hasher.update(content);
+    let hash_result = hasher.finalize();
+    ObjectId::from_bytes(hash_result.as_slice()).expect("SHA-1 digests are 20 bytes")
 }
 
 /*
@@ -58,6 +46,35 @@ pub fn hash_to_path(content_hash_hex: &str) -> (&str, &str) {
     (&content_hash_hex[0..2], &content_hash_hex[2..])
 }
 
+/*
+    Converts a workspace-relative path to the "/" separated form stored in
+    the index and used for tree entry paths.
+*/
+pub fn path_to_index_key(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/*
+    Resolves "." and ".." components lexically, without touching the filesystem,
+    so paths that do not exist (deleted files) can still be normalized.
+*/
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
 pub fn get_root_path() -> PathBuf {
     match env::current_dir() {
         Ok(cwd) => cwd,
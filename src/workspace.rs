@@ -1,76 +1,175 @@
 use std::{
-    fs::{self, Metadata},
+    fs::{self, Metadata, Permissions},
     io,
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
-    process,
 };
 
+use crate::entry::EXECUTABLE_MODE;
+use crate::ignore::Ignore;
+use crate::utils;
+
 #[derive(Debug)]
 pub struct Workspace {
-    ignore: [&'static str; 7],
+    ignore: Ignore,
     path: PathBuf,
 }
 
 impl Workspace {
     pub fn new(path: PathBuf) -> Self {
         Workspace {
-            ignore: [".", "..", ".vscode", ".git", "target", "src", ".gitignore"],
+            ignore: Ignore::new(path.clone()),
             path,
         }
     }
 
-    pub fn read_data(&self, path: &Path) -> io::Result<String> {
-        dbg!(&path);
-        fs::read_to_string(path)
+    pub fn root(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn ignore(&self) -> &Ignore {
+        &self.ignore
+    }
+
+    /*
+        Whether a workspace-relative path is excluded by the ignore rules,
+        including by an ignored parent directory.
+    */
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let is_dir = self.path.join(path).is_dir();
+        self.ignore
+            .match_with_parents(&utils::path_to_index_key(path), is_dir)
+            .is_some_and(|found| found.is_ignored())
+    }
+
+    /*
+        Paths handed to the workspace are relative to its root, absolute
+        paths are used as they are.
+    */
+    pub fn read_data(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.path.join(path))
     }
 
-    pub fn list_files(&self, cur_path: &PathBuf) -> io::Result<Vec<PathBuf>> {
-        let read_files_res = fs::read_dir(cur_path);
+    /*
+        Lists the files at cur_path relative to the workspace root, descending
+        into directories and skipping anything the ignore rules exclude.
+        A path naming a single file lists just that file.
+    */
+    pub fn list_files(&self, cur_path: &Path) -> io::Result<Vec<PathBuf>> {
+        let cur_path = self.path.join(cur_path);
         let mut v = Vec::new();
 
-        match read_files_res {
-            Ok(read_files) => {
-                for file in read_files {
-                    let path = file?.path();
-                    if self.ignore.into_iter().all(|x| !path.ends_with(x)) {
-                        if path.is_dir() {
-                            let mut files_from_dir = Self::list_files(self, &path.clone())?;
-                            v.append(&mut files_from_dir);
-                        } else if path.is_file() {
-                            // Strip root path.
-                            let absolute_path = path.as_path();
-                            let root_path = self.path.as_path();
-                            let relative_path = absolute_path.strip_prefix(root_path);
-                            // TODO: add custom error types and use the ? instead of pattern matching
-                            match relative_path {
-                                Ok(relative_path) => {
-                                    v.push(relative_path.to_path_buf());
-                                }
-                                Err(_) => {
-                                    eprintln!(
-                                        "Workspace::list_files error strippping relative path"
-                                    );
-                                    process::exit(1);
-                                }
-                            }
-                        }
-                    }
-                }
+        if cur_path.is_file() {
+            v.push(self.relative_path(&cur_path)?);
+            return Ok(v);
+        }
+
+        let mut paths = fs::read_dir(&cur_path)?
+            .map(|file| file.map(|file| file.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()?;
+        paths.sort();
+        for path in paths {
+            if path.ends_with(".git") {
+                continue;
+            }
+            let relative_path = self.relative_path(&path)?;
+            let key = utils::path_to_index_key(&relative_path);
+            let is_dir = path.is_dir();
+            if self.ignore.is_ignored(&key, is_dir) {
+                continue;
             }
-            Err(_) => {
-                eprintln!("error reading files in current directory");
-                process::exit(1);
+            if is_dir {
+                let mut files_from_dir = Self::list_files(self, &path)?;
+                v.append(&mut files_from_dir);
+            } else if path.is_file() {
+                v.push(relative_path);
             }
         }
         Ok(v)
     }
 
-    pub fn stat_file(&self, path: PathBuf) -> Metadata {
-        match fs::metadata(path) {
-            Ok(metadata) => metadata,
-            Err(_) => {
-                panic!("Could not stat_file in workspace");
+    pub fn stat_file(&self, path: &Path) -> io::Result<Metadata> {
+        fs::metadata(self.path.join(path))
+    }
+
+    /*
+        Replaces the file at path with data, executable if mode says so.
+        Its directory must already exist.
+    */
+    pub fn write_file(&self, path: &Path, data: &[u8], mode: u32) -> io::Result<()> {
+        let full_path = self.path.join(path);
+        fs::write(&full_path, data)?;
+        let permissions = if mode == EXECUTABLE_MODE {
+            0o755
+        } else {
+            0o644
+        };
+        fs::set_permissions(&full_path, Permissions::from_mode(permissions))
+    }
+
+    /*
+        Removes a file, doing nothing if it is already gone.
+    */
+    pub fn remove_file(&self, path: &Path) -> io::Result<()> {
+        match fs::remove_file(self.path.join(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /*
+        Creates the directory at path, replacing a file that is in the way.
+    */
+    pub fn make_directory(&self, path: &Path) -> io::Result<()> {
+        let full_path = self.path.join(path);
+        if full_path.is_file() {
+            fs::remove_file(&full_path)?;
+        }
+        match fs::create_dir(&full_path) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /*
+        Removes the directory at path if it is empty, leaving it otherwise.
+    */
+    pub fn remove_directory(&self, path: &Path) -> io::Result<()> {
+        match fs::remove_dir(self.path.join(path)) {
+            Err(err)
+                if !matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound
+                        | io::ErrorKind::DirectoryNotEmpty
+                        | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                Err(err)
             }
+            _ => Ok(()),
         }
     }
+
+    /*
+        Removes the directory at path with everything in it.
+    */
+    pub fn remove_tree(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(self.path.join(path))
+    }
+
+    fn relative_path(&self, absolute_path: &Path) -> io::Result<PathBuf> {
+        absolute_path
+            .strip_prefix(&self.path)
+            .map(Path::to_path_buf)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "'{}' is outside repository at '{}'",
+                        absolute_path.display(),
+                        self.path.display()
+                    ),
+                )
+            })
+    }
 }
@@ -7,4 +7,5 @@ mkdir foo/bar;
 touch foo/world.txt;
 touch foo/bar/bax.txt;
 touch foo/zzz.txt;
+cargo run --bin jit add .;
 cargo run --bin jit commit;